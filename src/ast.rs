@@ -1,5 +1,7 @@
-use crate::token::Token;
+use crate::token::{Span, Token};
+use serde_json::{Map, Value};
 use std::any::Any;
+use std::rc::Rc;
 
 /// AST node. contains two different types of nodes: expression and statement
 pub trait Node {
@@ -7,6 +9,9 @@ pub trait Node {
     /// this method will be used only for debugging and testing
     fn token_literal(&self) -> &str;
 
+    /// where the token the node is associated with appears in the source code
+    fn span(&self) -> Span;
+
     /// converting a trait into a concrete type
     /// refer to:
     /// * (downcast-trait-object)[https://bennetthardwick.com/rust/downcast-trait-object/]
@@ -15,20 +20,173 @@ pub trait Node {
 
     /// print AST nodes for debugging and to compare them with other AST nodes
     fn format(&self) -> String;
+
+    /// describe the node and its children, used by the `--ast` and `--ast-json` dump modes
+    fn dump(&self) -> Dump;
+}
+
+/// structural description of an AST node, which can be rendered as an indented tree or as JSON
+pub struct Dump {
+    /// name of the node type, such as `LetStatement`
+    pub kind: &'static str,
+    pub fields: Vec<(&'static str, DumpField)>,
+}
+
+pub enum DumpField {
+    /// a plain attribute of the node, such as an operator or a literal value
+    Value(Value),
+    /// an optional child node
+    Node(Option<Dump>),
+    /// a list of child nodes
+    List(Vec<Dump>),
+}
+
+impl Dump {
+    pub fn new(kind: &'static str) -> Self {
+        Self {
+            kind,
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn value(mut self, name: &'static str, value: impl Into<Value>) -> Self {
+        self.fields.push((name, DumpField::Value(value.into())));
+        self
+    }
+
+    pub fn node(mut self, name: &'static str, node: Option<Dump>) -> Self {
+        self.fields.push((name, DumpField::Node(node)));
+        self
+    }
+
+    pub fn list(mut self, name: &'static str, nodes: Vec<Dump>) -> Self {
+        self.fields.push((name, DumpField::List(nodes)));
+        self
+    }
+
+    /// render the node as an indented tree, one node per line.
+    /// plain attributes are printed next to the node type, child nodes are indented below it
+    pub fn to_tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(&mut out, "", 0);
+        out
+    }
+
+    fn write_tree(&self, out: &mut String, label: &str, depth: usize) {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(label);
+        out.push_str(self.kind);
+        for (name, field) in &self.fields {
+            if let DumpField::Value(value) = field {
+                out.push_str(&format!(" {}={}", name, value));
+            }
+        }
+        out.push('\n');
+
+        for (name, field) in &self.fields {
+            match field {
+                DumpField::Value(_) => {}
+                DumpField::Node(None) => {
+                    out.push_str(&"  ".repeat(depth + 1));
+                    out.push_str(&format!("{}: <none>\n", name));
+                }
+                DumpField::Node(Some(node)) => {
+                    node.write_tree(out, &format!("{}: ", name), depth + 1);
+                }
+                DumpField::List(nodes) => {
+                    for (i, node) in nodes.iter().enumerate() {
+                        node.write_tree(out, &format!("{}[{}]: ", name, i), depth + 1);
+                    }
+                }
+            }
+        }
+    }
+
+    /// render the node as a JSON object whose `type` key holds the node type
+    pub fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        object.insert("type".to_string(), Value::from(self.kind));
+        for (name, field) in &self.fields {
+            let value = match field {
+                DumpField::Value(value) => value.clone(),
+                DumpField::Node(node) => node.as_ref().map_or(Value::Null, Dump::to_json),
+                DumpField::List(nodes) => Value::Array(nodes.iter().map(Dump::to_json).collect()),
+            };
+            object.insert(name.to_string(), value);
+        }
+        Value::Object(object)
+    }
 }
 
 /// statement don't produce a value
 /// including `let`
-pub trait Statement: Node {
+pub trait Statement: Node + CloneStatement {
     fn statement_node(&self);
 }
 
 /// expression produces a value
 /// including `function literals`
-pub trait Expression: Node {
+pub trait Expression: Node + CloneExpression {
     fn expression_node(&self);
 }
 
+/// copy a boxed statement, every statement node implements it by deriving `Clone`
+pub trait CloneStatement {
+    fn clone_statement(&self) -> Box<dyn Statement>;
+}
+
+impl<T: Statement + Clone + 'static> CloneStatement for T {
+    fn clone_statement(&self) -> Box<dyn Statement> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Statement> {
+    fn clone(&self) -> Self {
+        self.clone_statement()
+    }
+}
+
+/// copy a boxed expression, every expression node implements it by deriving `Clone`
+pub trait CloneExpression {
+    fn clone_expression(&self) -> Box<dyn Expression>;
+}
+
+impl<T: Expression + Clone + 'static> CloneExpression for T {
+    fn clone_expression(&self) -> Box<dyn Expression> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Expression> {
+    fn clone(&self) -> Self {
+        self.clone_expression()
+    }
+}
+
+/// pattern is matched against a value, binding names to the parts of the value it matches
+/// including `_`, literals, names, `[first, ..rest]` and `{"key": pattern}`
+pub trait Pattern: Node + ClonePattern {
+    fn pattern_node(&self);
+}
+
+/// copy a boxed pattern, every pattern node implements it by deriving `Clone`
+pub trait ClonePattern {
+    fn clone_pattern(&self) -> Box<dyn Pattern>;
+}
+
+impl<T: Pattern + Clone + 'static> ClonePattern for T {
+    fn clone_pattern(&self) -> Box<dyn Pattern> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Pattern> {
+    fn clone(&self) -> Self {
+        self.clone_pattern()
+    }
+}
+
 /// the root node of every AST out parser produces
 /// every valid Lingo program is a series of statements
 pub struct Program {
@@ -36,17 +194,6 @@ pub struct Program {
 }
 
 impl Program {
-    fn token_literal(&self) -> &str {
-        if !self.statements.is_empty() {
-            match self.statements.get(0) {
-                None => "",
-                Some(statement) => statement.token_literal(),
-            }
-        } else {
-            ""
-        }
-    }
-
     pub fn format(&self) -> String {
         let mut out = String::new();
         for stat in &self.statements {
@@ -54,8 +201,16 @@ impl Program {
         }
         out
     }
+
+    pub fn dump(&self) -> Dump {
+        Dump::new("Program").list(
+            "statements",
+            self.statements.iter().map(|stat| stat.dump()).collect(),
+        )
+    }
 }
 
+#[derive(Clone)]
 pub struct Identifier {
     pub token: Token,
     pub value: String,
@@ -66,6 +221,10 @@ impl Node for Identifier {
         &self.token.literal
     }
 
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -73,6 +232,10 @@ impl Node for Identifier {
     fn format(&self) -> String {
         self.value.clone()
     }
+
+    fn dump(&self) -> Dump {
+        Dump::new("Identifier").value("value", self.value.clone())
+    }
 }
 
 /// the identifier in a let statement doesn't produce a value, but in order to keep things simple,
@@ -82,6 +245,12 @@ impl Expression for Identifier {
     fn expression_node(&self) {}
 }
 
+/// in a pattern, the identifier matches any value and binds it to the name
+impl Pattern for Identifier {
+    fn pattern_node(&self) {}
+}
+
+#[derive(Clone)]
 pub struct IntegerLiteral {
     pub token: Token,
     pub value: usize,
@@ -92,6 +261,10 @@ impl Node for IntegerLiteral {
         &self.token.literal
     }
 
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -99,6 +272,10 @@ impl Node for IntegerLiteral {
     fn format(&self) -> String {
         self.token.literal.clone()
     }
+
+    fn dump(&self) -> Dump {
+        Dump::new("IntegerLiteral").value("value", self.value)
+    }
 }
 
 impl Expression for IntegerLiteral {
@@ -106,9 +283,10 @@ impl Expression for IntegerLiteral {
 }
 
 /// struct of usage is the following:
-/// ```
+/// ```text
 /// <prefix operator><expression>;
 /// ```
+#[derive(Clone)]
 pub struct PrefixExpression {
     pub token: Token,
     /// contain either '-' or '!'
@@ -122,6 +300,10 @@ impl Node for PrefixExpression {
         &self.token.literal
     }
 
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -129,12 +311,19 @@ impl Node for PrefixExpression {
     fn format(&self) -> String {
         format!("({}{})", self.operator, self.right.format())
     }
+
+    fn dump(&self) -> Dump {
+        Dump::new("PrefixExpression")
+            .value("operator", self.operator.clone())
+            .node("right", Some(self.right.dump()))
+    }
 }
 
 impl Expression for PrefixExpression {
     fn expression_node(&self) {}
 }
 
+#[derive(Clone)]
 pub struct InfixExpression {
     pub token: Token,
     pub left: Box<dyn Expression>,
@@ -147,6 +336,10 @@ impl Node for InfixExpression {
         &self.token.literal
     }
 
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -159,12 +352,20 @@ impl Node for InfixExpression {
             self.right.format()
         )
     }
+
+    fn dump(&self) -> Dump {
+        Dump::new("InfixExpression")
+            .value("operator", self.operator.clone())
+            .node("left", Some(self.left.dump()))
+            .node("right", Some(self.right.dump()))
+    }
 }
 
 impl Expression for InfixExpression {
     fn expression_node(&self) {}
 }
 
+#[derive(Clone)]
 pub struct Boolean {
     pub token: Token,
     pub value: bool,
@@ -175,6 +376,10 @@ impl Node for Boolean {
         &self.token.literal
     }
 
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -182,139 +387,1310 @@ impl Node for Boolean {
     fn format(&self) -> String {
         self.token.literal.clone()
     }
+
+    fn dump(&self) -> Dump {
+        Dump::new("Boolean").value("value", self.value)
+    }
 }
 
 impl Expression for Boolean {
     fn expression_node(&self) {}
 }
 
+/// if-expression's form is as following:
+/// ```text
+/// if (<condition>) <consequence> else <alternative>
+/// ```
+/// the `else` branch is optional
+#[derive(Clone)]
 pub struct IfExpression {
     pub token: Token,
     pub condition: Box<dyn Expression>,
+    pub consequence: BlockStatement,
+    pub alternative: Option<BlockStatement>,
 }
 
-/// let-statement form is as following:
-/// ```
-/// let <identifier> = <expression>;
+impl Node for IfExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        let mut out = format!(
+            "if{} {}",
+            self.condition.format(),
+            self.consequence.format()
+        );
+        if let Some(alternative) = &self.alternative {
+            out.push_str(&format!("else {}", alternative.format()));
+        }
+        out
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("IfExpression")
+            .node("condition", Some(self.condition.dump()))
+            .node("consequence", Some(self.consequence.dump()))
+            .node(
+                "alternative",
+                self.alternative
+                    .as_ref()
+                    .map(|alternative| alternative.dump()),
+            )
+    }
+}
+
+impl Expression for IfExpression {
+    fn expression_node(&self) {}
+}
+
+/// a series of statements enclosed by `{` and `}`
+#[derive(Clone)]
+pub struct BlockStatement {
+    pub token: Token,
+    pub statements: Vec<Box<dyn Statement>>,
+}
+
+impl Node for BlockStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        let mut out = String::new();
+        for stat in &self.statements {
+            out.push_str(&stat.format());
+        }
+        out
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("BlockStatement").list(
+            "statements",
+            self.statements.iter().map(|stat| stat.dump()).collect(),
+        )
+    }
+}
+
+impl Statement for BlockStatement {
+    fn statement_node(&self) {}
+}
+
+/// function literal's form is as following:
+/// ```text
+/// fn <parameters> <block statement>
+/// fn(<parameter>, <parameter> = <expression>, ...<identifier>) <block statement>
 /// ```
-pub struct LetStatement {
+/// the parameters and the body are shared with the function objects created from the literal,
+/// which outlive the program they were parsed from
+#[derive(Clone)]
+pub struct FunctionLiteral {
     pub token: Token,
-    /// hold the identifier of the binding
-    pub name: Identifier,
-    /// the expression that produces the value
-    pub value: Option<Box<dyn Expression>>, // TODO
+    pub parameters: Rc<Vec<Parameter>>,
+    /// collects the arguments left after the parameters into an array
+    pub rest: Option<Identifier>,
+    pub body: Rc<BlockStatement>,
 }
 
-impl Node for LetStatement {
+/// a parameter is an identifier, or an array or hash pattern destructuring the argument.
+/// the default value is evaluated when a call leaves the argument out,
+/// after the parameters before it are bound
+#[derive(Clone)]
+pub struct Parameter {
+    pub pattern: Box<dyn Pattern>,
+    pub default: Option<Box<dyn Expression>>,
+}
+
+impl Parameter {
+    pub fn format(&self) -> String {
+        match &self.default {
+            Some(default) => format!("{} = {}", self.pattern.format(), default.format()),
+            None => self.pattern.format(),
+        }
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("Parameter")
+            .node("pattern", Some(self.pattern.dump()))
+            .node(
+                "default",
+                self.default.as_ref().map(|default| default.dump()),
+            )
+    }
+}
+
+impl Node for FunctionLiteral {
     fn token_literal(&self) -> &str {
         &self.token.literal
     }
 
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
 
     fn format(&self) -> String {
-        let value_format: String = match &self.value {
-            None => String::new(),
-            Some(expression) => expression.format(),
-        };
         format!(
-            "{} {} = {};",
+            "{}{} {}",
             self.token_literal(),
-            self.name.format(),
-            value_format
+            self.format_parameters(),
+            self.body.format()
         )
     }
+
+    fn dump(&self) -> Dump {
+        Dump::new("FunctionLiteral")
+            .list(
+                "parameters",
+                self.parameters.iter().map(|p| p.dump()).collect(),
+            )
+            .node("rest", self.rest.as_ref().map(|rest| rest.dump()))
+            .node("body", Some(self.body.dump()))
+    }
 }
 
-impl Statement for LetStatement {
-    fn statement_node(&self) {}
+impl Expression for FunctionLiteral {
+    fn expression_node(&self) {}
 }
 
-/// return-statement's form is as following:
+impl FunctionLiteral {
+    /// the parameters between parentheses, `(x, y = 1, ...rest)`
+    pub fn format_parameters(&self) -> String {
+        let mut params: Vec<String> = self.parameters.iter().map(|p| p.format()).collect();
+        if let Some(rest) = &self.rest {
+            params.push(format!("...{}", rest.format()));
+        }
+        format!("({})", params.join(", "))
+    }
+}
+
+/// macro literal's form is as following:
+/// ```text
+/// macro <parameters> <block statement>
 /// ```
-/// return <expression>;
+/// the macro is called with its arguments quoted and must return the code to replace the call with
+#[derive(Clone)]
+pub struct MacroLiteral {
+    pub token: Token,
+    pub parameters: Rc<Vec<Identifier>>,
+    pub body: Rc<BlockStatement>,
+}
+
+impl Node for MacroLiteral {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        let params: Vec<String> = self.parameters.iter().map(|p| p.format()).collect();
+        format!(
+            "{}({}) {}",
+            self.token_literal(),
+            params.join(", "),
+            self.body.format()
+        )
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("MacroLiteral")
+            .list(
+                "parameters",
+                self.parameters.iter().map(|p| p.dump()).collect(),
+            )
+            .node("body", Some(self.body.dump()))
+    }
+}
+
+impl Expression for MacroLiteral {
+    fn expression_node(&self) {}
+}
+
+/// call expression's form is as following:
+/// ```text
+/// <expression>(<comma separated expressions>)
+/// <expression>(<expression>, <identifier>: <expression>)
 /// ```
-pub struct ReturnStatement {
-    /// initial token
+#[derive(Clone)]
+pub struct CallExpression {
+    /// the `(` token
     pub token: Token,
-    /// contain the expression that is to be returned
-    pub return_value: Option<Box<dyn Expression>>, // TODO
+    /// identifier or function literal
+    pub function: Box<dyn Expression>,
+    pub arguments: Vec<Box<dyn Expression>>,
+    /// the arguments written `<name>: <expression>`, after the others
+    pub named_arguments: Vec<(Identifier, Box<dyn Expression>)>,
 }
 
-impl Node for ReturnStatement {
+impl Node for CallExpression {
     fn token_literal(&self) -> &str {
         &self.token.literal
     }
 
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
 
     fn format(&self) -> String {
-        let value_format: String = match &self.return_value {
-            None => String::new(),
-            Some(expression) => expression.format(),
-        };
-        format!("{} {};", self.token_literal(), value_format)
+        let mut args: Vec<String> = self.arguments.iter().map(|a| a.format()).collect();
+        args.extend(
+            self.named_arguments
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name.format(), value.format())),
+        );
+        format!("{}({})", self.function.format(), args.join(", "))
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("CallExpression")
+            .node("function", Some(self.function.dump()))
+            .list(
+                "arguments",
+                self.arguments.iter().map(|a| a.dump()).collect(),
+            )
+            .list(
+                "named_arguments",
+                self.named_arguments
+                    .iter()
+                    .map(|(name, value)| {
+                        Dump::new("NamedArgument")
+                            .node("name", Some(name.dump()))
+                            .node("value", Some(value.dump()))
+                    })
+                    .collect(),
+            )
     }
 }
 
-impl Statement for ReturnStatement {
-    fn statement_node(&self) {}
+impl Expression for CallExpression {
+    fn expression_node(&self) {}
 }
 
-pub struct ExpressionStatement {
+#[derive(Clone)]
+pub struct StringLiteral {
     pub token: Token,
-    pub expression: Option<Box<dyn Expression>>, // TODO
+    pub value: String,
 }
 
-impl Node for ExpressionStatement {
+impl Node for StringLiteral {
     fn token_literal(&self) -> &str {
         &self.token.literal
     }
 
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
 
     fn format(&self) -> String {
-        match &self.expression {
-            None => String::new(),
-            Some(expression) => expression.format(),
-        }
+        self.token.literal.clone()
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("StringLiteral").value("value", self.value.clone())
     }
 }
 
-impl Statement for ExpressionStatement {
-    fn statement_node(&self) {}
+impl Expression for StringLiteral {
+    fn expression_node(&self) {}
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::ast::{Identifier, LetStatement, Program};
-    use crate::token::{Token, IDENT, LET};
+/// array literal's form is as following:
+/// ```text
+/// [<comma separated expressions>]
+/// ```
+#[derive(Clone)]
+pub struct ArrayLiteral {
+    /// the `[` token
+    pub token: Token,
+    pub elements: Vec<Box<dyn Expression>>,
+}
 
-    #[test]
-    fn test_node_format() {
-        let program = Program {
-            statements: vec![Box::new(LetStatement {
-                token: Token {
-                    token_type: LET,
-                    literal: "let".to_string(),
-                },
-                name: Identifier {
-                    token: Token {
-                        token_type: IDENT,
-                        literal: "myVar".to_string(),
-                    },
-                    value: "myVar".to_string(),
-                },
+impl Node for ArrayLiteral {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        let elements: Vec<String> = self.elements.iter().map(|e| e.format()).collect();
+        format!("[{}]", elements.join(", "))
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("ArrayLiteral").list("elements", self.elements.iter().map(|e| e.dump()).collect())
+    }
+}
+
+impl Expression for ArrayLiteral {
+    fn expression_node(&self) {}
+}
+
+/// index expression's form is as following:
+/// ```text
+/// <expression>[<expression>]
+/// ```
+#[derive(Clone)]
+pub struct IndexExpression {
+    /// the `[` token
+    pub token: Token,
+    pub left: Box<dyn Expression>,
+    pub index: Box<dyn Expression>,
+}
+
+impl Node for IndexExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        format!("({}[{}])", self.left.format(), self.index.format())
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("IndexExpression")
+            .node("left", Some(self.left.dump()))
+            .node("index", Some(self.index.dump()))
+    }
+}
+
+impl Expression for IndexExpression {
+    fn expression_node(&self) {}
+}
+
+/// hash literal's form is as following:
+/// ```text
+/// {<expression> : <expression>, <expression> : <expression>, ... }
+/// ```
+/// the pairs keep the order in which they appear in the source code
+#[derive(Clone)]
+pub struct HashLiteral {
+    /// the `{` token
+    pub token: Token,
+    pub pairs: Vec<(Box<dyn Expression>, Box<dyn Expression>)>,
+}
+
+impl Node for HashLiteral {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        let pairs: Vec<String> = self
+            .pairs
+            .iter()
+            .map(|(key, value)| format!("{}:{}", key.format(), value.format()))
+            .collect();
+        format!("{{{}}}", pairs.join(", "))
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("HashLiteral").list(
+            "pairs",
+            self.pairs
+                .iter()
+                .map(|(key, value)| {
+                    Dump::new("HashPair")
+                        .node("key", Some(key.dump()))
+                        .node("value", Some(value.dump()))
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Expression for HashLiteral {
+    fn expression_node(&self) {}
+}
+
+/// let-statement form is as following:
+/// ```text
+/// let <identifier> = <expression>;
+/// const <identifier> = <expression>;
+/// let [<patterns>, ..<identifier>] = <expression>;
+/// let {<key>: <pattern>, <identifier>} = <expression>;
+/// ```
+#[derive(Clone)]
+pub struct LetStatement {
+    pub token: Token,
+    /// hold the identifier of the binding, or the array or hash pattern destructuring the value
+    pub pattern: Box<dyn Pattern>,
+    /// declared with `const`, the binding can't be changed afterwards
+    pub constant: bool,
+    /// the expression that produces the value
+    pub value: Option<Box<dyn Expression>>, // TODO
+}
+
+impl Node for LetStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        let value_format: String = match &self.value {
+            None => String::new(),
+            Some(expression) => expression.format(),
+        };
+        format!(
+            "{} {} = {};",
+            self.token_literal(),
+            self.pattern.format(),
+            value_format
+        )
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("LetStatement")
+            .value("constant", self.constant)
+            .node("pattern", Some(self.pattern.dump()))
+            .node("value", self.value.as_ref().map(|value| value.dump()))
+    }
+}
+
+impl Statement for LetStatement {
+    fn statement_node(&self) {}
+}
+
+impl LetStatement {
+    /// the identifier of the binding, `None` when the value is destructured
+    pub fn name(&self) -> Option<&Identifier> {
+        self.pattern.as_any().downcast_ref::<Identifier>()
+    }
+}
+
+/// return-statement's form is as following:
+/// ```text
+/// return <expression>;
+/// ```
+#[derive(Clone)]
+pub struct ReturnStatement {
+    /// initial token
+    pub token: Token,
+    /// contain the expression that is to be returned
+    pub return_value: Option<Box<dyn Expression>>, // TODO
+}
+
+impl Node for ReturnStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        let value_format: String = match &self.return_value {
+            None => String::new(),
+            Some(expression) => expression.format(),
+        };
+        format!("{} {};", self.token_literal(), value_format)
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("ReturnStatement").node(
+            "return_value",
+            self.return_value.as_ref().map(|value| value.dump()),
+        )
+    }
+}
+
+impl Statement for ReturnStatement {
+    fn statement_node(&self) {}
+}
+
+#[derive(Clone)]
+pub struct ExpressionStatement {
+    pub token: Token,
+    pub expression: Option<Box<dyn Expression>>, // TODO
+}
+
+impl Node for ExpressionStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        match &self.expression {
+            None => String::new(),
+            Some(expression) => expression.format(),
+        }
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("ExpressionStatement").node(
+            "expression",
+            self.expression.as_ref().map(|expression| expression.dump()),
+        )
+    }
+}
+
+impl Statement for ExpressionStatement {
+    fn statement_node(&self) {}
+}
+
+/// assign-statement's form is as following:
+/// ```text
+/// <target> <operator> <expression>;
+/// ```
+/// the target is an identifier or an index expression on one, such as `a[0][1]`.
+/// the operator is `=` or one of the compound assignments `+=`, `-=`, `*=` and `/=`
+#[derive(Clone)]
+pub struct AssignStatement {
+    /// the operator token
+    pub token: Token,
+    pub target: Box<dyn Expression>,
+    pub operator: String,
+    pub value: Box<dyn Expression>,
+}
+
+impl Node for AssignStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        format!(
+            "{} {} {};",
+            self.target.format(),
+            self.operator,
+            self.value.format()
+        )
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("AssignStatement")
+            .value("operator", self.operator.clone())
+            .node("target", Some(self.target.dump()))
+            .node("value", Some(self.value.dump()))
+    }
+}
+
+impl Statement for AssignStatement {
+    fn statement_node(&self) {}
+}
+
+/// while-statement's form is as following:
+/// ```text
+/// while (<condition>) <block statement>
+/// ```
+#[derive(Clone)]
+pub struct WhileStatement {
+    pub token: Token,
+    pub condition: Box<dyn Expression>,
+    pub body: BlockStatement,
+}
+
+impl Node for WhileStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        format!("while{} {}", self.condition.format(), self.body.format())
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("WhileStatement")
+            .node("condition", Some(self.condition.dump()))
+            .node("body", Some(self.body.dump()))
+    }
+}
+
+impl Statement for WhileStatement {
+    fn statement_node(&self) {}
+}
+
+/// for-statement's form is as following:
+/// ```text
+/// for (<identifier> in <expression>) <block statement>
+/// ```
+/// the expression can be an array, a string, whose characters are iterated over,
+/// or a hash, whose keys are iterated over
+#[derive(Clone)]
+pub struct ForStatement {
+    pub token: Token,
+    /// bound to each element in turn
+    pub variable: Identifier,
+    pub iterable: Box<dyn Expression>,
+    pub body: BlockStatement,
+}
+
+impl Node for ForStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        format!(
+            "for({} in {}) {}",
+            self.variable.format(),
+            self.iterable.format(),
+            self.body.format()
+        )
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("ForStatement")
+            .node("variable", Some(self.variable.dump()))
+            .node("iterable", Some(self.iterable.dump()))
+            .node("body", Some(self.body.dump()))
+    }
+}
+
+impl Statement for ForStatement {
+    fn statement_node(&self) {}
+}
+
+/// `break;` leaves the innermost loop
+#[derive(Clone)]
+pub struct BreakStatement {
+    pub token: Token,
+}
+
+impl Node for BreakStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        format!("{};", self.token_literal())
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("BreakStatement")
+    }
+}
+
+impl Statement for BreakStatement {
+    fn statement_node(&self) {}
+}
+
+/// `continue;` goes on with the next iteration of the innermost loop
+#[derive(Clone)]
+pub struct ContinueStatement {
+    pub token: Token,
+}
+
+impl Node for ContinueStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        format!("{};", self.token_literal())
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("ContinueStatement")
+    }
+}
+
+impl Statement for ContinueStatement {
+    fn statement_node(&self) {}
+}
+
+/// match expression's form is as following:
+/// ```text
+/// match <expression> { <pattern> [if <guard>] => <expression or block>, ... }
+/// ```
+/// the arms are tried in order, the first one whose pattern matches and whose guard is truthy
+/// gives the value of the expression
+#[derive(Clone)]
+pub struct MatchExpression {
+    pub token: Token,
+    pub subject: Box<dyn Expression>,
+    pub arms: Vec<MatchArm>,
+}
+
+#[derive(Clone)]
+pub struct MatchArm {
+    pub pattern: Box<dyn Pattern>,
+    pub guard: Option<Box<dyn Expression>>,
+    /// an arm written as a single expression gets a block holding that expression
+    pub body: BlockStatement,
+}
+
+impl MatchArm {
+    fn format(&self) -> String {
+        match &self.guard {
+            Some(guard) => format!(
+                "{} if {} => {}",
+                self.pattern.format(),
+                guard.format(),
+                self.body.format()
+            ),
+            None => format!("{} => {}", self.pattern.format(), self.body.format()),
+        }
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("MatchArm")
+            .node("pattern", Some(self.pattern.dump()))
+            .node("guard", self.guard.as_ref().map(|guard| guard.dump()))
+            .node("body", Some(self.body.dump()))
+    }
+}
+
+impl Node for MatchExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        let arms: Vec<String> = self.arms.iter().map(|arm| arm.format()).collect();
+        format!("match {} {{ {} }}", self.subject.format(), arms.join(", "))
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("MatchExpression")
+            .node("subject", Some(self.subject.dump()))
+            .list("arms", self.arms.iter().map(|arm| arm.dump()).collect())
+    }
+}
+
+impl Expression for MatchExpression {
+    fn expression_node(&self) {}
+}
+
+/// `_` matches any value without binding it
+#[derive(Clone)]
+pub struct WildcardPattern {
+    pub token: Token,
+}
+
+impl Node for WildcardPattern {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        "_".to_string()
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("WildcardPattern")
+    }
+}
+
+impl Pattern for WildcardPattern {
+    fn pattern_node(&self) {}
+}
+
+/// an integer, string or boolean literal, matching the values equal to it
+#[derive(Clone)]
+pub struct LiteralPattern {
+    pub token: Token,
+    pub value: Box<dyn Expression>,
+}
+
+impl Node for LiteralPattern {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        self.value.format()
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("LiteralPattern").node("value", Some(self.value.dump()))
+    }
+}
+
+impl Pattern for LiteralPattern {
+    fn pattern_node(&self) {}
+}
+
+/// array pattern's form is as following:
+/// ```text
+/// [<comma separated patterns>, ..<identifier>]
+/// ```
+/// without the `..rest` part, only arrays of as many elements as there are patterns match
+#[derive(Clone)]
+pub struct ArrayPattern {
+    /// the `[` token
+    pub token: Token,
+    pub elements: Vec<Box<dyn Pattern>>,
+    /// bound to the elements after the ones matched by `elements`
+    pub rest: Option<Identifier>,
+}
+
+impl Node for ArrayPattern {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        let mut elements: Vec<String> = self.elements.iter().map(|e| e.format()).collect();
+        if let Some(rest) = &self.rest {
+            elements.push(format!("..{}", rest.format()));
+        }
+        format!("[{}]", elements.join(", "))
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("ArrayPattern")
+            .list("elements", self.elements.iter().map(|e| e.dump()).collect())
+            .node("rest", self.rest.as_ref().map(|rest| rest.dump()))
+    }
+}
+
+impl Pattern for ArrayPattern {
+    fn pattern_node(&self) {}
+}
+
+/// hash pattern's form is as following:
+/// ```text
+/// {<key literal>: <pattern>, <identifier>, ...}
+/// ```
+/// where `<identifier>` is short for `"<identifier>": <identifier>`.
+/// a hash matches if it has every key and the values match, whatever its other keys
+#[derive(Clone)]
+pub struct HashPattern {
+    /// the `{` token
+    pub token: Token,
+    pub pairs: Vec<(Box<dyn Expression>, Box<dyn Pattern>)>,
+}
+
+impl Node for HashPattern {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        let pairs: Vec<String> = self
+            .pairs
+            .iter()
+            .map(|(key, pattern)| {
+                // `{name}` is short for `{"name": name}`
+                if pattern.as_any().is::<Identifier>() && key.format() == pattern.format() {
+                    pattern.format()
+                } else {
+                    format!("{}:{}", key.format(), pattern.format())
+                }
+            })
+            .collect();
+        format!("{{{}}}", pairs.join(", "))
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("HashPattern").list(
+            "pairs",
+            self.pairs
+                .iter()
+                .map(|(key, pattern)| {
+                    Dump::new("HashPatternPair")
+                        .node("key", Some(key.dump()))
+                        .node("pattern", Some(pattern.dump()))
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Pattern for HashPattern {
+    fn pattern_node(&self) {}
+}
+
+/// struct-statement's form is as following:
+/// ```text
+/// struct <identifier> { <identifier>, <identifier>, ... }
+/// ```
+/// it binds the name to the struct, whose instances have a value for each of the fields
+#[derive(Clone)]
+pub struct StructStatement {
+    pub token: Token,
+    pub name: Identifier,
+    pub fields: Vec<Identifier>,
+}
+
+impl Node for StructStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        let fields: Vec<String> = self.fields.iter().map(|field| field.format()).collect();
+        format!(
+            "{} {} {{ {} }}",
+            self.token_literal(),
+            self.name.format(),
+            fields.join(", ")
+        )
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("StructStatement")
+            .node("name", Some(self.name.dump()))
+            .list(
+                "fields",
+                self.fields.iter().map(|field| field.dump()).collect(),
+            )
+    }
+}
+
+impl Statement for StructStatement {
+    fn statement_node(&self) {}
+}
+
+/// struct literal's form is as following:
+/// ```text
+/// <identifier> { <identifier>: <expression>, <identifier>: <expression>, ... }
+/// ```
+/// every field of the struct must be given a value, in any order
+#[derive(Clone)]
+pub struct StructLiteral {
+    /// the `{` token
+    pub token: Token,
+    pub name: Identifier,
+    pub fields: Vec<(Identifier, Box<dyn Expression>)>,
+}
+
+impl Node for StructLiteral {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        let fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name.format(), value.format()))
+            .collect();
+        format!("{} {{{}}}", self.name.format(), fields.join(", "))
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("StructLiteral")
+            .node("name", Some(self.name.dump()))
+            .list(
+                "fields",
+                self.fields
+                    .iter()
+                    .map(|(name, value)| {
+                        Dump::new("FieldValue")
+                            .node("name", Some(name.dump()))
+                            .node("value", Some(value.dump()))
+                    })
+                    .collect(),
+            )
+    }
+}
+
+impl Expression for StructLiteral {
+    fn expression_node(&self) {}
+}
+
+/// member expression's form is as following:
+/// ```text
+/// <expression>.<identifier>
+/// ```
+/// the member is a field of the instance, or else one of the methods of its struct
+#[derive(Clone)]
+pub struct MemberExpression {
+    /// the `.` token
+    pub token: Token,
+    pub object: Box<dyn Expression>,
+    pub member: Identifier,
+}
+
+impl Node for MemberExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        format!("({}.{})", self.object.format(), self.member.format())
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("MemberExpression")
+            .node("object", Some(self.object.dump()))
+            .node("member", Some(self.member.dump()))
+    }
+}
+
+impl Expression for MemberExpression {
+    fn expression_node(&self) {}
+}
+
+/// impl-statement's form is as following:
+/// ```text
+/// impl <identifier> { fn <identifier>(<parameters>) <block statement> ... }
+/// ```
+/// it adds the methods to the struct, a method is called with the instance as its first argument
+#[derive(Clone)]
+pub struct ImplStatement {
+    pub token: Token,
+    pub name: Identifier,
+    pub methods: Vec<(Identifier, FunctionLiteral)>,
+}
+
+impl Node for ImplStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn format(&self) -> String {
+        let methods: Vec<String> = self
+            .methods
+            .iter()
+            .map(|(name, function)| {
+                format!(
+                    "{} {}{} {}",
+                    function.token_literal(),
+                    name.format(),
+                    function.format_parameters(),
+                    function.body.format()
+                )
+            })
+            .collect();
+        format!(
+            "{} {} {{ {} }}",
+            self.token_literal(),
+            self.name.format(),
+            methods.join(" ")
+        )
+    }
+
+    fn dump(&self) -> Dump {
+        Dump::new("ImplStatement")
+            .node("name", Some(self.name.dump()))
+            .list(
+                "methods",
+                self.methods
+                    .iter()
+                    .map(|(name, function)| {
+                        Dump::new("Method")
+                            .node("name", Some(name.dump()))
+                            .node("function", Some(function.dump()))
+                    })
+                    .collect(),
+            )
+    }
+}
+
+impl Statement for ImplStatement {
+    fn statement_node(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Identifier, LetStatement, Program};
+    use crate::token::{Span, Token, IDENT, LET};
+
+    #[test]
+    fn test_node_format() {
+        let program = Program {
+            statements: vec![Box::new(LetStatement {
+                token: Token {
+                    token_type: LET,
+                    literal: "let".to_string(),
+                    span: Span::default(),
+                },
+                pattern: Box::new(Identifier {
+                    token: Token {
+                        token_type: IDENT,
+                        literal: "myVar".to_string(),
+                        span: Span::default(),
+                    },
+                    value: "myVar".to_string(),
+                }),
+                constant: false,
                 value: Some(Box::new(Identifier {
                     token: Token {
                         token_type: IDENT,
                         literal: "anotherVar".to_string(),
+                        span: Span::default(),
                     },
                     value: "anotherVar".to_string(),
                 })),
@@ -0,0 +1,175 @@
+use crate::evaluator::new_array;
+use crate::gc;
+use crate::object::{Builtin, Object};
+
+/// the functions built into Lingo, available in every environment unless shadowed by a binding
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "len",
+        func: len,
+    },
+    Builtin {
+        name: "puts",
+        func: puts,
+    },
+    Builtin {
+        name: "first",
+        func: first,
+    },
+    Builtin {
+        name: "last",
+        func: last,
+    },
+    Builtin {
+        name: "rest",
+        func: rest,
+    },
+    Builtin {
+        name: "push",
+        func: push,
+    },
+    Builtin {
+        name: "gc",
+        func: collect_garbage,
+    },
+    Builtin {
+        name: "range",
+        func: range,
+    },
+];
+
+pub fn lookup_builtin(name: &str) -> Option<Builtin> {
+    BUILTINS
+        .iter()
+        .find(|builtin| builtin.name == name)
+        .copied()
+}
+
+fn wrong_arguments(args: &[Object], want: usize) -> Option<Object> {
+    if args.len() != want {
+        Some(Object::Error(format!(
+            "wrong number of arguments. got={}, want={}",
+            args.len(),
+            want
+        )))
+    } else {
+        None
+    }
+}
+
+fn len(args: Vec<Object>) -> Object {
+    if let Some(err) = wrong_arguments(&args, 1) {
+        return err;
+    }
+    match &args[0] {
+        Object::String(value) => Object::Integer(value.len() as i64),
+        Object::Array(elements) => Object::Integer(elements.len() as i64),
+        Object::Range(start, end) => match end.checked_sub(*start) {
+            Some(length) => Object::Integer(length),
+            None => Object::Error("integer overflow".to_string()),
+        },
+        other => Object::Error(format!(
+            "argument to `len` not supported, got {}",
+            other.object_type()
+        )),
+    }
+}
+
+fn puts(args: Vec<Object>) -> Object {
+    for arg in args {
+        println!("{}", arg.inspect());
+    }
+    Object::Null
+}
+
+fn first(args: Vec<Object>) -> Object {
+    if let Some(err) = wrong_arguments(&args, 1) {
+        return err;
+    }
+    match &args[0] {
+        Object::Array(elements) => elements.first().cloned().unwrap_or(Object::Null),
+        Object::Range(start, end) if start < end => Object::Integer(*start),
+        Object::Range(..) => Object::Null,
+        other => Object::Error(format!(
+            "argument to `first` must be ARRAY, got {}",
+            other.object_type()
+        )),
+    }
+}
+
+fn last(args: Vec<Object>) -> Object {
+    if let Some(err) = wrong_arguments(&args, 1) {
+        return err;
+    }
+    match &args[0] {
+        Object::Array(elements) => elements.last().cloned().unwrap_or(Object::Null),
+        Object::Range(start, end) if start < end => Object::Integer(end - 1),
+        Object::Range(..) => Object::Null,
+        other => Object::Error(format!(
+            "argument to `last` must be ARRAY, got {}",
+            other.object_type()
+        )),
+    }
+}
+
+fn rest(args: Vec<Object>) -> Object {
+    if let Some(err) = wrong_arguments(&args, 1) {
+        return err;
+    }
+    match &args[0] {
+        Object::Array(elements) if elements.is_empty() => Object::Null,
+        Object::Array(elements) => new_array(elements[1..].to_vec()),
+        other => Object::Error(format!(
+            "argument to `rest` must be ARRAY, got {}",
+            other.object_type()
+        )),
+    }
+}
+
+fn push(args: Vec<Object>) -> Object {
+    if let Some(err) = wrong_arguments(&args, 2) {
+        return err;
+    }
+    match &args[0] {
+        Object::Array(elements) => {
+            let mut elements = elements.as_ref().clone();
+            elements.push(args[1].clone());
+            new_array(elements)
+        }
+        other => Object::Error(format!(
+            "argument to `push` must be ARRAY, got {}",
+            other.object_type()
+        )),
+    }
+}
+
+/// run the garbage collector, returning the number of bytes it freed
+fn collect_garbage(args: Vec<Object>) -> Object {
+    if let Some(err) = wrong_arguments(&args, 0) {
+        return err;
+    }
+    Object::Integer(gc::collect(&[]) as i64)
+}
+
+/// the integers from the start, 0 unless given, up to but not including the end
+fn range(args: Vec<Object>) -> Object {
+    let (start, end) = match args.as_slice() {
+        [Object::Integer(end)] => (0, *end),
+        [Object::Integer(start), Object::Integer(end)] => (*start, *end),
+        [_] | [_, _] => {
+            let types: Vec<&str> = args.iter().map(|arg| arg.object_type()).collect();
+            return Object::Error(format!(
+                "arguments to `range` must be INTEGER, got {}",
+                types.join(", ")
+            ));
+        }
+        _ => {
+            return Object::Error(format!(
+                "wrong number of arguments. got={}, want=1 or 2",
+                args.len()
+            ))
+        }
+    };
+    // the integers are not stored, a range takes no memory however long it is
+    Object::Range(start, end.max(start))
+}
@@ -0,0 +1,454 @@
+use crate::builtins::BUILTINS;
+use crate::code::*;
+use crate::compiler::Bytecode;
+use crate::object::{CompiledFunction, Object, Signature};
+use std::rc::Rc;
+
+/// the first bytes of every `.lingoc` file
+pub const MAGIC: &[u8; 4] = b"LGOC";
+/// bumped whenever the layout of the file or the instruction set changes
+pub const FORMAT_VERSION: u16 = 3;
+pub const EXTENSION: &str = "lingoc";
+
+const FLAG_DEBUG_INFO: u8 = 1;
+
+const TAG_INTEGER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_FUNCTION: u8 = 2;
+
+/// serialize the bytecode into the `.lingoc` format. all numbers are big-endian:
+///
+/// ```text
+/// magic       4 bytes, "LGOC"
+/// version     u16
+/// flags       u8, bit 0 set if the functions carry line tables
+/// globals     u32 count, then the name of every global binding
+/// constants   u32 count, then every constant as a u8 tag followed by
+///             an i64 (integer), a string, or a function
+/// main        the function holding the top level of the program
+/// ```
+///
+/// strings are a u32 length followed by UTF-8 bytes. a function is its name
+/// (u8 presence flag, then the string), the u32 number of parameters, the u32 number of those
+/// without a default value, a u8 set to 1 if it has a rest parameter, the u32 count and names of
+/// its locals, the u32 length and bytes of its instructions and, with debug info,
+/// the u32 count and `(offset, line, column)` u32 triples of its line table
+/// followed by the u32 line and column the function is defined at
+pub fn encode(bytecode: &Bytecode, debug_info: bool) -> Vec<u8> {
+    let mut encoder = Encoder {
+        out: Vec::new(),
+        debug_info,
+    };
+    encoder.out.extend_from_slice(MAGIC);
+    encoder.out.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+    encoder
+        .out
+        .push(if debug_info { FLAG_DEBUG_INFO } else { 0 });
+
+    encoder.u32(bytecode.globals.len());
+    for name in &bytecode.globals {
+        encoder.string(name);
+    }
+
+    encoder.u32(bytecode.constants.len());
+    for constant in &bytecode.constants {
+        match constant {
+            Object::Integer(value) => {
+                encoder.out.push(TAG_INTEGER);
+                encoder.out.extend_from_slice(&value.to_be_bytes());
+            }
+            Object::String(value) => {
+                encoder.out.push(TAG_STRING);
+                encoder.string(value);
+            }
+            Object::CompiledFunction(function) => {
+                encoder.out.push(TAG_FUNCTION);
+                encoder.function(function);
+            }
+            // the compiler puts nothing else in the constant pool
+            other => unreachable!("{} constant", other.object_type()),
+        }
+    }
+
+    encoder.function(&bytecode.main);
+    encoder.out
+}
+
+/// load bytecode serialized by `encode`. anything that is not a well-formed file
+/// of the current format version is rejected, as well as instructions referring
+/// to constants, bindings or offsets that don't exist, so that the VM can run the result safely
+pub fn decode(bytes: &[u8]) -> Result<Bytecode, String> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err("not a .lingoc file".to_string());
+    }
+    let mut decoder = Decoder {
+        bytes,
+        position: MAGIC.len(),
+        debug_info: false,
+    };
+
+    let version = decoder.u16()?;
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "unsupported format version {}, this build reads version {}",
+            version, FORMAT_VERSION
+        ));
+    }
+    let flags = decoder.u8()?;
+    if flags & !FLAG_DEBUG_INFO != 0 {
+        return Err(format!("unknown flags {:#04x}", flags));
+    }
+    decoder.debug_info = flags & FLAG_DEBUG_INFO != 0;
+
+    let mut globals = Vec::new();
+    for _ in 0..decoder.u32()? {
+        globals.push(decoder.string()?);
+    }
+
+    let mut constants = Vec::new();
+    for index in 0..decoder.u32()? {
+        let constant = match decoder.u8()? {
+            TAG_INTEGER => Object::Integer(i64::from_be_bytes(decoder.array()?)),
+            TAG_STRING => Object::String(decoder.string()?.into()),
+            TAG_FUNCTION => Object::CompiledFunction(Rc::new(decoder.function()?)),
+            tag => return Err(format!("unknown tag {} of constant {}", tag, index)),
+        };
+        constants.push(constant);
+    }
+
+    let main = decoder.function()?;
+    if decoder.position != bytes.len() {
+        return Err(format!(
+            "{} unexpected bytes at the end of the file",
+            bytes.len() - decoder.position
+        ));
+    }
+
+    let bytecode = Bytecode {
+        main: Rc::new(main),
+        constants,
+        globals,
+    };
+    verify_function(&bytecode, &bytecode.main, "the top level")?;
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        if let Object::CompiledFunction(function) = constant {
+            verify_function(&bytecode, function, &format!("function constant {}", index))?;
+        }
+    }
+    Ok(bytecode)
+}
+
+struct Encoder {
+    out: Vec<u8>,
+    debug_info: bool,
+}
+
+impl Encoder {
+    fn u32(&mut self, value: usize) {
+        self.out.extend_from_slice(&(value as u32).to_be_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len());
+        self.out.extend_from_slice(value.as_bytes());
+    }
+
+    fn function(&mut self, function: &CompiledFunction) {
+        match &function.name {
+            Some(name) => {
+                self.out.push(1);
+                self.string(name);
+            }
+            None => self.out.push(0),
+        }
+        self.u32(function.signature.names.len());
+        self.u32(function.signature.required);
+        self.out.push(function.signature.rest as u8);
+        self.u32(function.locals.len());
+        for name in &function.locals {
+            self.string(name);
+        }
+        self.u32(function.instructions.len());
+        self.out.extend_from_slice(&function.instructions);
+        if self.debug_info {
+            self.u32(function.lines.len());
+            for (offset, line, column) in &function.lines {
+                self.u32(*offset);
+                self.u32(*line);
+                self.u32(*column);
+            }
+            self.u32(function.signature.line);
+            self.u32(function.signature.column);
+        }
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    debug_info: bool,
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| format!("unexpected end of file at byte {}", self.position))?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<usize, String> {
+        Ok(u32::from_be_bytes(self.array()?) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let position = self.position;
+        let len = self.u32()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| format!("invalid UTF-8 in string at byte {}", position))
+    }
+
+    fn function(&mut self) -> Result<CompiledFunction, String> {
+        let name = match self.u8()? {
+            0 => None,
+            1 => Some(self.string()?),
+            flag => return Err(format!("invalid function name flag {}", flag)),
+        };
+        let num_parameters = self.u32()?;
+        let required = self.u32()?;
+        let rest = match self.u8()? {
+            0 => false,
+            1 => true,
+            flag => return Err(format!("invalid rest parameter flag {}", flag)),
+        };
+        let mut locals = Vec::new();
+        for _ in 0..self.u32()? {
+            locals.push(self.string()?);
+        }
+        // the rest parameter is the local after the others
+        if num_parameters + rest as usize > locals.len() {
+            return Err(format!(
+                "function has {} parameters but only {} locals",
+                num_parameters + rest as usize,
+                locals.len()
+            ));
+        }
+        if required > num_parameters {
+            return Err(format!(
+                "function has {} required parameters but only {} parameters",
+                required, num_parameters
+            ));
+        }
+        let len = self.u32()?;
+        let instructions = self.take(len)?.to_vec();
+
+        let mut lines = Vec::new();
+        if self.debug_info {
+            for _ in 0..self.u32()? {
+                lines.push((self.u32()?, self.u32()?, self.u32()?));
+            }
+        }
+        let (line, column) = match self.debug_info {
+            true => (self.u32()?, self.u32()?),
+            false => (0, 0),
+        };
+        Ok(CompiledFunction {
+            name,
+            instructions,
+            lines,
+            signature: Signature {
+                names: locals[..num_parameters].to_vec(),
+                required,
+                rest,
+                line,
+                column,
+            },
+            locals,
+        })
+    }
+}
+
+/// check that every instruction of the function is complete and that its operands are in range
+fn verify_function(
+    bytecode: &Bytecode,
+    function: &CompiledFunction,
+    what: &str,
+) -> Result<(), String> {
+    let ins = &function.instructions;
+    let invalid = |offset: usize, reason: String| {
+        Err(format!(
+            "invalid instruction at offset {} of {}: {}",
+            offset, what, reason
+        ))
+    };
+
+    // jumps must land on the start of an instruction, which are only known after a first pass
+    let mut starts = vec![false; ins.len() + 1];
+    let mut jumps = Vec::new();
+    let mut offset = 0;
+    while offset < ins.len() {
+        starts[offset] = true;
+        let def = match lookup(ins[offset]) {
+            Some(def) => def,
+            None => return invalid(offset, format!("unknown opcode {}", ins[offset])),
+        };
+        let width: usize = def.operand_widths.iter().sum();
+        if offset + 1 + width > ins.len() {
+            return invalid(offset, format!("truncated {}", def.name));
+        }
+        let (operands, read) = read_operands(&def, &ins[offset + 1..]);
+
+        let in_range = |index: usize, len: usize, kind: &str| {
+            if index < len {
+                Ok(())
+            } else {
+                invalid(offset, format!("{} {} out of range", kind, index))
+            }
+        };
+        match ins[offset] {
+            OP_CONSTANT => in_range(operands[0], bytecode.constants.len(), "constant")?,
+            OP_CLOSURE => match bytecode.constants.get(operands[0]) {
+                Some(Object::CompiledFunction(_)) => {}
+                _ => {
+                    return invalid(
+                        offset,
+                        format!("constant {} is not a function", operands[0]),
+                    )
+                }
+            },
+            OP_GET_GLOBAL | OP_SET_GLOBAL => {
+                in_range(operands[0], bytecode.globals.len(), "global")?
+            }
+            OP_GET_LOCAL | OP_SET_LOCAL | OP_MISSING_ARGUMENT => {
+                in_range(operands[0], function.locals.len(), "local")?
+            }
+            OP_GET_BUILTIN => in_range(operands[0], BUILTINS.len(), "builtin")?,
+            OP_JUMP | OP_JUMP_NOT_TRUTHY | OP_ITER_NEXT => jumps.push((offset, operands[0])),
+            _ => {}
+        }
+        offset += 1 + read;
+    }
+    starts[ins.len()] = true;
+
+    for (offset, target) in jumps {
+        if !starts.get(target).copied().unwrap_or(false) {
+            return invalid(
+                offset,
+                format!("jump target {} is not an instruction", target),
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bytecode_file::{decode, encode, FORMAT_VERSION};
+    use crate::compiler::{Bytecode, Compiler};
+    use crate::disassembler::disassemble;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::vm::Vm;
+
+    const PROGRAM: &str = "\
+let greeting = \"hello\";
+let adder = fn(x, ...more) { fn(y, z = 0) { x + y + z } };
+let add_two = adder(2);
+if (add_two(40) == 42) { greeting + \" world\" } else { -1 }";
+
+    fn compile(input: &str) -> Bytecode {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program().unwrap();
+        Compiler::new().compile(&program).unwrap()
+    }
+
+    fn decode_error(bytes: &[u8]) -> String {
+        match decode(bytes) {
+            Ok(_) => panic!("decoding should have failed"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytecode = compile(PROGRAM);
+        let loaded = decode(&encode(&bytecode, true)).unwrap();
+        assert_eq!(disassemble(&loaded), disassemble(&bytecode));
+        assert_eq!(Vm::new().run(&loaded).unwrap().inspect(), "hello world");
+
+        // without debug info the program still runs, but the line tables are gone
+        let stripped = decode(&encode(&bytecode, false)).unwrap();
+        assert!(stripped.main.lines.is_empty());
+        assert_eq!(Vm::new().run(&stripped).unwrap().inspect(), "hello world");
+    }
+
+    #[test]
+    fn test_rejects_invalid_files() {
+        let bytes = encode(&compile(PROGRAM), true);
+
+        assert_eq!(decode_error(b""), "not a .lingoc file");
+        assert_eq!(decode_error(b"let x = 1;"), "not a .lingoc file");
+
+        let mut newer = bytes.clone();
+        newer[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_be_bytes());
+        assert_eq!(
+            decode_error(&newer),
+            format!(
+                "unsupported format version {}, this build reads version {}",
+                FORMAT_VERSION + 1,
+                FORMAT_VERSION
+            )
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            decode_error(&trailing),
+            "1 unexpected bytes at the end of the file"
+        );
+
+        // truncated files are always rejected, corrupted ones never make the loader panic
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len]).is_err(), "truncated at {}", len);
+        }
+        for position in 0..bytes.len() {
+            for value in [0, 1, 2, 0x7f, 0xff] {
+                let mut corrupted = bytes.clone();
+                corrupted[position] = value;
+                let _ = decode(&corrupted);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejects_out_of_range_operands() {
+        let bytecode = compile("1");
+        let mut bytes = encode(&bytecode, false);
+        // the top level is `OpConstant 0, OpReturnValue` at the very end of the file
+        let len = bytes.len();
+        bytes[len - 2] = 9;
+        assert_eq!(
+            decode_error(&bytes),
+            "invalid instruction at offset 0 of the top level: constant 9 out of range"
+        );
+    }
+}
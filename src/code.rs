@@ -0,0 +1,335 @@
+use iota::iota;
+
+/// a flat sequence of bytes, each instruction is an opcode followed by its operands
+pub type Instructions = Vec<u8>;
+
+pub type Opcode = u8;
+
+/// maps instruction offsets to source positions.
+/// holds `(offset, line, column)` triples, a triple is only added where the position changes
+pub type LineTable = Vec<(usize, usize, usize)>;
+
+/// the source line of the instruction at the offset, `None` if it has no position
+pub fn line_at(lines: &[(usize, usize, usize)], offset: usize) -> Option<usize> {
+    position_at(lines, offset).map(|(line, _)| line)
+}
+
+/// the source line and column of the instruction at the offset, `None` if it has no position
+pub fn position_at(lines: &[(usize, usize, usize)], offset: usize) -> Option<(usize, usize)> {
+    let index = lines.partition_point(|(start, _, _)| *start <= offset);
+    match index {
+        0 => None,
+        _ => {
+            let (_, line, column) = lines[index - 1];
+            Some((line, column)).filter(|_| line > 0)
+        }
+    }
+}
+
+iota! {
+    pub const OP_CONSTANT: Opcode = iota;
+        , OP_POP
+        , OP_ADD
+        , OP_SUB
+        , OP_MUL
+        , OP_DIV
+        , OP_TRUE
+        , OP_FALSE
+        , OP_NULL
+        , OP_EQUAL
+        , OP_NOT_EQUAL
+        , OP_GREATER_THAN
+        , OP_LESS_THAN
+        , OP_MINUS
+        , OP_BANG
+        , OP_JUMP_NOT_TRUTHY
+        , OP_JUMP
+        , OP_GET_GLOBAL
+        , OP_SET_GLOBAL
+        , OP_GET_LOCAL
+        , OP_SET_LOCAL
+        , OP_GET_FREE
+        , OP_GET_BUILTIN
+        , OP_ARRAY
+        , OP_HASH
+        , OP_INDEX
+        , OP_CALL
+        , OP_RETURN_VALUE
+        , OP_RETURN
+        , OP_CLOSURE
+        , OP_ITER
+        , OP_ITER_NEXT
+        , OP_SET_FREE
+        , OP_INDEX_KEEP
+        , OP_SET_INDEX
+        , OP_MATCH_LITERAL
+        , OP_MATCH_ARRAY
+        , OP_MATCH_KEY
+        , OP_REST
+        , OP_NO_MATCH
+        , OP_NO_DESTRUCTURE
+        , OP_MISSING_ARGUMENT
+        , OP_CALL_NAMED
+        , OP_MOD
+        , OP_POW
+        , OP_BIT_AND
+        , OP_BIT_OR
+        , OP_BIT_XOR
+        , OP_SHIFT_LEFT
+        , OP_SHIFT_RIGHT
+        , OP_BIT_NOT
+        , OP_STRUCT
+        , OP_INSTANCE
+        , OP_MEMBER
+        , OP_IMPL
+}
+
+/// the readable name of an opcode and the number of bytes each of its operands takes up
+pub struct Definition {
+    pub name: &'static str,
+    pub operand_widths: &'static [usize],
+}
+
+pub fn lookup(op: Opcode) -> Option<Definition> {
+    let (name, operand_widths): (&'static str, &'static [usize]) = match op {
+        // index of the constant in the constant pool
+        OP_CONSTANT => ("OpConstant", &[2]),
+        OP_POP => ("OpPop", &[]),
+        OP_ADD => ("OpAdd", &[]),
+        OP_SUB => ("OpSub", &[]),
+        OP_MUL => ("OpMul", &[]),
+        OP_DIV => ("OpDiv", &[]),
+        OP_TRUE => ("OpTrue", &[]),
+        OP_FALSE => ("OpFalse", &[]),
+        OP_NULL => ("OpNull", &[]),
+        OP_EQUAL => ("OpEqual", &[]),
+        OP_NOT_EQUAL => ("OpNotEqual", &[]),
+        OP_GREATER_THAN => ("OpGreaterThan", &[]),
+        OP_LESS_THAN => ("OpLessThan", &[]),
+        OP_MINUS => ("OpMinus", &[]),
+        OP_BANG => ("OpBang", &[]),
+        // absolute offset of the jump target
+        OP_JUMP_NOT_TRUTHY => ("OpJumpNotTruthy", &[2]),
+        OP_JUMP => ("OpJump", &[2]),
+        // index of the global binding
+        OP_GET_GLOBAL => ("OpGetGlobal", &[2]),
+        OP_SET_GLOBAL => ("OpSetGlobal", &[2]),
+        // index of the local binding in the current frame
+        OP_GET_LOCAL => ("OpGetLocal", &[1]),
+        OP_SET_LOCAL => ("OpSetLocal", &[1]),
+        // number of enclosing functions to go up, index of the local binding in that function
+        OP_GET_FREE => ("OpGetFree", &[1, 1]),
+        // index in `builtins::BUILTINS`
+        OP_GET_BUILTIN => ("OpGetBuiltin", &[1]),
+        // number of elements, for hashes keys and values are counted separately
+        OP_ARRAY => ("OpArray", &[2]),
+        OP_HASH => ("OpHash", &[2]),
+        OP_INDEX => ("OpIndex", &[]),
+        // number of arguments
+        OP_CALL => ("OpCall", &[1]),
+        OP_RETURN_VALUE => ("OpReturnValue", &[]),
+        OP_RETURN => ("OpReturn", &[]),
+        // index of the compiled function in the constant pool
+        OP_CLOSURE => ("OpClosure", &[2]),
+        // replace the value with the array of the values a `for` loop goes through
+        OP_ITER => ("OpIter", &[]),
+        // pop an index and the array below it, push the next index and the element at the index,
+        // or jump to the absolute offset once the array is exhausted
+        OP_ITER_NEXT => ("OpIterNext", &[2]),
+        // like OpGetFree
+        OP_SET_FREE => ("OpSetFree", &[1, 1]),
+        // like OpIndex, but the collection and the index are left on the stack below the element
+        OP_INDEX_KEEP => ("OpIndexKeep", &[]),
+        // pop a value, an index and a collection, push a copy of the collection
+        // holding the value at the index
+        OP_SET_INDEX => ("OpSetIndex", &[]),
+        // pop a literal and a value, push whether a literal pattern matches the value
+        OP_MATCH_LITERAL => ("OpMatchLiteral", &[]),
+        // number of element patterns, 1 if the pattern has a rest. pop a value,
+        // push whether it is an array of a length the array pattern matches
+        OP_MATCH_ARRAY => ("OpMatchArray", &[2, 1]),
+        // pop a key and a value, push whether the value is a hash holding the key
+        OP_MATCH_KEY => ("OpMatchKey", &[]),
+        // number of elements to leave out. pop an array, push the elements after those
+        OP_REST => ("OpRest", &[2]),
+        // pop the value no arm of a match expression matched and fail
+        OP_NO_MATCH => ("OpNoMatch", &[]),
+        // pop the format of a pattern and the value of a `let` or a parameter it didn't match, and fail
+        OP_NO_DESTRUCTURE => ("OpNoDestructure", &[]),
+        // the local of a parameter. push whether the call left its argument out
+        OP_MISSING_ARGUMENT => ("OpMissingArgument", &[1]),
+        // number of arguments, number of named arguments, which follow the others on the stack
+        // each as its name and its value
+        OP_CALL_NAMED => ("OpCallNamed", &[1, 1]),
+        // pop the right and the left operand, push the remainder of their division
+        OP_MOD => ("OpMod", &[]),
+        // pop the exponent and the base, push the power
+        OP_POW => ("OpPow", &[]),
+        OP_BIT_AND => ("OpBitAnd", &[]),
+        OP_BIT_OR => ("OpBitOr", &[]),
+        OP_BIT_XOR => ("OpBitXor", &[]),
+        // pop the shift amount and the integer shifted, push the result
+        OP_SHIFT_LEFT => ("OpShiftLeft", &[]),
+        OP_SHIFT_RIGHT => ("OpShiftRight", &[]),
+        // pop an integer, push it with all its bits flipped
+        OP_BIT_NOT => ("OpBitNot", &[]),
+        // number of fields. pop the name of the struct and of each field, push the struct
+        OP_STRUCT => ("OpStruct", &[1]),
+        // number of fields. pop the struct and the fields, each as its name and its value,
+        // push the instance
+        OP_INSTANCE => ("OpInstance", &[1]),
+        // pop an instance or a struct and the name of the member, push the member
+        OP_MEMBER => ("OpMember", &[]),
+        // number of methods. pop the struct and the methods, each as its name and its closure,
+        // add the methods to the struct
+        OP_IMPL => ("OpImpl", &[1]),
+        _ => return None,
+    };
+    Some(Definition {
+        name,
+        operand_widths,
+    })
+}
+
+/// encode an instruction, operands are written in big-endian order
+pub fn make(op: Opcode, operands: &[usize]) -> Instructions {
+    let def = match lookup(op) {
+        Some(def) => def,
+        None => return Vec::new(),
+    };
+
+    let len = 1 + def.operand_widths.iter().sum::<usize>();
+    let mut instruction = Vec::with_capacity(len);
+    instruction.push(op);
+    for (operand, width) in operands.iter().zip(def.operand_widths) {
+        match width {
+            2 => instruction.extend_from_slice(&(*operand as u16).to_be_bytes()),
+            1 => instruction.push(*operand as u8),
+            _ => {}
+        }
+    }
+    instruction
+}
+
+/// decode the operands following an opcode, returning them with the number of bytes read
+pub fn read_operands(def: &Definition, ins: &[u8]) -> (Vec<usize>, usize) {
+    let mut operands = Vec::with_capacity(def.operand_widths.len());
+    let mut offset = 0;
+    for width in def.operand_widths {
+        match width {
+            2 => operands.push(read_u16(ins, offset) as usize),
+            1 => operands.push(ins[offset] as usize),
+            _ => {}
+        }
+        offset += width;
+    }
+    (operands, offset)
+}
+
+pub fn read_u16(ins: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([ins[offset], ins[offset + 1]])
+}
+
+/// print the instructions one per line, prefixed by their offset
+#[cfg(test)]
+pub fn format_instructions(ins: &[u8]) -> String {
+    let mut out = String::new();
+    let mut offset = 0;
+    while offset < ins.len() {
+        let (instruction, len) = format_instruction(ins, offset);
+        out.push_str(&format!("{:04} {}\n", offset, instruction));
+        offset += len;
+    }
+    out
+}
+
+/// print the name and operands of the instruction at the offset,
+/// returning them with the length of the instruction
+pub fn format_instruction(ins: &[u8], offset: usize) -> (String, usize) {
+    let def = match lookup(ins[offset]) {
+        Some(def) => def,
+        None => return (format!("ERROR: undefined opcode {}", ins[offset]), 1),
+    };
+    let width: usize = def.operand_widths.iter().sum();
+    if offset + 1 + width > ins.len() {
+        return (format!("ERROR: truncated {}", def.name), ins.len() - offset);
+    }
+
+    let (operands, read) = read_operands(&def, &ins[offset + 1..]);
+    let mut out = def.name.to_string();
+    for operand in operands {
+        out.push_str(&format!(" {}", operand));
+    }
+    (out, 1 + read)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::code::*;
+
+    #[test]
+    fn test_make() {
+        let tests: Vec<(Opcode, Vec<usize>, Vec<u8>)> = vec![
+            (OP_CONSTANT, vec![65534], vec![OP_CONSTANT, 255, 254]),
+            (OP_ADD, vec![], vec![OP_ADD]),
+            (OP_GET_LOCAL, vec![255], vec![OP_GET_LOCAL, 255]),
+            (OP_GET_FREE, vec![1, 2], vec![OP_GET_FREE, 1, 2]),
+        ];
+        for (op, operands, expected) in tests {
+            assert_eq!(make(op, &operands), expected);
+        }
+    }
+
+    #[test]
+    fn test_read_operands() {
+        let tests: Vec<(Opcode, Vec<usize>, usize)> = vec![
+            (OP_CONSTANT, vec![65535], 2),
+            (OP_GET_LOCAL, vec![255], 1),
+            (OP_GET_FREE, vec![3, 255], 2),
+        ];
+        for (op, operands, bytes_read) in tests {
+            let instruction = make(op, &operands);
+            let def = lookup(op).unwrap();
+            let (read, n) = read_operands(&def, &instruction[1..]);
+            assert_eq!(n, bytes_read);
+            assert_eq!(read, operands);
+        }
+    }
+
+    #[test]
+    fn test_format_instructions() {
+        let instructions = [
+            make(OP_ADD, &[]),
+            make(OP_GET_LOCAL, &[1]),
+            make(OP_CONSTANT, &[2]),
+            make(OP_CONSTANT, &[65535]),
+            make(OP_GET_FREE, &[1, 0]),
+        ]
+        .concat();
+        let expected = "\
+0000 OpAdd
+0001 OpGetLocal 1
+0003 OpConstant 2
+0006 OpConstant 65535
+0009 OpGetFree 1 0
+";
+        assert_eq!(format_instructions(&instructions), expected);
+        assert_eq!(
+            format_instructions(&[OP_CONSTANT, 0, 1, 255, OP_CONSTANT, 0]),
+            "0000 OpConstant 1\n0003 ERROR: undefined opcode 255\n0004 ERROR: truncated OpConstant\n"
+        );
+    }
+
+    #[test]
+    fn test_line_at() {
+        let lines = vec![(0, 1, 1), (4, 3, 5), (7, 3, 9), (9, 0, 0)];
+        assert_eq!(line_at(&lines, 0), Some(1));
+        assert_eq!(line_at(&lines, 3), Some(1));
+        assert_eq!(line_at(&lines, 4), Some(3));
+        assert_eq!(line_at(&lines, 10), None);
+        assert_eq!(line_at(&[], 0), None);
+        assert_eq!(position_at(&lines, 5), Some((3, 5)));
+        assert_eq!(position_at(&lines, 8), Some((3, 9)));
+        assert_eq!(position_at(&lines, 9), None);
+    }
+}
@@ -0,0 +1,1083 @@
+use crate::ast::{
+    ArrayLiteral, ArrayPattern, AssignStatement, BlockStatement, Boolean, BreakStatement,
+    CallExpression, ContinueStatement, Expression, ExpressionStatement, ForStatement,
+    FunctionLiteral, HashLiteral, HashPattern, Identifier, IfExpression, ImplStatement,
+    IndexExpression, InfixExpression, IntegerLiteral, LetStatement, LiteralPattern, MacroLiteral,
+    MatchExpression, MemberExpression, Pattern, PrefixExpression, Program, ReturnStatement,
+    Statement, StringLiteral, StructLiteral, StructStatement, WhileStatement, WildcardPattern,
+};
+use crate::builtins::BUILTINS;
+use crate::code::*;
+use crate::evaluator::{is_quote_call, MACRO_LITERAL_ERROR};
+use crate::limits;
+use crate::object::{CompiledFunction, Object, Signature};
+use crate::symbol_table::{Symbol, SymbolScope, SymbolTable};
+use std::mem;
+use std::rc::Rc;
+
+/// what the compiler hands over to the virtual machine
+pub struct Bytecode {
+    /// the top level of the program, compiled like the body of a function without parameters
+    pub main: Rc<CompiledFunction>,
+    pub constants: Vec<Object>,
+    /// the names of the global bindings indexed by their slot
+    pub globals: Vec<String>,
+}
+
+#[derive(Clone, Copy)]
+struct EmittedInstruction {
+    opcode: Opcode,
+    position: usize,
+}
+
+/// a loop being compiled, so that `break` and `continue` know where to jump
+struct Loop {
+    /// where `continue` jumps to
+    start: usize,
+    /// the jumps emitted by `break`, patched once the end of the loop is known
+    breaks: Vec<usize>,
+}
+
+/// the instructions of the function being compiled
+#[derive(Default)]
+struct CompilationScope {
+    instructions: Instructions,
+    lines: LineTable,
+    last_instruction: Option<EmittedInstruction>,
+    previous_instruction: Option<EmittedInstruction>,
+    /// the loops enclosing the statement being compiled, innermost last
+    loops: Vec<Loop>,
+}
+
+/// lower the AST to bytecode for the virtual machine.
+/// the compiler keeps its constants and global bindings between calls to `compile`,
+/// so that a program can be compiled piece by piece
+pub struct Compiler {
+    constants: Vec<Object>,
+    symbol_table: SymbolTable,
+    scopes: Vec<CompilationScope>,
+    /// the source line and column of the node being compiled
+    position: (usize, usize),
+    /// how many match expressions enclose the node being compiled
+    match_depth: usize,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        let mut symbol_table = SymbolTable::new();
+        for (index, builtin) in BUILTINS.iter().enumerate() {
+            symbol_table.define_builtin(index, builtin.name);
+        }
+        Compiler {
+            constants: Vec::new(),
+            symbol_table,
+            scopes: vec![CompilationScope::default()],
+            position: (0, 0),
+            match_depth: 0,
+        }
+    }
+
+    /// compile the program like the body of a function,
+    /// its value is the value of its last statement unless it returns earlier
+    pub fn compile(&mut self, program: &Program) -> Result<Bytecode, String> {
+        self.scopes = vec![CompilationScope::default()];
+        for stat in &program.statements {
+            self.compile_statement(stat.as_ref())?;
+        }
+        self.finish_function_body();
+
+        let scope = mem::take(self.current_scope_mut());
+        let main = CompiledFunction {
+            name: None,
+            instructions: scope.instructions,
+            lines: scope.lines,
+            locals: Vec::new(),
+            signature: Signature::default(),
+        };
+        Ok(Bytecode {
+            main: Rc::new(main),
+            constants: self.constants.clone(),
+            globals: self.symbol_table.names(),
+        })
+    }
+
+    fn compile_statement(&mut self, stat: &dyn Statement) -> Result<(), String> {
+        let span = stat.span();
+        let outer_position = mem::replace(&mut self.position, (span.line, span.column));
+        let result = limits::grow_stack(|| self.compile_statement_at_line(stat));
+        self.position = outer_position;
+        result
+    }
+
+    fn compile_statement_at_line(&mut self, stat: &dyn Statement) -> Result<(), String> {
+        let any = stat.as_any();
+        if let Some(stat) = any.downcast_ref::<ExpressionStatement>() {
+            self.compile_optional_expression(&stat.expression)?;
+            self.emit(OP_POP, &[]);
+        } else if let Some(stat) = any.downcast_ref::<LetStatement>() {
+            let function = stat
+                .value
+                .as_ref()
+                .and_then(|value| value.as_any().downcast_ref::<FunctionLiteral>());
+            match (stat.name(), function) {
+                // functions are bound before their body is compiled so that they can call themselves
+                (Some(name), Some(function)) => {
+                    let symbol = self.symbol_table.define(&name.value);
+                    self.compile_function_literal(function, Some(&name.value))?;
+                    self.set_symbol(&symbol)?;
+                }
+                (Some(name), None) => {
+                    self.compile_optional_expression(&stat.value)?;
+                    let symbol = self.symbol_table.define(&name.value);
+                    self.set_symbol(&symbol)?;
+                }
+                (None, _) => {
+                    self.compile_optional_expression(&stat.value)?;
+                    let value = self.symbol_table.define("<let>");
+                    self.set_symbol(&value)?;
+                    self.compile_destructure(stat.pattern.as_ref(), &value, "let")?;
+                }
+            }
+        } else if let Some(stat) = any.downcast_ref::<ReturnStatement>() {
+            self.compile_optional_expression(&stat.return_value)?;
+            self.emit(OP_RETURN_VALUE, &[]);
+        } else if let Some(block) = any.downcast_ref::<BlockStatement>() {
+            self.compile_block_statement(block)?;
+            self.emit(OP_POP, &[]);
+        } else if let Some(stat) = any.downcast_ref::<AssignStatement>() {
+            self.compile_assign_statement(stat)?;
+        } else if let Some(stat) = any.downcast_ref::<WhileStatement>() {
+            self.compile_while_statement(stat)?;
+        } else if let Some(stat) = any.downcast_ref::<ForStatement>() {
+            self.compile_for_statement(stat)?;
+        } else if let Some(stat) = any.downcast_ref::<StructStatement>() {
+            for name in std::iter::once(&stat.name).chain(&stat.fields) {
+                let name = self.add_constant(Object::String(name.value.as_str().into()))?;
+                self.emit(OP_CONSTANT, &[name]);
+            }
+            let count = check_operand(stat.fields.len(), u8::MAX, "fields")?;
+            self.emit(OP_STRUCT, &[count]);
+            let symbol = self.symbol_table.define(&stat.name.value);
+            self.set_symbol(&symbol)?;
+        } else if let Some(stat) = any.downcast_ref::<ImplStatement>() {
+            self.compile_expression(&stat.name)?;
+            for (name, literal) in &stat.methods {
+                let constant = self.add_constant(Object::String(name.value.as_str().into()))?;
+                self.emit(OP_CONSTANT, &[constant]);
+                let qualified = format!("{}.{}", stat.name.value, name.value);
+                self.compile_function_literal(literal, Some(&qualified))?;
+            }
+            let count = check_operand(stat.methods.len(), u8::MAX, "methods")?;
+            self.emit(OP_IMPL, &[count]);
+        } else if any.is::<BreakStatement>() {
+            let jump = self.emit(OP_JUMP, &[0]);
+            match self.current_scope_mut().loops.last_mut() {
+                Some(innermost) => innermost.breaks.push(jump),
+                None => return Err("break outside of a loop".to_string()),
+            }
+        } else if any.is::<ContinueStatement>() {
+            match self.current_scope().loops.last() {
+                Some(innermost) => {
+                    let start = innermost.start;
+                    self.emit(OP_JUMP, &[start]);
+                }
+                None => return Err("continue outside of a loop".to_string()),
+            }
+        } else {
+            return Err(format!("unknown statement: {}", stat.format()));
+        }
+        Ok(())
+    }
+
+    /// leave the value of the last statement of the block on the stack, `null` if it has none
+    fn compile_block_statement(&mut self, block: &BlockStatement) -> Result<(), String> {
+        let start = self.current_scope().instructions.len();
+        for stat in &block.statements {
+            self.compile_statement(stat.as_ref())?;
+        }
+        let ends_with_pop = self
+            .current_scope()
+            .last_instruction
+            .is_some_and(|last| last.opcode == OP_POP && last.position >= start);
+        if ends_with_pop {
+            self.remove_last_instruction();
+        } else {
+            self.emit(OP_NULL, &[]);
+        }
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, expression: &dyn Expression) -> Result<(), String> {
+        let span = expression.span();
+        let outer_position = mem::replace(&mut self.position, (span.line, span.column));
+        let result = limits::grow_stack(|| self.compile_expression_at_line(expression));
+        self.position = outer_position;
+        result
+    }
+
+    fn compile_expression_at_line(&mut self, expression: &dyn Expression) -> Result<(), String> {
+        let any = expression.as_any();
+        if let Some(literal) = any.downcast_ref::<IntegerLiteral>() {
+            let value = i64::try_from(literal.value)
+                .map_err(|_| format!("integer literal out of range: {}", literal.value))?;
+            let index = self.add_constant(Object::Integer(value))?;
+            self.emit(OP_CONSTANT, &[index]);
+        } else if let Some(boolean) = any.downcast_ref::<Boolean>() {
+            self.emit(if boolean.value { OP_TRUE } else { OP_FALSE }, &[]);
+        } else if let Some(literal) = any.downcast_ref::<StringLiteral>() {
+            let index = self.add_constant(Object::String(literal.value.as_str().into()))?;
+            self.emit(OP_CONSTANT, &[index]);
+        } else if let Some(prefix) = any.downcast_ref::<PrefixExpression>() {
+            self.compile_expression(prefix.right.as_ref())?;
+            let op = match prefix.operator.as_str() {
+                "!" => OP_BANG,
+                "-" => OP_MINUS,
+                "~" => OP_BIT_NOT,
+                other => return Err(format!("unknown operator: {}", other)),
+            };
+            self.emit(op, &[]);
+        } else if let Some(infix) = any.downcast_ref::<InfixExpression>() {
+            self.compile_expression(infix.left.as_ref())?;
+            self.compile_expression(infix.right.as_ref())?;
+            let op = match infix.operator.as_str() {
+                "+" => OP_ADD,
+                "-" => OP_SUB,
+                "*" => OP_MUL,
+                "/" => OP_DIV,
+                "%" => OP_MOD,
+                "**" => OP_POW,
+                "&" => OP_BIT_AND,
+                "|" => OP_BIT_OR,
+                "^" => OP_BIT_XOR,
+                "<<" => OP_SHIFT_LEFT,
+                ">>" => OP_SHIFT_RIGHT,
+                "==" => OP_EQUAL,
+                "!=" => OP_NOT_EQUAL,
+                ">" => OP_GREATER_THAN,
+                "<" => OP_LESS_THAN,
+                other => return Err(format!("unknown operator: {}", other)),
+            };
+            self.emit(op, &[]);
+        } else if let Some(if_expression) = any.downcast_ref::<IfExpression>() {
+            self.compile_if_expression(if_expression)?;
+        } else if let Some(ident) = any.downcast_ref::<Identifier>() {
+            // names which are not bound yet may still be bound globally before the code runs
+            let symbol = match self.symbol_table.resolve(&ident.value) {
+                Some(symbol) => symbol,
+                None => self.symbol_table.global_mut().define(&ident.value),
+            };
+            self.load_symbol(&symbol)?;
+        } else if let Some(literal) = any.downcast_ref::<FunctionLiteral>() {
+            self.compile_function_literal(literal, None)?;
+        } else if let Some(call) = any.downcast_ref::<CallExpression>() {
+            // quoted code is a value of the evaluator, the virtual machine has no AST to run
+            if is_quote_call(call) {
+                return Err("quote is only supported by the evaluator and in macros".to_string());
+            }
+            self.compile_expression(call.function.as_ref())?;
+            for argument in &call.arguments {
+                self.compile_expression(argument.as_ref())?;
+            }
+            let count = check_operand(call.arguments.len(), u8::MAX, "arguments")?;
+            if call.named_arguments.is_empty() {
+                self.emit(OP_CALL, &[count]);
+            } else {
+                // each named argument is pushed as its name followed by its value
+                for (name, value) in &call.named_arguments {
+                    let name = self.add_constant(Object::String(name.value.as_str().into()))?;
+                    self.emit(OP_CONSTANT, &[name]);
+                    self.compile_expression(value.as_ref())?;
+                }
+                let named = check_operand(call.named_arguments.len(), u8::MAX, "arguments")?;
+                self.emit(OP_CALL_NAMED, &[count, named]);
+            }
+        } else if let Some(array) = any.downcast_ref::<ArrayLiteral>() {
+            for element in &array.elements {
+                self.compile_expression(element.as_ref())?;
+            }
+            let count = check_operand(array.elements.len(), u16::MAX, "array elements")?;
+            self.emit(OP_ARRAY, &[count]);
+        } else if let Some(index) = any.downcast_ref::<IndexExpression>() {
+            self.compile_expression(index.left.as_ref())?;
+            self.compile_expression(index.index.as_ref())?;
+            self.emit(OP_INDEX, &[]);
+        } else if let Some(hash) = any.downcast_ref::<HashLiteral>() {
+            for (key, value) in &hash.pairs {
+                self.compile_expression(key.as_ref())?;
+                self.compile_expression(value.as_ref())?;
+            }
+            let count = check_operand(hash.pairs.len() * 2, u16::MAX, "hash pairs")?;
+            self.emit(OP_HASH, &[count]);
+        } else if let Some(literal) = any.downcast_ref::<StructLiteral>() {
+            self.compile_expression(&literal.name)?;
+            for (name, value) in &literal.fields {
+                let name = self.add_constant(Object::String(name.value.as_str().into()))?;
+                self.emit(OP_CONSTANT, &[name]);
+                self.compile_expression(value.as_ref())?;
+            }
+            let count = check_operand(literal.fields.len(), u8::MAX, "fields")?;
+            self.emit(OP_INSTANCE, &[count]);
+        } else if let Some(member) = any.downcast_ref::<MemberExpression>() {
+            self.compile_expression(member.object.as_ref())?;
+            let name = self.add_constant(Object::String(member.member.value.as_str().into()))?;
+            self.emit(OP_CONSTANT, &[name]);
+            self.emit(OP_MEMBER, &[]);
+        } else if let Some(expression) = any.downcast_ref::<MatchExpression>() {
+            self.match_depth += 1;
+            let result = self.compile_match_expression(expression);
+            self.match_depth -= 1;
+            result?;
+        } else if any.is::<MacroLiteral>() {
+            return Err(MACRO_LITERAL_ERROR.to_string());
+        } else {
+            return Err(format!("unknown expression: {}", expression.format()));
+        }
+        Ok(())
+    }
+
+    /// expressions missing from a statement evaluate to `null`
+    fn compile_optional_expression(
+        &mut self,
+        expression: &Option<Box<dyn Expression>>,
+    ) -> Result<(), String> {
+        match expression {
+            None => {
+                self.emit(OP_NULL, &[]);
+                Ok(())
+            }
+            Some(expression) => self.compile_expression(expression.as_ref()),
+        }
+    }
+
+    fn compile_if_expression(&mut self, if_expression: &IfExpression) -> Result<(), String> {
+        self.compile_expression(if_expression.condition.as_ref())?;
+        // the jump targets are patched once the branches are compiled
+        let jump_not_truthy = self.emit(OP_JUMP_NOT_TRUTHY, &[0]);
+        self.compile_block_statement(&if_expression.consequence)?;
+        let jump = self.emit(OP_JUMP, &[0]);
+
+        let after_consequence = self.current_scope().instructions.len();
+        self.change_operand(jump_not_truthy, after_consequence)?;
+        match &if_expression.alternative {
+            None => {
+                self.emit(OP_NULL, &[]);
+            }
+            Some(alternative) => self.compile_block_statement(alternative)?,
+        }
+
+        let after_alternative = self.current_scope().instructions.len();
+        self.change_operand(jump, after_alternative)
+    }
+
+    /// load the binding, which fails like the evaluator does if it doesn't exist yet,
+    /// then the indexes and the collections they index. the assigned value is put
+    /// into copies of the collections from the inside out, the outermost one is bound again
+    fn compile_assign_statement(&mut self, stat: &AssignStatement) -> Result<(), String> {
+        let mut indexes = Vec::new();
+        let mut target = stat.target.as_ref();
+        while let Some(index) = target.as_any().downcast_ref::<IndexExpression>() {
+            indexes.push(index.index.as_ref());
+            target = index.left.as_ref();
+        }
+        indexes.reverse();
+        let name = match target.as_any().downcast_ref::<Identifier>() {
+            Some(ident) => &ident.value,
+            None => return Err(format!("cannot assign to {}", stat.target.format())),
+        };
+        let symbol = match self.symbol_table.resolve(name) {
+            Some(symbol) => symbol,
+            None => self.symbol_table.global_mut().define(name),
+        };
+        let operator = match stat.operator.as_str() {
+            "=" => None,
+            "+=" => Some(OP_ADD),
+            "-=" => Some(OP_SUB),
+            "*=" => Some(OP_MUL),
+            "/=" => Some(OP_DIV),
+            other => return Err(format!("unknown operator: {}", other)),
+        };
+
+        self.load_symbol(&symbol)?;
+        if indexes.is_empty() && operator.is_none() {
+            self.emit(OP_POP, &[]);
+        }
+        for (i, index) in indexes.iter().enumerate() {
+            self.compile_expression(*index)?;
+            // compound assignments need the current element as well
+            if i + 1 < indexes.len() || operator.is_some() {
+                self.emit(OP_INDEX_KEEP, &[]);
+            }
+        }
+        self.compile_expression(stat.value.as_ref())?;
+        if let Some(operator) = operator {
+            self.emit(operator, &[]);
+        }
+        for _ in &indexes {
+            self.emit(OP_SET_INDEX, &[]);
+        }
+        self.set_symbol(&symbol)
+    }
+
+    /// loops are statements, they leave nothing on the stack
+    fn compile_while_statement(&mut self, stat: &WhileStatement) -> Result<(), String> {
+        let start = self.current_scope().instructions.len();
+        self.compile_expression(stat.condition.as_ref())?;
+        let exit = self.emit(OP_JUMP_NOT_TRUTHY, &[0]);
+        let breaks = self.compile_loop_body(&stat.body, start)?;
+        self.finish_loop(start, exit, breaks)
+    }
+
+    /// the array being iterated over and the index of the next element are kept in bindings
+    /// the program can't refer to, named after how deep the loop is nested
+    fn compile_for_statement(&mut self, stat: &ForStatement) -> Result<(), String> {
+        let depth = self.current_scope().loops.len();
+        let items = self.symbol_table.define(&format!("<items {}>", depth));
+        let index = self.symbol_table.define(&format!("<index {}>", depth));
+
+        self.compile_expression(stat.iterable.as_ref())?;
+        self.emit(OP_ITER, &[]);
+        self.set_symbol(&items)?;
+        let zero = self.add_constant(Object::Integer(0))?;
+        self.emit(OP_CONSTANT, &[zero]);
+        self.set_symbol(&index)?;
+
+        let start = self.current_scope().instructions.len();
+        self.load_symbol(&items)?;
+        self.load_symbol(&index)?;
+        let exit = self.emit(OP_ITER_NEXT, &[0]);
+        let variable = self.symbol_table.define(&stat.variable.value);
+        self.set_symbol(&variable)?;
+        self.set_symbol(&index)?;
+        let breaks = self.compile_loop_body(&stat.body, start)?;
+        self.finish_loop(start, exit, breaks)
+    }
+
+    /// compile the statements of the body, returning the jumps of its `break` statements
+    fn compile_loop_body(
+        &mut self,
+        body: &BlockStatement,
+        start: usize,
+    ) -> Result<Vec<usize>, String> {
+        self.current_scope_mut().loops.push(Loop {
+            start,
+            breaks: Vec::new(),
+        });
+        let result = body
+            .statements
+            .iter()
+            .try_for_each(|stat| self.compile_statement(stat.as_ref()));
+        let innermost = self.current_scope_mut().loops.pop().unwrap();
+        result.map(|()| innermost.breaks)
+    }
+
+    /// jump back to the start of the loop and point the jumps leaving it past that jump
+    fn finish_loop(&mut self, start: usize, exit: usize, breaks: Vec<usize>) -> Result<(), String> {
+        let start = check_operand(start, u16::MAX, "instructions")?;
+        self.emit(OP_JUMP, &[start]);
+        let end = self.current_scope().instructions.len();
+        self.change_operand(exit, end)?;
+        for jump in breaks {
+            self.change_operand(jump, end)?;
+        }
+        Ok(())
+    }
+
+    /// the subject and the parts of it the patterns look into are kept in hidden bindings,
+    /// so that an arm which doesn't match leaves nothing on the stack.
+    /// the names of a pattern are bound to hidden bindings as well, which the guard refers to,
+    /// and are only copied to the bindings of the names once the arm is taken
+    fn compile_match_expression(&mut self, expression: &MatchExpression) -> Result<(), String> {
+        let path = format!("match {}", self.match_depth);
+        let subject = self.symbol_table.define(&format!("<{}>", path));
+        self.compile_expression(expression.subject.as_ref())?;
+        self.set_symbol(&subject)?;
+
+        let mut ends = Vec::new();
+        for arm in &expression.arms {
+            let mut fails = Vec::new();
+            let mut bindings = Vec::new();
+            self.compile_pattern(
+                arm.pattern.as_ref(),
+                &subject,
+                &path,
+                &mut fails,
+                Some(&mut bindings),
+            )?;
+            if let Some(guard) = &arm.guard {
+                let shadowed: Vec<_> = bindings
+                    .iter()
+                    .map(|(name, symbol)| self.symbol_table.alias(name, symbol))
+                    .collect();
+                let guard = self.compile_expression(guard.as_ref());
+                for ((name, _), previous) in bindings.iter().zip(shadowed).rev() {
+                    self.symbol_table.restore(name, previous);
+                }
+                guard?;
+                fails.push(self.emit(OP_JUMP_NOT_TRUTHY, &[0]));
+            }
+            for (name, hidden) in &bindings {
+                self.load_symbol(hidden)?;
+                let symbol = self.symbol_table.define(name);
+                self.set_symbol(&symbol)?;
+            }
+            self.compile_block_statement(&arm.body)?;
+            ends.push(self.emit(OP_JUMP, &[0]));
+
+            let next_arm = self.current_scope().instructions.len();
+            for jump in fails {
+                self.change_operand(jump, next_arm)?;
+            }
+        }
+        self.load_symbol(&subject)?;
+        self.emit(OP_NO_MATCH, &[]);
+
+        let end = self.current_scope().instructions.len();
+        for jump in ends {
+            self.change_operand(jump, end)?;
+        }
+        Ok(())
+    }
+
+    /// test the value of the symbol against the pattern and bind its names,
+    /// recording the jumps taken when it doesn't match.
+    /// given `bindings`, the names are bound to hidden bindings recorded there instead
+    fn compile_pattern(
+        &mut self,
+        pattern: &dyn Pattern,
+        value: &Symbol,
+        path: &str,
+        fails: &mut Vec<usize>,
+        mut bindings: Option<&mut Vec<(String, Symbol)>>,
+    ) -> Result<(), String> {
+        let any = pattern.as_any();
+        if any.is::<WildcardPattern>() {
+            return Ok(());
+        } else if let Some(ident) = any.downcast_ref::<Identifier>() {
+            self.load_symbol(value)?;
+            let symbol = self.define_pattern_name(&ident.value, path, bindings);
+            self.set_symbol(&symbol)?;
+        } else if let Some(literal) = any.downcast_ref::<LiteralPattern>() {
+            self.load_symbol(value)?;
+            self.compile_expression(literal.value.as_ref())?;
+            self.emit(OP_MATCH_LITERAL, &[]);
+            fails.push(self.emit(OP_JUMP_NOT_TRUTHY, &[0]));
+        } else if let Some(array) = any.downcast_ref::<ArrayPattern>() {
+            let count = check_operand(array.elements.len(), u16::MAX, "array patterns")?;
+            self.load_symbol(value)?;
+            self.emit(OP_MATCH_ARRAY, &[count, array.rest.is_some() as usize]);
+            fails.push(self.emit(OP_JUMP_NOT_TRUTHY, &[0]));
+            for (index, element) in array.elements.iter().enumerate() {
+                self.load_symbol(value)?;
+                let index_constant = self.add_constant(Object::Integer(index as i64))?;
+                self.emit(OP_CONSTANT, &[index_constant]);
+                self.emit(OP_INDEX, &[]);
+                let path = format!("{}.{}", path, index);
+                let symbol = self.symbol_table.define(&format!("<{}>", path));
+                self.set_symbol(&symbol)?;
+                self.compile_pattern(
+                    element.as_ref(),
+                    &symbol,
+                    &path,
+                    fails,
+                    bindings.as_deref_mut(),
+                )?;
+            }
+            if let Some(rest) = &array.rest {
+                self.load_symbol(value)?;
+                self.emit(OP_REST, &[count]);
+                let symbol = self.define_pattern_name(&rest.value, path, bindings);
+                self.set_symbol(&symbol)?;
+            }
+        } else if let Some(hash) = any.downcast_ref::<HashPattern>() {
+            for (index, (key, pattern)) in hash.pairs.iter().enumerate() {
+                self.load_symbol(value)?;
+                self.compile_expression(key.as_ref())?;
+                self.emit(OP_MATCH_KEY, &[]);
+                fails.push(self.emit(OP_JUMP_NOT_TRUTHY, &[0]));
+                self.load_symbol(value)?;
+                self.compile_expression(key.as_ref())?;
+                self.emit(OP_INDEX, &[]);
+                let path = format!("{}.{}", path, index);
+                let symbol = self.symbol_table.define(&format!("<{}>", path));
+                self.set_symbol(&symbol)?;
+                self.compile_pattern(
+                    pattern.as_ref(),
+                    &symbol,
+                    &path,
+                    fails,
+                    bindings.as_deref_mut(),
+                )?;
+            }
+        } else {
+            return Err(format!("unknown pattern: {}", pattern.format()));
+        }
+        Ok(())
+    }
+
+    /// the binding a name of a pattern is stored in: the one of the name itself,
+    /// or a hidden one recorded in `bindings`
+    fn define_pattern_name(
+        &mut self,
+        name: &str,
+        path: &str,
+        bindings: Option<&mut Vec<(String, Symbol)>>,
+    ) -> Symbol {
+        match bindings {
+            Some(bindings) => {
+                let symbol = self.symbol_table.define(&format!("<{} {}>", path, name));
+                bindings.push((name.to_string(), symbol.clone()));
+                symbol
+            }
+            None => self.symbol_table.define(name),
+        }
+    }
+
+    /// bind the names of the pattern of a `let` or a parameter to the parts of the value,
+    /// failing at runtime with an error naming the pattern if the value doesn't have its shape
+    fn compile_destructure(
+        &mut self,
+        pattern: &dyn Pattern,
+        value: &Symbol,
+        path: &str,
+    ) -> Result<(), String> {
+        let mut fails = Vec::new();
+        self.compile_pattern(pattern, value, path, &mut fails, None)?;
+        if fails.is_empty() {
+            return Ok(());
+        }
+        let end = self.emit(OP_JUMP, &[0]);
+        let fail = self.current_scope().instructions.len();
+        for jump in fails {
+            self.change_operand(jump, fail)?;
+        }
+        self.load_symbol(value)?;
+        let format = self.add_constant(Object::String(pattern.format().into()))?;
+        self.emit(OP_CONSTANT, &[format]);
+        self.emit(OP_NO_DESTRUCTURE, &[]);
+        let after = self.current_scope().instructions.len();
+        self.change_operand(end, after)
+    }
+
+    fn compile_function_literal(
+        &mut self,
+        literal: &FunctionLiteral,
+        name: Option<&str>,
+    ) -> Result<(), String> {
+        self.enter_scope();
+        if let Err(err) = self.compile_function_body(literal) {
+            self.leave_scope();
+            return Err(err);
+        }
+        let locals = self.symbol_table.names();
+        let scope = self.leave_scope();
+        check_operand(locals.len(), u8::MAX as usize + 1, "local bindings")?;
+
+        let function = CompiledFunction {
+            name: name.map(|name| name.to_string()),
+            instructions: scope.instructions,
+            lines: scope.lines,
+            locals,
+            signature: Signature::new(literal),
+        };
+        let index = self.add_constant(Object::CompiledFunction(Rc::new(function)))?;
+        self.emit(OP_CLOSURE, &[index]);
+        Ok(())
+    }
+
+    /// bind the parameters, taking the ones after them for the rest parameter,
+    /// and compile the statements of the body
+    fn compile_function_body(&mut self, literal: &FunctionLiteral) -> Result<(), String> {
+        // a destructured argument is kept in a local named after the pattern
+        let symbols: Vec<Symbol> = literal
+            .parameters
+            .iter()
+            .map(|param| self.symbol_table.define(&param.pattern.format()))
+            .collect();
+        if let Some(rest) = &literal.rest {
+            self.symbol_table.define(&rest.value);
+        }
+        for (index, (param, symbol)) in literal.parameters.iter().zip(&symbols).enumerate() {
+            if let Some(default) = &param.default {
+                self.emit(OP_MISSING_ARGUMENT, &[symbol.index]);
+                let jump = self.emit(OP_JUMP_NOT_TRUTHY, &[0]);
+                self.compile_expression(default.as_ref())?;
+                self.set_symbol(symbol)?;
+                let after_default = self.current_scope().instructions.len();
+                self.change_operand(jump, after_default)?;
+            }
+            if !param.pattern.as_any().is::<Identifier>() {
+                let path = format!("param {}", index);
+                self.compile_destructure(param.pattern.as_ref(), symbol, &path)?;
+            }
+        }
+        for stat in &literal.body.statements {
+            self.compile_statement(stat.as_ref())?;
+        }
+        self.finish_function_body();
+        Ok(())
+    }
+
+    /// return the value of the last statement, or `null` if the body ends with something else
+    fn finish_function_body(&mut self) {
+        match self.current_scope().last_instruction {
+            Some(last) if last.opcode == OP_POP => {
+                self.current_scope_mut().instructions[last.position] = OP_RETURN_VALUE;
+                self.current_scope_mut().last_instruction = Some(EmittedInstruction {
+                    opcode: OP_RETURN_VALUE,
+                    position: last.position,
+                });
+            }
+            _ => {
+                self.emit(OP_RETURN, &[]);
+            }
+        }
+    }
+
+    fn load_symbol(&mut self, symbol: &Symbol) -> Result<(), String> {
+        match symbol.scope {
+            SymbolScope::Global => {
+                let index = check_operand(symbol.index, u16::MAX, "global bindings")?;
+                self.emit(OP_GET_GLOBAL, &[index]);
+            }
+            SymbolScope::Local => {
+                self.emit(OP_GET_LOCAL, &[symbol.index]);
+            }
+            SymbolScope::Builtin => {
+                self.emit(OP_GET_BUILTIN, &[symbol.index]);
+            }
+            SymbolScope::Free { depth } => {
+                let depth = check_operand(depth, u8::MAX, "nested functions")?;
+                self.emit(OP_GET_FREE, &[depth, symbol.index]);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_symbol(&mut self, symbol: &Symbol) -> Result<(), String> {
+        match symbol.scope {
+            SymbolScope::Global => {
+                let index = check_operand(symbol.index, u16::MAX, "global bindings")?;
+                self.emit(OP_SET_GLOBAL, &[index]);
+            }
+            SymbolScope::Local => {
+                self.emit(OP_SET_LOCAL, &[symbol.index]);
+            }
+            SymbolScope::Builtin => {
+                return Err(format!(
+                    "cannot assign to builtin function: {}",
+                    symbol.name
+                ));
+            }
+            SymbolScope::Free { depth } => {
+                let depth = check_operand(depth, u8::MAX, "nested functions")?;
+                self.emit(OP_SET_FREE, &[depth, symbol.index]);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_constant(&mut self, constant: Object) -> Result<usize, String> {
+        self.constants.push(constant);
+        check_operand(self.constants.len() - 1, u16::MAX, "constants")
+    }
+
+    /// append the instruction to the current scope, returning its position
+    fn emit(&mut self, op: Opcode, operands: &[usize]) -> usize {
+        let instruction = make(op, operands);
+        let (line, column) = self.position;
+        let scope = self.current_scope_mut();
+        let position = scope.instructions.len();
+        if scope.lines.last().map(|(_, line, column)| (*line, *column)) != Some((line, column)) {
+            scope.lines.push((position, line, column));
+        }
+        scope.instructions.extend(instruction);
+        scope.previous_instruction = scope.last_instruction;
+        scope.last_instruction = Some(EmittedInstruction {
+            opcode: op,
+            position,
+        });
+        position
+    }
+
+    fn remove_last_instruction(&mut self) {
+        let scope = self.current_scope_mut();
+        if let Some(last) = scope.last_instruction {
+            scope.instructions.truncate(last.position);
+            if scope
+                .lines
+                .last()
+                .is_some_and(|(start, _, _)| *start >= last.position)
+            {
+                scope.lines.pop();
+            }
+            scope.last_instruction = scope.previous_instruction.take();
+        }
+    }
+
+    fn change_operand(&mut self, position: usize, operand: usize) -> Result<(), String> {
+        let operand = check_operand(operand, u16::MAX, "instructions")?;
+        let scope = self.current_scope_mut();
+        let instruction = make(scope.instructions[position], &[operand]);
+        scope.instructions[position..position + instruction.len()].copy_from_slice(&instruction);
+        Ok(())
+    }
+
+    fn current_scope(&self) -> &CompilationScope {
+        self.scopes.last().unwrap()
+    }
+
+    fn current_scope_mut(&mut self) -> &mut CompilationScope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(CompilationScope::default());
+        let outer = mem::take(&mut self.symbol_table);
+        self.symbol_table = SymbolTable::new_enclosed(outer);
+    }
+
+    fn leave_scope(&mut self) -> CompilationScope {
+        let scope = self.scopes.pop().unwrap();
+        let outer = self.symbol_table.outer.take().unwrap();
+        self.symbol_table = *outer;
+        scope
+    }
+}
+
+/// make sure the value fits in the operand of an instruction
+fn check_operand(value: usize, max: impl Into<usize>, what: &str) -> Result<usize, String> {
+    if value > max.into() {
+        return Err(format!("too many {}: {}", what, value));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::code::*;
+    use crate::compiler::{Bytecode, Compiler};
+    use crate::lexer::Lexer;
+    use crate::object::Object;
+    use crate::parser::Parser;
+
+    fn compile(input: &str) -> Bytecode {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program().unwrap();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        Compiler::new().compile(&program).unwrap()
+    }
+
+    fn constants(bytecode: &Bytecode) -> Vec<String> {
+        bytecode.constants.iter().map(|c| c.inspect()).collect()
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let bytecode = compile("1 + 2; -3");
+        let expected = [
+            make(OP_CONSTANT, &[0]),
+            make(OP_CONSTANT, &[1]),
+            make(OP_ADD, &[]),
+            make(OP_POP, &[]),
+            make(OP_CONSTANT, &[2]),
+            make(OP_MINUS, &[]),
+            make(OP_RETURN_VALUE, &[]),
+        ]
+        .concat();
+        assert_eq!(
+            format_instructions(&bytecode.main.instructions),
+            format_instructions(&expected)
+        );
+        assert_eq!(constants(&bytecode), vec!["1", "2", "3"]);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let bytecode = compile("if (true) { 10 }; 3333;");
+        let expected = [
+            // 0000
+            make(OP_TRUE, &[]),
+            // 0001
+            make(OP_JUMP_NOT_TRUTHY, &[10]),
+            // 0004
+            make(OP_CONSTANT, &[0]),
+            // 0007
+            make(OP_JUMP, &[11]),
+            // 0010
+            make(OP_NULL, &[]),
+            // 0011
+            make(OP_POP, &[]),
+            // 0012
+            make(OP_CONSTANT, &[1]),
+            // 0015
+            make(OP_RETURN_VALUE, &[]),
+        ]
+        .concat();
+        assert_eq!(
+            format_instructions(&bytecode.main.instructions),
+            format_instructions(&expected)
+        );
+    }
+
+    #[test]
+    fn test_loops() {
+        let bytecode = compile("while (true) { break; continue; }");
+        let expected = [
+            // 0000
+            make(OP_TRUE, &[]),
+            // 0001
+            make(OP_JUMP_NOT_TRUTHY, &[13]),
+            // 0004
+            make(OP_JUMP, &[13]),
+            // 0007
+            make(OP_JUMP, &[0]),
+            // 0010
+            make(OP_JUMP, &[0]),
+            // 0013
+            make(OP_RETURN, &[]),
+        ]
+        .concat();
+        assert_eq!(
+            format_instructions(&bytecode.main.instructions),
+            format_instructions(&expected)
+        );
+
+        let bytecode = compile("for (x in []) { }");
+        let expected = [
+            make(OP_ARRAY, &[0]),
+            make(OP_ITER, &[]),
+            make(OP_SET_GLOBAL, &[0]),
+            make(OP_CONSTANT, &[0]),
+            make(OP_SET_GLOBAL, &[1]),
+            // 0013
+            make(OP_GET_GLOBAL, &[0]),
+            make(OP_GET_GLOBAL, &[1]),
+            make(OP_ITER_NEXT, &[31]),
+            make(OP_SET_GLOBAL, &[2]),
+            make(OP_SET_GLOBAL, &[1]),
+            make(OP_JUMP, &[13]),
+            // 0031
+            make(OP_RETURN, &[]),
+        ]
+        .concat();
+        assert_eq!(
+            format_instructions(&bytecode.main.instructions),
+            format_instructions(&expected)
+        );
+        assert_eq!(bytecode.globals, vec!["<items 0>", "<index 0>", "x"]);
+    }
+
+    #[test]
+    fn test_assignments() {
+        let bytecode = compile("let a = [1]; a = 2; a[0] += 3;");
+        let expected = [
+            make(OP_CONSTANT, &[0]),
+            make(OP_ARRAY, &[1]),
+            make(OP_SET_GLOBAL, &[0]),
+            make(OP_GET_GLOBAL, &[0]),
+            make(OP_POP, &[]),
+            make(OP_CONSTANT, &[1]),
+            make(OP_SET_GLOBAL, &[0]),
+            make(OP_GET_GLOBAL, &[0]),
+            make(OP_CONSTANT, &[2]),
+            make(OP_INDEX_KEEP, &[]),
+            make(OP_CONSTANT, &[3]),
+            make(OP_ADD, &[]),
+            make(OP_SET_INDEX, &[]),
+            make(OP_SET_GLOBAL, &[0]),
+            make(OP_RETURN, &[]),
+        ]
+        .concat();
+        assert_eq!(
+            format_instructions(&bytecode.main.instructions),
+            format_instructions(&expected)
+        );
+
+        let mut parser = Parser::new(Lexer::new("len = 1".to_string()));
+        let program = parser.parse_program().unwrap();
+        assert_eq!(
+            Compiler::new().compile(&program).err(),
+            Some("cannot assign to builtin function: len".to_string())
+        );
+    }
+
+    #[test]
+    fn test_global_let_statements() {
+        let bytecode = compile("let one = 1; let two = one; two;");
+        let expected = [
+            make(OP_CONSTANT, &[0]),
+            make(OP_SET_GLOBAL, &[0]),
+            make(OP_GET_GLOBAL, &[0]),
+            make(OP_SET_GLOBAL, &[1]),
+            make(OP_GET_GLOBAL, &[1]),
+            make(OP_RETURN_VALUE, &[]),
+        ]
+        .concat();
+        assert_eq!(
+            format_instructions(&bytecode.main.instructions),
+            format_instructions(&expected)
+        );
+        assert_eq!(bytecode.globals, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn test_functions_and_closures() {
+        let bytecode = compile("fn(a) { fn(b) { a + b + len } }");
+        let inner = [
+            make(OP_GET_FREE, &[1, 0]),
+            make(OP_GET_LOCAL, &[0]),
+            make(OP_ADD, &[]),
+            make(OP_GET_BUILTIN, &[0]),
+            make(OP_ADD, &[]),
+            make(OP_RETURN_VALUE, &[]),
+        ]
+        .concat();
+        let outer = [make(OP_CLOSURE, &[0]), make(OP_RETURN_VALUE, &[])].concat();
+
+        let functions: Vec<_> = bytecode
+            .constants
+            .iter()
+            .map(|constant| match constant {
+                Object::CompiledFunction(function) => function.clone(),
+                other => panic!("expected compiled function, got {}", other.inspect()),
+            })
+            .collect();
+        assert_eq!(
+            format_instructions(&functions[0].instructions),
+            format_instructions(&inner)
+        );
+        assert_eq!(functions[0].locals, vec!["b"]);
+        assert_eq!(
+            format_instructions(&functions[1].instructions),
+            format_instructions(&outer)
+        );
+        assert_eq!(functions[1].parameters().len(), 1);
+        assert_eq!(
+            format_instructions(&bytecode.main.instructions),
+            format_instructions(&[make(OP_CLOSURE, &[1]), make(OP_RETURN_VALUE, &[])].concat())
+        );
+    }
+
+    #[test]
+    fn test_empty_function_and_let_ending() {
+        let bytecode = compile("fn() { }; let a = 1;");
+        let expected = [
+            make(OP_CLOSURE, &[0]),
+            make(OP_POP, &[]),
+            make(OP_CONSTANT, &[1]),
+            make(OP_SET_GLOBAL, &[0]),
+            make(OP_RETURN, &[]),
+        ]
+        .concat();
+        assert_eq!(
+            format_instructions(&bytecode.main.instructions),
+            format_instructions(&expected)
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use crate::builtins::BUILTINS;
+use crate::code::*;
+use crate::compiler::Bytecode;
+use crate::object::{CompiledFunction, Object};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+/// print the bytecode in a readable form: the constant pool and the global bindings,
+/// then the instructions of the top level followed by those of every function.
+/// each instruction is shown with its offset, its source line, its decoded operands
+/// and what they refer to, such as the value of a constant or the name of a binding
+pub fn disassemble(bytecode: &Bytecode) -> String {
+    let mut out = String::new();
+
+    out.push_str("constants:\n");
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        out.push_str(&format!(
+            "  {:>4}  {}\n",
+            index,
+            describe_constant(constant)
+        ));
+    }
+    out.push_str(&format!("globals: {}\n", bytecode.globals.join(", ")));
+
+    out.push_str("\n== <main> ==\n");
+    let mut functions = VecDeque::new();
+    disassemble_function(&mut out, bytecode, &bytecode.main, &[], &mut functions);
+
+    // functions are listed in the order their closures are created,
+    // each one with the functions it is nested in so that free bindings can be named
+    while let Some((index, enclosing)) = functions.pop_front() {
+        let function = match bytecode.constants.get(index) {
+            Some(Object::CompiledFunction(function)) => function.clone(),
+            _ => continue,
+        };
+        out.push_str(&format!(
+            "\n== constant {}: {} ==\n",
+            index,
+            describe_function(&function)
+        ));
+        if !function.locals.is_empty() {
+            out.push_str(&format!("locals: {}\n", function.locals.join(", ")));
+        }
+        disassemble_function(&mut out, bytecode, &function, &enclosing, &mut functions);
+    }
+    out
+}
+
+fn disassemble_function(
+    out: &mut String,
+    bytecode: &Bytecode,
+    function: &Rc<CompiledFunction>,
+    enclosing: &[Rc<CompiledFunction>],
+    functions: &mut VecDeque<(usize, Vec<Rc<CompiledFunction>>)>,
+) {
+    let ins = &function.instructions;
+    let mut previous_line = None;
+    let mut offset = 0;
+    while offset < ins.len() {
+        let (instruction, len) = format_instruction(ins, offset);
+
+        let line = line_at(&function.lines, offset);
+        let line_column = match line {
+            None => "-".to_string(),
+            Some(_) if line == previous_line => "|".to_string(),
+            Some(line) => line.to_string(),
+        };
+        previous_line = line;
+
+        let operands = match lookup(ins[offset]) {
+            Some(def) if offset + len <= ins.len() => read_operands(&def, &ins[offset + 1..]).0,
+            _ => Vec::new(),
+        };
+        let comment = match (ins[offset], operands.as_slice()) {
+            (OP_CONSTANT, [index]) => bytecode.constants.get(*index).map(describe_constant),
+            (OP_CLOSURE, [index]) => {
+                let mut nested = enclosing.to_vec();
+                nested.push(function.clone());
+                functions.push_back((*index, nested));
+                bytecode.constants.get(*index).map(describe_constant)
+            }
+            (OP_GET_GLOBAL | OP_SET_GLOBAL, [index]) => bytecode.globals.get(*index).cloned(),
+            (OP_GET_LOCAL | OP_SET_LOCAL, [index]) => function.locals.get(*index).cloned(),
+            (OP_GET_FREE | OP_SET_FREE, [depth, index]) => enclosing
+                .len()
+                .checked_sub(*depth)
+                .and_then(|outer| enclosing[outer].locals.get(*index).cloned()),
+            (OP_GET_BUILTIN, [index]) => {
+                BUILTINS.get(*index).map(|builtin| builtin.name.to_string())
+            }
+            _ => None,
+        };
+
+        let mut row = format!("{:04} {:>4}  {}", offset, line_column, instruction);
+        if let Some(comment) = comment {
+            row = format!("{:<40} ; {}", row, comment);
+        }
+        out.push_str(&row);
+        out.push('\n');
+        offset += len;
+    }
+}
+
+fn describe_constant(constant: &Object) -> String {
+    match constant {
+        Object::String(value) => format!("{:?}", value),
+        Object::CompiledFunction(function) => describe_function(function),
+        other => other.inspect(),
+    }
+}
+
+fn describe_function(function: &CompiledFunction) -> String {
+    format!(
+        "fn {}({})",
+        function.name.as_deref().unwrap_or("<anonymous>"),
+        function.format_parameters()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::Compiler;
+    use crate::disassembler::disassemble;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_disassemble() {
+        let input = "\
+let greeting = \"hi\";
+let add = fn(a) {
+  fn(b) {
+    a + b + len(greeting)
+  }
+};
+add(1)(2)";
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program().unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+
+        let expected = r#"constants:
+     0  "hi"
+     1  fn <anonymous>(b)
+     2  fn add(a)
+     3  1
+     4  2
+globals: greeting, add
+
+== <main> ==
+0000    1  OpConstant 0                  ; "hi"
+0003    |  OpSetGlobal 0                 ; greeting
+0006    2  OpClosure 2                   ; fn add(a)
+0009    |  OpSetGlobal 1                 ; add
+0012    7  OpGetGlobal 1                 ; add
+0015    |  OpConstant 3                  ; 1
+0018    |  OpCall 1
+0020    |  OpConstant 4                  ; 2
+0023    |  OpCall 1
+0025    |  OpReturnValue
+
+== constant 2: fn add(a) ==
+locals: a
+0000    3  OpClosure 1                   ; fn <anonymous>(b)
+0003    |  OpReturnValue
+
+== constant 1: fn <anonymous>(b) ==
+locals: b
+0000    4  OpGetFree 1 0                 ; a
+0003    |  OpGetLocal 0                  ; b
+0005    |  OpAdd
+0006    |  OpGetBuiltin 0                ; len
+0008    |  OpGetGlobal 0                 ; greeting
+0011    |  OpCall 1
+0013    |  OpAdd
+0014    |  OpReturnValue
+"#;
+        assert_eq!(disassemble(&bytecode), expected);
+    }
+}
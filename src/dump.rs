@@ -0,0 +1,142 @@
+use crate::ast::Program;
+use crate::compiler::Compiler;
+use crate::disassembler::disassemble;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::token::EOF;
+
+/// what the debug dump modes print for a piece of source code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DumpMode {
+    /// the token stream, one token per line together with its span
+    Tokens,
+    /// the parsed AST as an indented tree
+    Ast,
+    /// the parsed AST as pretty-printed JSON
+    AstJson,
+    /// the disassembled bytecode the program compiles to
+    Bytecode,
+}
+
+/// why the source code could not be dumped
+#[derive(Debug, PartialEq, Eq)]
+pub enum DumpError {
+    Parser(Vec<String>),
+    Compiler(String),
+}
+
+/// dump the source code in the given mode.
+/// the other modes fail if the source code could not be parsed, or compiled for `Bytecode`
+pub fn dump(source: &str, mode: DumpMode) -> Result<String, DumpError> {
+    match mode {
+        DumpMode::Tokens => Ok(dump_tokens(source)),
+        DumpMode::Ast => {
+            let program = parse(source)?;
+            Ok(program.dump().to_tree())
+        }
+        DumpMode::AstJson => {
+            let program = parse(source)?;
+            let json = serde_json::to_string_pretty(&program.dump().to_json())
+                .expect("AST dump is always valid JSON");
+            Ok(json + "\n")
+        }
+        DumpMode::Bytecode => {
+            let program = parse(source)?;
+            let bytecode = Compiler::new()
+                .compile(&program)
+                .map_err(DumpError::Compiler)?;
+            Ok(disassemble(&bytecode))
+        }
+    }
+}
+
+/// list every token up to and including `EOF`
+pub fn dump_tokens(source: &str) -> String {
+    let mut lex = Lexer::new(source.to_string());
+    let mut out = String::new();
+    loop {
+        let token = lex.next_token();
+        out.push_str(&format!("{:<16} {}\n", token.span.to_string(), token));
+        if token.token_type == EOF {
+            break;
+        }
+    }
+    out
+}
+
+fn parse(source: &str) -> Result<Program, DumpError> {
+    let mut parser = Parser::new(Lexer::new(source.to_string()));
+    let program = parser.parse_program();
+    if !parser.errors().is_empty() {
+        return Err(DumpError::Parser(parser.errors().to_vec()));
+    }
+    Ok(program.expect("parse_program always returns a program"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dump::{dump, DumpError, DumpMode};
+
+    #[test]
+    fn test_dump_tokens() {
+        let expected = "\
+1:1 (0..1)       [Type:INT, Literal: 1]
+1:3 (2..3)       [Type:+, Literal: +]
+2:1 (4..5)       [Type:IDENT, Literal: x]
+2:2 (5..5)       [Type:EOF, Literal: ]
+";
+        assert_eq!(dump("1 +\nx", DumpMode::Tokens).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_dump_ast() {
+        let expected = "\
+Program
+  statements[0]: ExpressionStatement
+    expression: InfixExpression operator=\"+\"
+      left: PrefixExpression operator=\"-\"
+        right: IntegerLiteral value=1
+      right: Identifier value=\"a\"
+";
+        assert_eq!(dump("-1 + a", DumpMode::Ast).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_dump_ast_json() {
+        let expected = r#"{
+  "type": "Program",
+  "statements": [
+    {
+      "type": "ExpressionStatement",
+      "expression": {
+        "type": "Boolean",
+        "value": true
+      }
+    }
+  ]
+}
+"#;
+        assert_eq!(dump("true;", DumpMode::AstJson).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_dump_parser_errors() {
+        let errors = dump(")", DumpMode::Ast).unwrap_err();
+        assert_eq!(
+            errors,
+            DumpError::Parser(vec!["no prefix parse function for ) found".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_dump_bytecode() {
+        let out = dump("1 + 2", DumpMode::Bytecode).unwrap();
+        assert!(out.contains("0006    |  OpAdd\n"), "{}", out);
+
+        let errors = dump("9223372036854775808", DumpMode::Bytecode).unwrap_err();
+        assert_eq!(
+            errors,
+            DumpError::Compiler("integer literal out of range: 9223372036854775808".to_string())
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use crate::gc;
+use crate::object::Object;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::rc::Rc;
+
+/// environments are shared between the evaluator and the closures created in them
+pub type Env = Rc<RefCell<Environment>>;
+
+/// associate names with the values bound to them.
+/// a function call gets an environment enclosed by the one the function was defined in,
+/// which is consulted when a name is not bound in the inner environment
+#[derive(Default)]
+pub struct Environment {
+    store: HashMap<String, Object>,
+    outer: Option<Env>,
+}
+
+impl Environment {
+    pub fn new() -> Env {
+        let env = Rc::new(RefCell::new(Environment::default()));
+        gc::track_env(&env);
+        env
+    }
+
+    pub fn new_enclosed(outer: Env) -> Env {
+        let env = Rc::new(RefCell::new(Environment {
+            store: HashMap::new(),
+            outer: Some(outer),
+        }));
+        gc::track_env(&env);
+        env
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        match self.store.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.outer.as_ref()?.borrow().get(name),
+        }
+    }
+
+    /// the bindings of this environment, without the ones of the outer environments, sorted by name
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        let mut bindings: Vec<(String, Object)> = self
+            .store
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
+    pub fn set(&mut self, name: &str, value: Object) {
+        self.store.insert(name.to_string(), value);
+    }
+
+    /// rebind the name in the environment it is bound in, which may be an outer one,
+    /// returning whether it was bound at all
+    pub fn assign(&mut self, name: &str, value: Object) -> bool {
+        match self.store.get_mut(name) {
+            Some(bound) => {
+                *bound = value;
+                true
+            }
+            None => match &self.outer {
+                Some(outer) => outer.borrow_mut().assign(name, value),
+                None => false,
+            },
+        }
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Object> {
+        self.store.values()
+    }
+
+    pub fn outer(&self) -> Option<&Env> {
+        self.outer.as_ref()
+    }
+
+    /// drop every binding, which is how the garbage collector breaks reference cycles
+    pub fn clear(&mut self) {
+        self.store.clear();
+    }
+
+    /// an estimate of the memory taken up by the environment and its bindings
+    pub fn size(&self) -> usize {
+        let bindings: usize = self
+            .store
+            .keys()
+            .map(|name| size_of::<(String, Object)>() + name.len())
+            .sum();
+        size_of::<Environment>() + bindings
+    }
+}
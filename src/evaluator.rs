@@ -0,0 +1,1304 @@
+use crate::ast::{
+    ArrayLiteral, ArrayPattern, AssignStatement, BlockStatement, Boolean, BreakStatement,
+    CallExpression, ContinueStatement, Expression, ExpressionStatement, ForStatement,
+    FunctionLiteral, HashLiteral, HashPattern, Identifier, IfExpression, ImplStatement,
+    IndexExpression, InfixExpression, IntegerLiteral, LetStatement, LiteralPattern, MacroLiteral,
+    MatchExpression, MemberExpression, Pattern, PrefixExpression, Program, ReturnStatement,
+    Statement, StringLiteral, StructLiteral, StructStatement, WhileStatement, WildcardPattern,
+};
+use crate::builtins::lookup_builtin;
+use crate::environment::{Env, Environment};
+use crate::gc::{self, Root};
+use crate::limits;
+use crate::macro_expansion;
+use crate::object::{
+    BoundMethod, Function, HashKey, HashPair, HostFunction, Instance, Object, Signature, Struct,
+};
+use crate::token::Span;
+use crate::trace::{self, Frame};
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// evaluate the statements of the program one after another in the given environment.
+/// the evaluation stops at the first `return` statement or runtime error
+pub fn eval_program(program: &Program, env: &Env) -> Object {
+    limits::start_run();
+    trace::clear();
+    let mut result = Object::Null;
+    for stat in &program.statements {
+        result = eval_statement(stat.as_ref(), env);
+        match result {
+            Object::ReturnValue(value) => return *value,
+            Object::Error(_) => return result,
+            _ => {}
+        }
+    }
+    result
+}
+
+/// unlike `eval_program`, the `ReturnValue` is not unwrapped
+/// so that it stops the evaluation of the enclosing blocks as well,
+/// `Break` and `Continue` stop them up to the enclosing loop
+pub fn eval_block_statement(block: &BlockStatement, env: &Env) -> Object {
+    if let Err(err) = limits::enter_nesting() {
+        return err.into();
+    }
+    let result = limits::grow_stack(|| eval_nested_block_statement(block, env));
+    limits::exit_nesting();
+    result
+}
+
+fn eval_nested_block_statement(block: &BlockStatement, env: &Env) -> Object {
+    let mut result = Object::Null;
+    for stat in &block.statements {
+        result = eval_statement(stat.as_ref(), env);
+        if matches!(
+            result,
+            Object::ReturnValue(_) | Object::Error(_) | Object::Break | Object::Continue
+        ) {
+            return result;
+        }
+    }
+    result
+}
+
+fn eval_statement(stat: &dyn Statement, env: &Env) -> Object {
+    if let Err(err) = limits::step() {
+        return err.into();
+    }
+    let any = stat.as_any();
+    if let Some(stat) = any.downcast_ref::<ExpressionStatement>() {
+        match &stat.expression {
+            None => Object::Null,
+            Some(expression) => eval_expression(expression.as_ref(), env),
+        }
+    } else if let Some(stat) = any.downcast_ref::<LetStatement>() {
+        let value = match &stat.value {
+            None => Object::Null,
+            Some(expression) => match expression.as_any().downcast_ref::<FunctionLiteral>() {
+                // functions remember the name they are bound to for stack traces
+                Some(literal) => {
+                    let name = stat.name().map(|name| name.value.as_str());
+                    eval_function_literal(literal, env, name)
+                }
+                None => eval_expression(expression.as_ref(), env),
+            },
+        };
+        if value.stops_evaluation() {
+            return value;
+        }
+        match destructure(stat.pattern.as_ref(), &value, env) {
+            Ok(()) => Object::Null,
+            Err(err) => err,
+        }
+    } else if let Some(stat) = any.downcast_ref::<ReturnStatement>() {
+        let value = match &stat.return_value {
+            None => Object::Null,
+            Some(expression) => eval_expression(expression.as_ref(), env),
+        };
+        if value.stops_evaluation() {
+            return value;
+        }
+        Object::ReturnValue(Box::new(value))
+    } else if let Some(block) = any.downcast_ref::<BlockStatement>() {
+        eval_block_statement(block, env)
+    } else if let Some(stat) = any.downcast_ref::<AssignStatement>() {
+        eval_assign_statement(stat, env)
+    } else if let Some(stat) = any.downcast_ref::<WhileStatement>() {
+        eval_while_statement(stat, env)
+    } else if let Some(stat) = any.downcast_ref::<ForStatement>() {
+        eval_for_statement(stat, env)
+    } else if let Some(stat) = any.downcast_ref::<StructStatement>() {
+        let fields = stat
+            .fields
+            .iter()
+            .map(|field| field.value.clone())
+            .collect();
+        let definition = new_struct(&stat.name.value, fields);
+        if definition.is_error() {
+            return definition;
+        }
+        env.borrow_mut().set(&stat.name.value, definition);
+        Object::Null
+    } else if let Some(stat) = any.downcast_ref::<ImplStatement>() {
+        let definition = eval_identifier(&stat.name, env);
+        if definition.is_error() {
+            return definition;
+        }
+        let methods = stat
+            .methods
+            .iter()
+            .map(|(name, literal)| {
+                let qualified = format!("{}.{}", stat.name.value, name.value);
+                let method = eval_function_literal(literal, env, Some(&qualified));
+                (name.value.clone(), method)
+            })
+            .collect();
+        add_methods(definition, methods)
+    } else if any.is::<BreakStatement>() {
+        Object::Break
+    } else if any.is::<ContinueStatement>() {
+        Object::Continue
+    } else {
+        Object::Error(format!("unknown statement: {}", stat.format()))
+    }
+}
+
+pub fn eval_expression(expression: &dyn Expression, env: &Env) -> Object {
+    if let Err(err) = limits::enter_nesting() {
+        return err.into();
+    }
+    let result = limits::grow_stack(|| eval_nested_expression(expression, env));
+    limits::exit_nesting();
+    result
+}
+
+fn eval_nested_expression(expression: &dyn Expression, env: &Env) -> Object {
+    if let Err(err) = limits::step() {
+        return err.into();
+    }
+    let any = expression.as_any();
+    if let Some(literal) = any.downcast_ref::<IntegerLiteral>() {
+        match i64::try_from(literal.value) {
+            Ok(value) => Object::Integer(value),
+            Err(_) => Object::Error(format!("integer literal out of range: {}", literal.value)),
+        }
+    } else if let Some(boolean) = any.downcast_ref::<Boolean>() {
+        Object::Boolean(boolean.value)
+    } else if let Some(literal) = any.downcast_ref::<StringLiteral>() {
+        Object::String(literal.value.as_str().into())
+    } else if let Some(prefix) = any.downcast_ref::<PrefixExpression>() {
+        let right = eval_expression(prefix.right.as_ref(), env);
+        if right.stops_evaluation() {
+            return right;
+        }
+        eval_prefix_expression(&prefix.operator, right)
+    } else if let Some(infix) = any.downcast_ref::<InfixExpression>() {
+        let left = eval_expression(infix.left.as_ref(), env);
+        if left.stops_evaluation() {
+            return left;
+        }
+        let right = eval_expression(infix.right.as_ref(), env);
+        if right.stops_evaluation() {
+            return right;
+        }
+        eval_infix_expression(&infix.operator, left, right)
+    } else if let Some(if_expression) = any.downcast_ref::<IfExpression>() {
+        eval_if_expression(if_expression, env)
+    } else if let Some(ident) = any.downcast_ref::<Identifier>() {
+        eval_identifier(ident, env)
+    } else if let Some(literal) = any.downcast_ref::<FunctionLiteral>() {
+        eval_function_literal(literal, env, None)
+    } else if let Some(call) = any.downcast_ref::<CallExpression>() {
+        if is_quote_call(call) {
+            return macro_expansion::quote(call, env);
+        }
+        let function = eval_expression(call.function.as_ref(), env);
+        if function.stops_evaluation() {
+            return function;
+        }
+        let args = match eval_expressions(&call.arguments, env) {
+            Ok(args) => args,
+            Err(err) => return err,
+        };
+        let mut named = Vec::with_capacity(call.named_arguments.len());
+        for (name, value) in &call.named_arguments {
+            let value = eval_expression(value.as_ref(), env);
+            if value.stops_evaluation() {
+                return value;
+            }
+            named.push((name.value.clone(), value));
+        }
+        apply_function_at(function, args, named, call.token.span)
+    } else if let Some(array) = any.downcast_ref::<ArrayLiteral>() {
+        match eval_expressions(&array.elements, env) {
+            Ok(elements) => new_array(elements),
+            Err(err) => err,
+        }
+    } else if let Some(index) = any.downcast_ref::<IndexExpression>() {
+        let left = eval_expression(index.left.as_ref(), env);
+        if left.stops_evaluation() {
+            return left;
+        }
+        let index = eval_expression(index.index.as_ref(), env);
+        if index.stops_evaluation() {
+            return index;
+        }
+        eval_index_expression(left, index)
+    } else if let Some(hash) = any.downcast_ref::<HashLiteral>() {
+        eval_hash_literal(hash, env)
+    } else if let Some(literal) = any.downcast_ref::<StructLiteral>() {
+        let definition = eval_identifier(&literal.name, env);
+        if definition.is_error() {
+            return definition;
+        }
+        let mut fields = Vec::with_capacity(literal.fields.len());
+        for (name, value) in &literal.fields {
+            let value = eval_expression(value.as_ref(), env);
+            if value.stops_evaluation() {
+                return value;
+            }
+            fields.push((name.value.clone(), value));
+        }
+        new_instance(definition, fields)
+    } else if let Some(member) = any.downcast_ref::<MemberExpression>() {
+        let object = eval_expression(member.object.as_ref(), env);
+        if object.stops_evaluation() {
+            return object;
+        }
+        eval_member_expression(object, &member.member.value)
+    } else if let Some(expression) = any.downcast_ref::<MatchExpression>() {
+        eval_match_expression(expression, env)
+    } else if any.is::<MacroLiteral>() {
+        Object::Error(MACRO_LITERAL_ERROR.to_string())
+    } else {
+        Object::Error(format!("unknown expression: {}", expression.format()))
+    }
+}
+
+/// the error of the macro literals left after `define_macros`,
+/// which only takes the ones bound by a `let` statement at the top level
+pub const MACRO_LITERAL_ERROR: &str = "macros must be defined by a let statement at the top level";
+
+/// whether the call is `quote(...)`, which returns its argument as code instead of evaluating it
+pub fn is_quote_call(call: &CallExpression) -> bool {
+    matches!(
+        call.function.as_any().downcast_ref::<Identifier>(),
+        Some(ident) if ident.value == "quote"
+    )
+}
+
+fn eval_function_literal(literal: &FunctionLiteral, env: &Env, name: Option<&str>) -> Object {
+    Object::Function(Rc::new(Function {
+        name: name.map(str::to_string),
+        parameters: literal.parameters.clone(),
+        rest: literal.rest.clone(),
+        signature: Signature::new(literal),
+        body: literal.body.clone(),
+        env: env.clone(),
+    }))
+}
+
+/// evaluate the expressions from left to right, stopping at the first error
+fn eval_expressions(expressions: &[Box<dyn Expression>], env: &Env) -> Result<Vec<Object>, Object> {
+    let mut result = Vec::with_capacity(expressions.len());
+    for expression in expressions {
+        let evaluated = eval_expression(expression.as_ref(), env);
+        if evaluated.stops_evaluation() {
+            return Err(evaluated);
+        }
+        result.push(evaluated);
+    }
+    Ok(result)
+}
+
+pub fn eval_prefix_expression(operator: &str, right: Object) -> Object {
+    match operator {
+        "!" => Object::Boolean(!right.is_truthy()),
+        "-" => match right {
+            Object::Integer(value) => match value.checked_neg() {
+                Some(value) => Object::Integer(value),
+                None => Object::Error("integer overflow".to_string()),
+            },
+            right => Object::Error(format!("unknown operator: -{}", right.object_type())),
+        },
+        "~" => match right {
+            Object::Integer(value) => Object::Integer(!value),
+            right => Object::Error(format!("unknown operator: ~{}", right.object_type())),
+        },
+        _ => Object::Error(format!(
+            "unknown operator: {}{}",
+            operator,
+            right.object_type()
+        )),
+    }
+}
+
+pub fn eval_infix_expression(operator: &str, left: Object, right: Object) -> Object {
+    match (&left, &right) {
+        (Object::Integer(left), Object::Integer(right)) => {
+            eval_integer_infix_expression(operator, *left, *right)
+        }
+        (Object::String(left), Object::String(right)) => {
+            eval_string_infix_expression(operator, left, right)
+        }
+        (Object::Boolean(left), Object::Boolean(right)) => match operator {
+            "==" => Object::Boolean(left == right),
+            "!=" => Object::Boolean(left != right),
+            _ => Object::Error(format!("unknown operator: BOOLEAN {} BOOLEAN", operator)),
+        },
+        _ if left.object_type() != right.object_type() => match operator {
+            "==" => Object::Boolean(false),
+            "!=" => Object::Boolean(true),
+            _ => Object::Error(format!(
+                "type mismatch: {} {} {}",
+                left.object_type(),
+                operator,
+                right.object_type()
+            )),
+        },
+        (Object::Null, Object::Null) if operator == "==" || operator == "!=" => {
+            Object::Boolean(operator == "==")
+        }
+        _ => Object::Error(format!(
+            "unknown operator: {} {} {}",
+            left.object_type(),
+            operator,
+            right.object_type()
+        )),
+    }
+}
+
+fn eval_integer_infix_expression(operator: &str, left: i64, right: i64) -> Object {
+    let result = match operator {
+        "+" => left.checked_add(right),
+        "-" => left.checked_sub(right),
+        "*" => left.checked_mul(right),
+        "/" => {
+            if right == 0 {
+                return Object::Error("division by zero".to_string());
+            }
+            left.checked_div(right)
+        }
+        "%" => {
+            if right == 0 {
+                return Object::Error("division by zero".to_string());
+            }
+            left.checked_rem(right)
+        }
+        "&" => Some(left & right),
+        "|" => Some(left | right),
+        "^" => Some(left ^ right),
+        "<<" | ">>" => {
+            if right < 0 {
+                return Object::Error("negative shift amount".to_string());
+            }
+            if right >= i64::BITS as i64 {
+                return Object::Error("shift amount too large".to_string());
+            }
+            Some(if operator == "<<" {
+                left << right
+            } else {
+                left >> right
+            })
+        }
+        "**" => {
+            if right < 0 {
+                return Object::Error("negative exponent".to_string());
+            }
+            match u32::try_from(right) {
+                Ok(exponent) => left.checked_pow(exponent),
+                // only the powers of 0, 1 and -1 fit for such an exponent
+                Err(_) => match left {
+                    0 | 1 => Some(left),
+                    -1 => Some(if right % 2 == 0 { 1 } else { -1 }),
+                    _ => None,
+                },
+            }
+        }
+        "<" => return Object::Boolean(left < right),
+        ">" => return Object::Boolean(left > right),
+        "==" => return Object::Boolean(left == right),
+        "!=" => return Object::Boolean(left != right),
+        _ => {
+            return Object::Error(format!("unknown operator: INTEGER {} INTEGER", operator));
+        }
+    };
+    match result {
+        Some(value) => Object::Integer(value),
+        None => Object::Error("integer overflow".to_string()),
+    }
+}
+
+fn eval_string_infix_expression(operator: &str, left: &str, right: &str) -> Object {
+    match operator {
+        "+" => match limits::allocate(left.len() + right.len()) {
+            Ok(()) => Object::String(format!("{}{}", left, right).into()),
+            Err(err) => err.into(),
+        },
+        "==" => Object::Boolean(left == right),
+        "!=" => Object::Boolean(left != right),
+        _ => Object::Error(format!("unknown operator: STRING {} STRING", operator)),
+    }
+}
+
+fn eval_if_expression(if_expression: &IfExpression, env: &Env) -> Object {
+    let condition = eval_expression(if_expression.condition.as_ref(), env);
+    if condition.stops_evaluation() {
+        return condition;
+    }
+
+    if condition.is_truthy() {
+        eval_block_statement(&if_expression.consequence, env)
+    } else if let Some(alternative) = &if_expression.alternative {
+        eval_block_statement(alternative, env)
+    } else {
+        Object::Null
+    }
+}
+
+/// the target is evaluated before the value: first the binding, then the indexes from left to right,
+/// looking up the collections they index, and for compound assignments the current value
+fn eval_assign_statement(stat: &AssignStatement, env: &Env) -> Object {
+    let mut indexes = Vec::new();
+    let mut target = stat.target.as_ref();
+    while let Some(index) = target.as_any().downcast_ref::<IndexExpression>() {
+        indexes.push(index.index.as_ref());
+        target = index.left.as_ref();
+    }
+    indexes.reverse();
+    let name = match target.as_any().downcast_ref::<Identifier>() {
+        Some(ident) => &ident.value,
+        None => return Object::Error(format!("cannot assign to {}", stat.target.format())),
+    };
+
+    let bound = env.borrow().get(name);
+    let mut collections = match bound {
+        Some(value) => vec![value],
+        None => return unbound_assignment_error(name),
+    };
+    let mut keys = Vec::with_capacity(indexes.len());
+    for (i, index) in indexes.iter().enumerate() {
+        let key = eval_expression(*index, env);
+        if key.stops_evaluation() {
+            return key;
+        }
+        // the last collection is indexed by the assignment itself
+        if i + 1 < indexes.len() {
+            let inner = eval_index_expression(collections[i].clone(), key.clone());
+            if inner.is_error() {
+                return inner;
+            }
+            collections.push(inner);
+        }
+        keys.push(key);
+    }
+
+    // `x += 1` adds 1 to the current value of `x`
+    let operator = stat.operator.strip_suffix('=').unwrap_or_default();
+    let current = match (operator, keys.last()) {
+        ("", _) => None,
+        (_, None) => collections.last().cloned(),
+        (_, Some(key)) => Some(eval_index_expression(
+            collections.last().unwrap().clone(),
+            key.clone(),
+        )),
+    };
+    if let Some(current @ Object::Error(_)) = current {
+        return current;
+    }
+
+    let mut value = eval_expression(stat.value.as_ref(), env);
+    if value.stops_evaluation() {
+        return value;
+    }
+    if let Some(current) = current {
+        value = eval_infix_expression(operator, current, value);
+        if value.is_error() {
+            return value;
+        }
+    }
+    // collections are values, the changed ones are copies put back in place from the inside out
+    for (collection, key) in collections.into_iter().zip(keys).rev() {
+        value = eval_index_assignment(collection, key, value);
+        if value.is_error() {
+            return value;
+        }
+    }
+    env.borrow_mut().assign(name, value);
+    Object::Null
+}
+
+/// only existing bindings can be assigned to, builtins can only be shadowed with `let`
+pub fn unbound_assignment_error(name: &str) -> Object {
+    match lookup_builtin(name) {
+        Some(_) => Object::Error(format!("cannot assign to builtin function: {}", name)),
+        None => Object::Error(format!("identifier not found: {}", name)),
+    }
+}
+
+fn eval_while_statement(stat: &WhileStatement, env: &Env) -> Object {
+    loop {
+        let condition = eval_expression(stat.condition.as_ref(), env);
+        if condition.stops_evaluation() {
+            return condition;
+        }
+        if !condition.is_truthy() {
+            return Object::Null;
+        }
+        match eval_block_statement(&stat.body, env) {
+            Object::Break => return Object::Null,
+            result @ (Object::ReturnValue(_) | Object::Error(_)) => return result,
+            _ => {}
+        }
+    }
+}
+
+/// the loop variable is bound in the environment of the loop, like a `let` statement would
+fn eval_for_statement(stat: &ForStatement, env: &Env) -> Object {
+    let iterable = eval_expression(stat.iterable.as_ref(), env);
+    if iterable.stops_evaluation() {
+        return iterable;
+    }
+    let items = eval_iterable(iterable);
+    if items.is_error() {
+        return items;
+    }
+    let mut index = 0;
+    while let Some(item) = iteration_item(&items, index) {
+        index += 1;
+        env.borrow_mut().set(&stat.variable.value, item);
+        match eval_block_statement(&stat.body, env) {
+            Object::Break => break,
+            result @ (Object::ReturnValue(_) | Object::Error(_)) => return result,
+            _ => {}
+        }
+    }
+    Object::Null
+}
+
+/// the values a `for` loop goes through collected into an array: the elements of an array,
+/// the characters of a string or the keys of a hash. a range is iterated over as it is
+pub fn eval_iterable(iterable: Object) -> Object {
+    let items = match &iterable {
+        Object::Array(_) | Object::Range(..) => return iterable,
+        Object::String(value) => value
+            .chars()
+            .map(|ch| Object::String(ch.to_string().into()))
+            .collect(),
+        Object::Hash(pairs) => pairs.values().map(|pair| pair.key.clone()).collect(),
+        other => return Object::Error(format!("not iterable: {}", other.object_type())),
+    };
+    new_array(items)
+}
+
+/// the item at the index of the array or range returned by `eval_iterable`,
+/// `None` once they are all iterated over
+pub fn iteration_item(items: &Object, index: i64) -> Option<Object> {
+    match items {
+        Object::Array(elements) => elements.get(usize::try_from(index).ok()?).cloned(),
+        Object::Range(start, end) => range_element(*start, *end, index).map(Object::Integer),
+        _ => None,
+    }
+}
+
+/// the integer at the index of the range, `None` if the index is out of it
+pub fn range_element(start: i64, end: i64, index: i64) -> Option<i64> {
+    if index < 0 {
+        return None;
+    }
+    start.checked_add(index).filter(|value| *value < end)
+}
+
+/// try the arms in order. the names of a pattern are bound in a scope of their own
+/// while the pattern and its guard are tried, and in the scope of the match expression
+/// once the arm is taken
+fn eval_match_expression(expression: &MatchExpression, env: &Env) -> Object {
+    let subject = eval_expression(expression.subject.as_ref(), env);
+    if subject.stops_evaluation() {
+        return subject;
+    }
+    for arm in &expression.arms {
+        let scope = Environment::new_enclosed(env.clone());
+        match match_pattern(arm.pattern.as_ref(), &subject, &scope) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(err) => return err,
+        }
+        if let Some(guard) = &arm.guard {
+            let guard = eval_expression(guard.as_ref(), &scope);
+            if guard.stops_evaluation() {
+                return guard;
+            }
+            if !guard.is_truthy() {
+                continue;
+            }
+        }
+        for (name, value) in scope.borrow().bindings() {
+            env.borrow_mut().set(&name, value);
+        }
+        return eval_block_statement(&arm.body, env);
+    }
+    Object::Error(no_match_error(&subject))
+}
+
+/// whether the value matches the pattern, binding its names from left to right.
+/// fails with the error of a literal which can't be evaluated
+fn match_pattern(pattern: &dyn Pattern, value: &Object, env: &Env) -> Result<bool, Object> {
+    let any = pattern.as_any();
+    if any.is::<WildcardPattern>() {
+        Ok(true)
+    } else if let Some(ident) = any.downcast_ref::<Identifier>() {
+        env.borrow_mut().set(&ident.value, value.clone());
+        Ok(true)
+    } else if let Some(literal) = any.downcast_ref::<LiteralPattern>() {
+        let literal = eval_expression(literal.value.as_ref(), env);
+        if literal.stops_evaluation() {
+            return Err(literal);
+        }
+        Ok(literal_matches(&literal, value))
+    } else if let Some(array) = any.downcast_ref::<ArrayPattern>() {
+        let Object::Array(elements) = value else {
+            return Ok(false);
+        };
+        if !array_length_matches(elements.len(), array.elements.len(), array.rest.is_some()) {
+            return Ok(false);
+        }
+        for (pattern, element) in array.elements.iter().zip(elements.iter()) {
+            if !match_pattern(pattern.as_ref(), element, env)? {
+                return Ok(false);
+            }
+        }
+        if let Some(rest) = &array.rest {
+            let rest_value = new_array(elements[array.elements.len()..].to_vec());
+            if rest_value.is_error() {
+                return Err(rest_value);
+            }
+            env.borrow_mut().set(&rest.value, rest_value);
+        }
+        Ok(true)
+    } else if let Some(hash) = any.downcast_ref::<HashPattern>() {
+        let Object::Hash(pairs) = value else {
+            return Ok(false);
+        };
+        for (key, pattern) in &hash.pairs {
+            let key = eval_expression(key.as_ref(), env);
+            if key.stops_evaluation() {
+                return Err(key);
+            }
+            let pair = key.hash_key().and_then(|key| pairs.get(&key));
+            match pair {
+                Some(pair) if match_pattern(pattern.as_ref(), &pair.value, env)? => {}
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    } else {
+        Err(Object::Error(format!(
+            "unknown pattern: {}",
+            pattern.format()
+        )))
+    }
+}
+
+/// literal patterns only match values of their own type
+pub fn literal_matches(literal: &Object, value: &Object) -> bool {
+    match (literal, value) {
+        (Object::Integer(a), Object::Integer(b)) => a == b,
+        (Object::Boolean(a), Object::Boolean(b)) => a == b,
+        (Object::String(a), Object::String(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// whether an array pattern of `patterns` elements, with a rest or not,
+/// matches an array of `length` elements
+pub fn array_length_matches(length: usize, patterns: usize, has_rest: bool) -> bool {
+    if has_rest {
+        length >= patterns
+    } else {
+        length == patterns
+    }
+}
+
+pub fn no_match_error(value: &Object) -> String {
+    format!("no match arm for value: {}", value.inspect())
+}
+
+/// bind the names of the pattern of a `let` or a parameter to the parts of the value,
+/// failing with an error naming the pattern if the value doesn't have its shape
+pub fn destructure(pattern: &dyn Pattern, value: &Object, env: &Env) -> Result<(), Object> {
+    match match_pattern(pattern, value, env)? {
+        true => Ok(()),
+        false => Err(Object::Error(destructure_error(&pattern.format(), value))),
+    }
+}
+
+pub fn destructure_error(pattern: &str, value: &Object) -> String {
+    format!("pattern {} does not match {}", pattern, value.inspect())
+}
+
+fn eval_identifier(ident: &Identifier, env: &Env) -> Object {
+    if let Some(value) = env.borrow().get(&ident.value) {
+        return value;
+    }
+    match lookup_builtin(&ident.value) {
+        Some(builtin) => Object::Builtin(builtin),
+        None => Object::Error(format!("identifier not found: {}", ident.value)),
+    }
+}
+
+/// call the function with the arguments, from outside of the program
+pub fn apply_function(function: Object, args: Vec<Object>) -> Object {
+    apply_function_at(function, args, Vec::new(), Span::default())
+}
+
+/// call the function from the call site, which is recorded in the stack trace
+/// if a runtime error happens during the call
+fn apply_function_at(
+    function: Object,
+    args: Vec<Object>,
+    named: Vec<(String, Object)>,
+    call_site: Span,
+) -> Object {
+    if !named.is_empty() && !matches!(function, Object::Function(_) | Object::BoundMethod(_)) {
+        return Object::Error(NAMED_ARGUMENTS_ERROR.to_string());
+    }
+    match function {
+        Object::BoundMethod(method) => {
+            let mut args = args;
+            args.insert(0, Object::Instance(method.receiver.clone()));
+            apply_function_at(method.method.clone(), args, named, call_site)
+        }
+        Object::Function(function) => {
+            let values = match bind_arguments(&function.signature, args, named) {
+                Ok(values) => values,
+                Err(message) => return Object::Error(message),
+            };
+
+            if let Err(err) = limits::enter_call() {
+                return err.into();
+            }
+            let env = Environment::new_enclosed(function.env.clone());
+            let bound = bind_parameters(&function, values, &env);
+            if gc::should_collect() {
+                gc::collect(&[Root::Env(&env)]);
+            }
+
+            let result = match bound {
+                Ok(()) => match limits::check_heap(0) {
+                    Ok(()) => eval_block_statement(&function.body, &env),
+                    Err(err) => err.into(),
+                },
+                Err(err) => err,
+            };
+            limits::exit_call();
+            if result.is_error() {
+                trace::push(Frame {
+                    function: function
+                        .name
+                        .as_deref()
+                        .unwrap_or("<anonymous>")
+                        .to_string(),
+                    line: call_site.line,
+                    column: call_site.column,
+                });
+            }
+            match result {
+                Object::ReturnValue(value) => *value,
+                result => result,
+            }
+        }
+        Object::Builtin(builtin) => (builtin.func)(args),
+        Object::HostFunction(function) => call_host_function(&function, args),
+        other => Object::Error(format!("not a function: {}", other.object_type())),
+    }
+}
+
+pub const NAMED_ARGUMENTS_ERROR: &str = "named arguments are only supported by Lingo functions";
+
+/// bind the values of the parameters in the environment of the call, from the first to the last.
+/// a parameter the call left out gets its default value, which can use the parameters before it
+fn bind_parameters(
+    function: &Function,
+    values: Vec<Option<Object>>,
+    env: &Env,
+) -> Result<(), Object> {
+    let mut values = values.into_iter();
+    for param in function.parameters.iter() {
+        let value = match (values.next().flatten(), &param.default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => {
+                let value = eval_expression(default.as_ref(), env);
+                if value.stops_evaluation() {
+                    return Err(value);
+                }
+                value
+            }
+            (None, None) => unreachable!("bind_arguments gives every required parameter a value"),
+        };
+        destructure(param.pattern.as_ref(), &value, env)?;
+    }
+    if let (Some(rest), Some(Some(value))) = (&function.rest, values.next()) {
+        env.borrow_mut().set(&rest.value, value);
+    }
+    Ok(())
+}
+
+/// match the arguments of a call with the parameters of the function, in both back ends.
+/// the values are in the order of the parameters, `None` for those the call left out,
+/// followed by the array of the remaining arguments if the function has a rest parameter
+pub fn bind_arguments(
+    signature: &Signature,
+    mut args: Vec<Object>,
+    named: Vec<(String, Object)>,
+) -> Result<Vec<Option<Object>>, String> {
+    // a misspelled name is a better explanation than the arity of the function
+    if let Some((name, _)) = named
+        .iter()
+        .find(|(name, _)| !signature.names.contains(name))
+    {
+        return Err(format!("unknown named argument {}", name));
+    }
+    let given = args.len() + named.len();
+    let count = signature.names.len();
+    let remaining = args.split_off(args.len().min(count));
+    if given < signature.required || (!remaining.is_empty() && !signature.rest) {
+        return Err(arity_error(signature, given));
+    }
+
+    let mut values: Vec<Option<Object>> = args.into_iter().map(Some).collect();
+    values.resize(count, None);
+    for (name, value) in named {
+        let index = signature.names.iter().position(|param| *param == name);
+        let index = index.expect("the names of the named arguments were checked");
+        if values[index].is_some() {
+            return Err(format!("parameter {} got more than one argument", name));
+        }
+        values[index] = Some(value);
+    }
+    if let Some(index) = values[..signature.required]
+        .iter()
+        .position(Option::is_none)
+    {
+        return Err(format!(
+            "missing argument for parameter {}",
+            signature.names[index]
+        ));
+    }
+    if signature.rest {
+        match new_array(remaining) {
+            Object::Error(message) => return Err(message),
+            rest => values.push(Some(rest)),
+        }
+    }
+    Ok(values)
+}
+
+/// the number of arguments the function wants, and where it is defined if that is known
+fn arity_error(signature: &Signature, given: usize) -> String {
+    let count = signature.names.len();
+    let want = if signature.rest {
+        format!("at least {}", signature.required)
+    } else if signature.required < count {
+        format!("{} to {}", signature.required, count)
+    } else {
+        count.to_string()
+    };
+    let mut message = format!("wrong number of arguments: want={}, got={}", want, given);
+    if signature.line > 0 {
+        message.push_str(&format!(
+            ", function defined at line {}, column {}",
+            signature.line, signature.column
+        ));
+    }
+    message
+}
+
+pub fn call_host_function(function: &HostFunction, args: Vec<Object>) -> Object {
+    match (function.func)(args) {
+        Ok(value) => value,
+        Err(message) => Object::Error(format!("{}: {}", function.name, message)),
+    }
+}
+
+pub fn eval_index_expression(left: Object, index: Object) -> Object {
+    match (&left, &index) {
+        (Object::Array(elements), Object::Integer(i)) => {
+            if *i < 0 {
+                return Object::Null;
+            }
+            elements.get(*i as usize).cloned().unwrap_or(Object::Null)
+        }
+        (Object::Range(start, end), Object::Integer(i)) => range_element(*start, *end, *i)
+            .map(Object::Integer)
+            .unwrap_or(Object::Null),
+        (Object::Hash(pairs), _) => match index.hash_key() {
+            Some(key) => pairs
+                .get(&key)
+                .map(|pair| pair.value.clone())
+                .unwrap_or(Object::Null),
+            None => Object::Error(format!("unusable as hash key: {}", index.object_type())),
+        },
+        _ => Object::Error(format!(
+            "index operator not supported: {}",
+            left.object_type()
+        )),
+    }
+}
+
+/// the collection with the element at the index replaced. the collection is copied,
+/// leaving alone the other bindings holding it
+pub fn eval_index_assignment(left: Object, index: Object, value: Object) -> Object {
+    match (&left, &index) {
+        (Object::Array(elements), Object::Integer(i)) => {
+            if *i < 0 || *i as usize >= elements.len() {
+                return Object::Error(format!("index out of range: {}", i));
+            }
+            let mut elements = elements.as_ref().clone();
+            elements[*i as usize] = value;
+            new_array(elements)
+        }
+        (Object::Hash(pairs), _) => match index.hash_key() {
+            Some(key) => {
+                let mut pairs = pairs.as_ref().clone();
+                pairs.insert(key, HashPair { key: index, value });
+                new_hash(pairs)
+            }
+            None => Object::Error(format!("unusable as hash key: {}", index.object_type())),
+        },
+        _ => Object::Error(format!(
+            "index assignment not supported: {}",
+            left.object_type()
+        )),
+    }
+}
+
+/// every key and value is evaluated, from left to right, before the keys are checked
+fn eval_hash_literal(hash: &HashLiteral, env: &Env) -> Object {
+    let mut evaluated = Vec::with_capacity(hash.pairs.len());
+    for (key_expression, value_expression) in &hash.pairs {
+        let key = eval_expression(key_expression.as_ref(), env);
+        if key.stops_evaluation() {
+            return key;
+        }
+        let value = eval_expression(value_expression.as_ref(), env);
+        if value.stops_evaluation() {
+            return value;
+        }
+        evaluated.push((key, value));
+    }
+    let mut pairs = IndexMap::new();
+    for (key, value) in evaluated {
+        let hash_key = match key.hash_key() {
+            Some(hash_key) => hash_key,
+            None => return Object::Error(format!("unusable as hash key: {}", key.object_type())),
+        };
+        pairs.insert(hash_key, HashPair { key, value });
+    }
+    new_hash(pairs)
+}
+
+/// create an array, counting it against the heap size limit
+pub fn new_array(elements: Vec<Object>) -> Object {
+    let array = Object::Array(Rc::new(elements));
+    match limits::allocate(gc::value_size(&array)) {
+        Ok(()) => array,
+        Err(err) => err.into(),
+    }
+}
+
+/// create a hash, counting it against the heap size limit
+pub fn new_hash(pairs: IndexMap<HashKey, HashPair>) -> Object {
+    let hash = Object::Hash(Rc::new(pairs));
+    match limits::allocate(gc::value_size(&hash)) {
+        Ok(()) => hash,
+        Err(err) => err.into(),
+    }
+}
+
+/// create a struct with the fields
+pub fn new_struct(name: &str, fields: Vec<String>) -> Object {
+    Object::Struct(Rc::new(Struct {
+        name: name.to_string(),
+        fields,
+        methods: RefCell::new(IndexMap::new()),
+    }))
+}
+
+/// create an instance of the struct, every one of its fields must be given a value
+pub fn new_instance(definition: Object, fields: Vec<(String, Object)>) -> Object {
+    let definition = match definition {
+        Object::Struct(definition) => definition,
+        other => return Object::Error(format!("not a struct: {}", other.object_type())),
+    };
+    let mut values = vec![None; definition.fields.len()];
+    for (name, value) in fields {
+        match definition.fields.iter().position(|field| *field == name) {
+            Some(index) => values[index] = Some(value),
+            None => return Object::Error(unknown_field_error(&definition, &name)),
+        }
+    }
+    let mut missing = definition.fields.iter().zip(&values);
+    if let Some((field, _)) = missing.find(|(_, value)| value.is_none()) {
+        return Object::Error(format!(
+            "missing field {} of struct {}",
+            field, definition.name
+        ));
+    }
+    let instance = Object::Instance(Rc::new(Instance {
+        values: values.into_iter().flatten().collect(),
+        definition,
+    }));
+    match limits::allocate(gc::value_size(&instance)) {
+        Ok(()) => instance,
+        Err(err) => err.into(),
+    }
+}
+
+/// read a field of the instance, or else one of the methods of its struct
+/// bound to the instance. a method read from the struct itself is not bound
+pub fn eval_member_expression(object: Object, name: &str) -> Object {
+    match object {
+        Object::Instance(instance) => {
+            let definition = &instance.definition;
+            if let Some(index) = definition.fields.iter().position(|field| field == name) {
+                return instance.values[index].clone();
+            }
+            let method = definition.methods.borrow().get(name).cloned();
+            match method {
+                Some(method) => Object::BoundMethod(Rc::new(BoundMethod {
+                    receiver: instance.clone(),
+                    name: name.to_string(),
+                    method,
+                })),
+                None => Object::Error(unknown_field_error(definition, name)),
+            }
+        }
+        Object::Struct(definition) => match definition.methods.borrow().get(name) {
+            Some(method) => method.clone(),
+            None => Object::Error(format!("struct {} has no method {}", definition.name, name)),
+        },
+        other => Object::Error(format!(
+            "cannot access field {} of {}",
+            name,
+            other.object_type()
+        )),
+    }
+}
+
+fn unknown_field_error(definition: &Struct, name: &str) -> String {
+    format!("struct {} has no field {}", definition.name, name)
+}
+
+/// add the methods of an `impl` statement to the struct
+pub fn add_methods(definition: Object, methods: Vec<(String, Object)>) -> Object {
+    match definition {
+        Object::Struct(definition) => {
+            definition.methods.borrow_mut().extend(methods);
+            Object::Null
+        }
+        other => Object::Error(format!("not a struct: {}", other.object_type())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::environment::Environment;
+    use crate::evaluator::eval_program;
+    use crate::lexer::Lexer;
+    use crate::object::Object;
+    use crate::parser::Parser;
+
+    fn test_eval(input: &str) -> Object {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program().unwrap();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        eval_program(&program, &Environment::new())
+    }
+
+    fn assert_eval(input: &str, expected: &str) {
+        assert_eq!(test_eval(input).inspect(), expected, "input: {}", input);
+    }
+
+    #[test]
+    fn test_eval_integer_expression() {
+        let tests = vec![
+            ("5", "5"),
+            ("-10", "-10"),
+            ("--5", "5"),
+            ("5 + 5 + 5 + 5 - 10", "10"),
+            ("2 * 2 * 2 * 2 * 2", "32"),
+            ("-50 + 100 + -50", "0"),
+            ("20 + 2 * -10", "0"),
+            ("50 / 2 * 2 + 10", "60"),
+            ("3 * (3 * 3) + 10", "37"),
+            ("(5 + 10 * 2 + 15 / 3) * 2 + -10", "50"),
+            ("7 % 3 * 2", "2"),
+            ("-7 % 3", "-1"),
+            ("2 ** 3 ** 2", "512"),
+            ("2 * 3 ** 2", "18"),
+            ("-2 ** 2", "-4"),
+            ("(-2) ** 2", "4"),
+            ("5 ** 0", "1"),
+            ("6 & 3 | 8 ^ 1", "11"),
+            ("1 << 4 >> 2", "4"),
+            ("-16 >> 2", "-4"),
+            ("~5", "-6"),
+        ];
+        for (input, expected) in tests {
+            assert_eval(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_eval_boolean_expression() {
+        let tests = vec![
+            ("true", "true"),
+            ("1 < 2", "true"),
+            ("1 > 2", "false"),
+            ("1 == 1", "true"),
+            ("1 != 1", "false"),
+            ("true == false", "false"),
+            ("(1 < 2) == true", "true"),
+            ("!true", "false"),
+            ("!!5", "true"),
+            ("\"a\" == \"a\"", "true"),
+        ];
+        for (input, expected) in tests {
+            assert_eval(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_if_else_expressions() {
+        let tests = vec![
+            ("if (true) { 10 }", "10"),
+            ("if (false) { 10 }", "null"),
+            ("if (1) { 10 }", "10"),
+            ("if (1 > 2) { 10 } else { 20 }", "20"),
+        ];
+        for (input, expected) in tests {
+            assert_eval(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_return_statements() {
+        let tests = vec![
+            ("return 10; 9;", "10"),
+            ("9; return 2 * 5; 9;", "10"),
+            ("if (10 > 1) { if (10 > 1) { return 10; } return 1; }", "10"),
+        ];
+        for (input, expected) in tests {
+            assert_eval(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_error_handling() {
+        let tests = vec![
+            ("5 + true;", "type mismatch: INTEGER + BOOLEAN"),
+            ("-true", "unknown operator: -BOOLEAN"),
+            ("true + false;", "unknown operator: BOOLEAN + BOOLEAN"),
+            (
+                "if (10 > 1) { return true + false; }",
+                "unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            ("foobar", "identifier not found: foobar"),
+            ("\"Hello\" - \"World\"", "unknown operator: STRING - STRING"),
+            (
+                "{\"name\": \"Lingo\"}[fn(x) { x }];",
+                "unusable as hash key: FUNCTION",
+            ),
+            ("1 / 0", "division by zero"),
+            ("1 % 0", "division by zero"),
+            ("2 ** -1", "negative exponent"),
+            ("2 ** 63", "integer overflow"),
+            ("1 << -1", "negative shift amount"),
+            ("1 >> 64", "shift amount too large"),
+            ("~true", "unknown operator: ~BOOLEAN"),
+            ("true & false", "unknown operator: BOOLEAN & BOOLEAN"),
+            (
+                "fn(x) { x }()",
+                "wrong number of arguments: want=1, got=0, function defined at line 1, column 1",
+            ),
+            ("x = 1", "identifier not found: x"),
+            ("len = 1", "cannot assign to builtin function: len"),
+        ];
+        for (input, expected) in tests {
+            assert_eval(input, &format!("ERROR: {}", expected));
+        }
+    }
+
+    #[test]
+    fn test_let_statements() {
+        let tests = vec![
+            ("let a = 5; a;", "5"),
+            ("let a = 5 * 5; a;", "25"),
+            ("let a = 5; let b = a; let c = a + b + 5; c;", "15"),
+        ];
+        for (input, expected) in tests {
+            assert_eval(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_assignments() {
+        let tests = vec![
+            ("let a = 5; a = a * 2; a;", "10"),
+            ("let a = 5; a += 1; a -= 2; a *= 3; a /= 4; a;", "3"),
+            ("let len = 1; len = 2; len", "2"),
+            ("let a = [1, [2]]; a[1][0] = 3; a", "[1, [3]]"),
+        ];
+        for (input, expected) in tests {
+            assert_eval(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_functions_and_closures() {
+        let tests =
+            vec![
+            ("let identity = fn(x) { x; }; identity(5);", "5"),
+            ("let double = fn(x) { x * 2; }; double(5);", "10"),
+            ("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));", "20"),
+            ("fn(x) { x; }(5)", "5"),
+            (
+                "let newAdder = fn(x) { fn(y) { x + y }; }; let addTwo = newAdder(2); addTwo(2);",
+                "4",
+            ),
+            ("fn(x) { x }", "fn(x) {\nx\n}"),
+        ];
+        for (input, expected) in tests {
+            assert_eval(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_strings_arrays_and_hashes() {
+        let tests = vec![
+            ("\"Hello\" + \" \" + \"World!\"", "Hello World!"),
+            ("[1, 2 * 2, 3 + 3]", "[1, 4, 6]"),
+            ("let i = 0; [1][i];", "1"),
+            ("[1, 2, 3][3]", "null"),
+            ("[1, 2, 3][-1]", "null"),
+            (
+                "let two = \"two\"; {\"one\": 10 - 9, two: 1 + 1, 4: 4, true: 5}",
+                "{one: 1, two: 2, 4: 4, true: 5}",
+            ),
+            ("{\"foo\": 5}[\"foo\"]", "5"),
+            ("{\"foo\": 5}[\"bar\"]", "null"),
+            ("{}[\"foo\"]", "null"),
+        ];
+        for (input, expected) in tests {
+            assert_eval(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_builtin_functions() {
+        let tests = vec![
+            ("len(\"\")", "0"),
+            ("len(\"hello world\")", "11"),
+            (
+                "len(1)",
+                "ERROR: argument to `len` not supported, got INTEGER",
+            ),
+            (
+                "len(\"one\", \"two\")",
+                "ERROR: wrong number of arguments. got=2, want=1",
+            ),
+            ("len([1, 2, 3])", "3"),
+            ("first([1, 2, 3])", "1"),
+            ("last([1, 2, 3])", "3"),
+            ("rest([1, 2, 3])", "[2, 3]"),
+            ("rest([])", "null"),
+            ("push([], 1)", "[1]"),
+        ];
+        for (input, expected) in tests {
+            assert_eval(input, expected);
+        }
+    }
+}
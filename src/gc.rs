@@ -0,0 +1,503 @@
+use crate::environment::{Env, Environment};
+use crate::object::{HashKey, HashPair, Locals, Object};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
+use std::rc::{Rc, Weak};
+
+/// how many bytes can be allocated between two automatic collections by default
+pub const DEFAULT_THRESHOLD: usize = 1 << 20;
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap::default());
+}
+
+/// what the collector did so far on the current thread
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GcStats {
+    /// number of collections run, automatic or not
+    pub collections: usize,
+    /// environments and call locals reclaimed by the collections
+    pub objects_freed: usize,
+    /// estimated memory reclaimed by the collections
+    pub bytes_freed: usize,
+    /// estimated memory taken up by the environments, call locals and values still alive,
+    /// as of the last collection plus what was allocated since
+    pub live_bytes: usize,
+}
+
+/// values are reference counted, so the only memory which is never given back is that of
+/// reference cycles. every cycle goes through the bindings of an environment or of the locals
+/// of a VM call, for example a function bound in the environment it was defined in,
+/// which is why the heap only keeps track of those two
+struct Heap {
+    envs: Vec<Weak<RefCell<Environment>>>,
+    locals: Vec<Weak<Locals>>,
+    /// bytes allocated since the last collection
+    allocated: usize,
+    threshold: usize,
+    stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Heap {
+            envs: Vec::new(),
+            locals: Vec::new(),
+            allocated: 0,
+            threshold: DEFAULT_THRESHOLD,
+            stats: GcStats::default(),
+        }
+    }
+}
+
+/// a value the collector must keep alive
+pub enum Root<'a> {
+    Env(&'a Env),
+    Locals(&'a Rc<Locals>),
+    Value(&'a Object),
+}
+
+pub fn track_env(env: &Env) {
+    let size = env.borrow().size();
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.envs.push(Rc::downgrade(env));
+        heap.allocate(size);
+    });
+}
+
+pub fn track_locals(locals: &Rc<Locals>) {
+    let size = locals_size(locals);
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.locals.push(Rc::downgrade(locals));
+        heap.allocate(size);
+    });
+}
+
+/// record memory allocated for a value, see `value_size`
+pub fn allocate(bytes: usize) {
+    HEAP.with(|heap| heap.borrow_mut().allocate(bytes));
+}
+
+/// an estimate of the memory a value owns, leaving out the values it refers to
+pub fn value_size(value: &Object) -> usize {
+    match value {
+        Object::String(value) => value.len(),
+        Object::Array(elements) => elements.len() * size_of::<Object>(),
+        Object::Hash(pairs) => pairs.len() * size_of::<(HashKey, HashPair)>(),
+        Object::Instance(instance) => instance.values.len() * size_of::<Object>(),
+        Object::ReturnValue(value) => value_size(value),
+        _ => 0,
+    }
+}
+
+/// whether enough memory was allocated since the last collection to run another one
+pub fn should_collect() -> bool {
+    HEAP.with(|heap| {
+        let heap = heap.borrow();
+        heap.allocated >= heap.threshold
+    })
+}
+
+/// set how many bytes can be allocated between two automatic collections
+pub fn set_threshold(bytes: usize) {
+    HEAP.with(|heap| heap.borrow_mut().threshold = bytes);
+}
+
+pub fn stats() -> GcStats {
+    HEAP.with(|heap| heap.borrow().stats)
+}
+
+/// mark everything reachable from the roots and sweep the unreachable environments and locals,
+/// returning the number of bytes freed.
+///
+/// the interpreter may hold values the roots don't lead to, such as the arguments of a call
+/// being evaluated. such values are found by comparing the reference count of every object
+/// to the number of references the collector sees, any object with more references
+/// is held from outside the heap and is treated as a root as well
+pub fn collect(roots: &[Root]) -> usize {
+    let (envs, locals) = HEAP.with(|heap| {
+        let heap = heap.borrow();
+        let envs: Vec<Env> = heap.envs.iter().filter_map(Weak::upgrade).collect();
+        let locals: Vec<Rc<Locals>> = heap.locals.iter().filter_map(Weak::upgrade).collect();
+        (envs, locals)
+    });
+
+    let mut graph = Graph::default();
+    for env in &envs {
+        graph.discover(Node::Env(env.clone()));
+    }
+    for locals in &locals {
+        graph.discover(Node::Locals(locals.clone()));
+    }
+
+    // mark
+    let mut marked = HashSet::new();
+    let mut pending: Vec<usize> = graph
+        .nodes
+        .iter()
+        .filter(|(_, (node, internal))| {
+            // the graph and the lists above hold one reference to every node, or two for the
+            // tracked ones
+            let held = if matches!(node, Node::Env(_) | Node::Locals(_)) {
+                2
+            } else {
+                1
+            };
+            node.strong_count() > held + internal
+        })
+        .map(|(id, _)| *id)
+        .collect();
+    for root in roots {
+        match root {
+            Root::Env(env) => pending.push(Node::Env((*env).clone()).id()),
+            Root::Locals(locals) => pending.push(Node::Locals((*locals).clone()).id()),
+            Root::Value(value) => {
+                let mut children = Vec::new();
+                value_children(value, &mut children);
+                pending.extend(children.iter().map(Node::id));
+            }
+        }
+    }
+    while let Some(id) = pending.pop() {
+        if !marked.insert(id) {
+            continue;
+        }
+        if let Some((node, _)) = graph.nodes.get(&id) {
+            pending.extend(node.children().iter().map(Node::id));
+        }
+    }
+
+    let live_bytes: usize = marked
+        .iter()
+        .filter_map(|id| graph.nodes.get(id))
+        .map(|(node, _)| node.size())
+        .sum();
+
+    // sweep
+    let mut objects_freed = 0;
+    let mut bytes_freed = 0;
+    for env in &envs {
+        if !marked.contains(&(Rc::as_ptr(env) as usize)) {
+            objects_freed += 1;
+            bytes_freed += env.borrow().size();
+            env.borrow_mut().clear();
+        }
+    }
+    for locals in &locals {
+        if !marked.contains(&(Rc::as_ptr(locals) as usize)) {
+            objects_freed += 1;
+            bytes_freed += locals_size(locals);
+            locals.slots.borrow_mut().clear();
+        }
+    }
+    drop(graph);
+    drop(envs);
+    drop(locals);
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.envs.retain(|env| env.strong_count() > 0);
+        heap.locals.retain(|locals| locals.strong_count() > 0);
+
+        heap.allocated = 0;
+        heap.stats.collections += 1;
+        heap.stats.objects_freed += objects_freed;
+        heap.stats.bytes_freed += bytes_freed;
+        heap.stats.live_bytes = live_bytes;
+    });
+    bytes_freed
+}
+
+fn locals_size(locals: &Locals) -> usize {
+    size_of::<Locals>() + locals.slots.borrow().len() * size_of::<Option<Object>>()
+}
+
+impl Heap {
+    fn allocate(&mut self, bytes: usize) {
+        self.allocated += bytes;
+        self.stats.live_bytes += bytes;
+    }
+}
+
+/// an object which can hold references to other objects
+#[derive(Clone)]
+enum Node {
+    Env(Env),
+    Locals(Rc<Locals>),
+    Value(Object),
+}
+
+impl Node {
+    /// the address of the object, which identifies it in the graph
+    fn id(&self) -> usize {
+        match self {
+            Node::Env(env) => Rc::as_ptr(env) as usize,
+            Node::Locals(locals) => Rc::as_ptr(locals) as usize,
+            Node::Value(Object::Function(function)) => Rc::as_ptr(function) as usize,
+            Node::Value(Object::Closure(closure)) => Rc::as_ptr(closure) as usize,
+            Node::Value(Object::Array(elements)) => Rc::as_ptr(elements) as usize,
+            Node::Value(Object::Hash(pairs)) => Rc::as_ptr(pairs) as usize,
+            Node::Value(Object::Struct(definition)) => Rc::as_ptr(definition) as usize,
+            Node::Value(Object::Instance(instance)) => Rc::as_ptr(instance) as usize,
+            Node::Value(Object::BoundMethod(method)) => Rc::as_ptr(method) as usize,
+            Node::Value(_) => 0,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Env(env) => Rc::strong_count(env),
+            Node::Locals(locals) => Rc::strong_count(locals),
+            Node::Value(Object::Function(function)) => Rc::strong_count(function),
+            Node::Value(Object::Closure(closure)) => Rc::strong_count(closure),
+            Node::Value(Object::Array(elements)) => Rc::strong_count(elements),
+            Node::Value(Object::Hash(pairs)) => Rc::strong_count(pairs),
+            Node::Value(Object::Struct(definition)) => Rc::strong_count(definition),
+            Node::Value(Object::Instance(instance)) => Rc::strong_count(instance),
+            Node::Value(Object::BoundMethod(method)) => Rc::strong_count(method),
+            Node::Value(_) => 0,
+        }
+    }
+
+    /// an estimate of the memory the object owns, with the strings it holds.
+    /// the other objects it refers to are nodes of their own
+    fn size(&self) -> usize {
+        match self {
+            Node::Env(env) => {
+                let env = env.borrow();
+                let size = env.size() + string_bytes(env.values());
+                size
+            }
+            Node::Locals(locals) => {
+                let slots = locals.slots.borrow();
+                locals_size(locals) + string_bytes(slots.iter().flatten())
+            }
+            Node::Value(value @ Object::Array(elements)) => {
+                value_size(value) + string_bytes(elements.iter())
+            }
+            Node::Value(value @ Object::Hash(pairs)) => {
+                value_size(value)
+                    + string_bytes(pairs.values().flat_map(|pair| [&pair.key, &pair.value]))
+            }
+            Node::Value(value @ Object::Instance(instance)) => {
+                value_size(value) + string_bytes(instance.values.iter())
+            }
+            Node::Value(value) => size_of::<Object>() + value_size(value),
+        }
+    }
+
+    /// the objects this one holds a reference to, once per reference
+    fn children(&self) -> Vec<Node> {
+        let mut children = Vec::new();
+        match self {
+            Node::Env(env) => {
+                let env = env.borrow();
+                for value in env.values() {
+                    value_children(value, &mut children);
+                }
+                if let Some(outer) = env.outer() {
+                    children.push(Node::Env(outer.clone()));
+                }
+            }
+            Node::Locals(locals) => {
+                for value in locals.slots.borrow().iter().flatten() {
+                    value_children(value, &mut children);
+                }
+                if let Some(outer) = &locals.outer {
+                    children.push(Node::Locals(outer.clone()));
+                }
+            }
+            Node::Value(Object::Function(function)) => {
+                children.push(Node::Env(function.env.clone()));
+            }
+            Node::Value(Object::Closure(closure)) => {
+                if let Some(env) = &closure.env {
+                    children.push(Node::Locals(env.clone()));
+                }
+            }
+            Node::Value(Object::Array(elements)) => {
+                for element in elements.iter() {
+                    value_children(element, &mut children);
+                }
+            }
+            Node::Value(Object::Hash(pairs)) => {
+                for pair in pairs.values() {
+                    value_children(&pair.value, &mut children);
+                }
+            }
+            Node::Value(Object::Struct(definition)) => {
+                for method in definition.methods.borrow().values() {
+                    value_children(method, &mut children);
+                }
+            }
+            Node::Value(Object::Instance(instance)) => {
+                children.push(Node::Value(Object::Struct(instance.definition.clone())));
+                for value in &instance.values {
+                    value_children(value, &mut children);
+                }
+            }
+            Node::Value(Object::BoundMethod(method)) => {
+                children.push(Node::Value(Object::Instance(method.receiver.clone())));
+                value_children(&method.method, &mut children);
+            }
+            Node::Value(_) => {}
+        }
+        children
+    }
+}
+
+fn string_bytes<'a>(values: impl Iterator<Item = &'a Object>) -> usize {
+    values
+        .filter(|value| matches!(value, Object::String(_)))
+        .map(value_size)
+        .sum()
+}
+
+/// the objects directly referenced by a value, which is the value itself unless it is a scalar
+fn value_children(value: &Object, children: &mut Vec<Node>) {
+    match value {
+        Object::ReturnValue(value) => value_children(value, children),
+        Object::Function(_)
+        | Object::Closure(_)
+        | Object::Array(_)
+        | Object::Hash(_)
+        | Object::Struct(_)
+        | Object::Instance(_)
+        | Object::BoundMethod(_) => children.push(Node::Value(value.clone())),
+        _ => {}
+    }
+}
+
+/// every object reachable from the tracked ones, with the number of references to it
+/// coming from other objects of the graph
+#[derive(Default)]
+struct Graph {
+    nodes: HashMap<usize, (Node, usize)>,
+}
+
+impl Graph {
+    fn discover(&mut self, start: Node) {
+        if self.nodes.contains_key(&start.id()) {
+            return;
+        }
+        self.nodes.insert(start.id(), (start.clone(), 0));
+        let mut pending = vec![start];
+        while let Some(node) = pending.pop() {
+            for child in node.children() {
+                let id = child.id();
+                match self.nodes.get_mut(&id) {
+                    Some((_, internal)) => *internal += 1,
+                    None => {
+                        self.nodes.insert(id, (child.clone(), 1));
+                        pending.push(child);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::Compiler;
+    use crate::environment::Environment;
+    use crate::evaluator::eval_program;
+    use crate::gc::{collect, set_threshold, stats, Root};
+    use crate::lexer::Lexer;
+    use crate::object::Object;
+    use crate::parser::Parser;
+    use crate::vm::Vm;
+    use std::rc::Rc;
+
+    fn eval(input: &str) -> Object {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program().unwrap();
+        eval_program(&program, &Environment::new())
+    }
+
+    #[test]
+    fn test_cycles_are_reclaimed() {
+        let env = Environment::new();
+        let inner = Environment::new_enclosed(env.clone());
+        // a function bound in the environment it closes over keeps it alive
+        let function = {
+            let mut parser = Parser::new(Lexer::new("fn() { f }".to_string()));
+            let program = parser.parse_program().unwrap();
+            eval_program(&program, &inner)
+        };
+        inner.borrow_mut().set("f", function);
+        let weak = Rc::downgrade(&inner);
+        drop(inner);
+        assert!(
+            weak.upgrade().is_some(),
+            "the cycle leaks without the collector"
+        );
+
+        let before = stats();
+        let freed = collect(&[Root::Env(&env)]);
+        assert!(weak.upgrade().is_none());
+        assert!(freed > 0);
+        let after = stats();
+        assert_eq!(after.collections, before.collections + 1);
+        assert_eq!(after.objects_freed, before.objects_freed + 1);
+        assert_eq!(after.bytes_freed, before.bytes_freed + freed);
+    }
+
+    #[test]
+    fn test_reachable_objects_survive() {
+        let env = Environment::new();
+        let mut parser = Parser::new(Lexer::new(
+            "let counter = fn(n) { let next = fn() { counter(n + 1) }; [n, next] };
+             let pair = counter(1);"
+                .to_string(),
+        ));
+        let program = parser.parse_program().unwrap();
+        eval_program(&program, &env);
+
+        collect(&[Root::Env(&env)]);
+        // values only held by the interpreter, like this one, are kept alive as well
+        let held = env.borrow().get("pair").unwrap();
+        collect(&[]);
+
+        let mut parser = Parser::new(Lexer::new("pair[1]()[1]()[0]".to_string()));
+        let program = parser.parse_program().unwrap();
+        assert_eq!(eval_program(&program, &env).inspect(), "3");
+        assert_eq!(held.inspect(), "[1, fn() {\ncounter((n + 1))\n}]");
+    }
+
+    #[test]
+    fn test_gc_builtin_and_automatic_collections() {
+        let leak = "let leak = fn() { let f = fn() { f }; 1 };";
+        let freed = eval(&format!("{} leak(); leak(); gc()", leak));
+        match freed {
+            Object::Integer(bytes) => assert!(bytes > 0),
+            other => panic!("expected the bytes freed, got {}", other.inspect()),
+        }
+
+        set_threshold(1);
+        let before = stats().collections;
+        let program = format!(
+            "{} let loop = fn(n) {{ if (n > 0) {{ leak(); loop(n - 1) }} else {{ 0 }} }}; loop(50)",
+            leak
+        );
+        assert_eq!(eval(&program).inspect(), "0");
+        assert!(stats().collections > before);
+        set_threshold(super::DEFAULT_THRESHOLD);
+    }
+
+    #[test]
+    fn test_vm_cycles_are_reclaimed() {
+        let input = "let leak = fn() { let f = fn() { f }; 1 }; leak(); leak(); leak(); gc()";
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program().unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+
+        let before = stats().objects_freed;
+        let freed = Vm::new().run(&bytecode).unwrap();
+        assert!(matches!(freed, Object::Integer(bytes) if bytes > 0));
+        assert!(stats().objects_freed >= before + 3);
+    }
+}
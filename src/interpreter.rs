@@ -0,0 +1,701 @@
+use crate::environment::{Env, Environment};
+use crate::evaluator::{apply_function, eval_program};
+use crate::lexer::Lexer;
+use crate::limits::{self, Limits};
+use crate::macro_expansion::{define_macros, expand_macros};
+use crate::object::{HostFunction, Object};
+use crate::parser::{Operator, Parser};
+use crate::resolver::{ResolveError, Resolver};
+use crate::serde_value::{from_value, to_value};
+use crate::trace::{self, Frame};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::rc::Rc;
+
+/// runs Lingo programs for a Rust application. the bindings made by one program,
+/// the functions registered by the application and the globals it sets
+/// are all visible to the programs evaluated after it.
+///
+/// programs can be run on any thread, the evaluator grows the native stack
+/// as the calls nest until they reach the call depth limit
+pub struct Interpreter {
+    env: Env,
+    resolver: Resolver,
+    /// the macros defined by the programs evaluated so far
+    macros: Env,
+    limits: Limits,
+    /// the operators the programs are parsed with
+    operators: Vec<Operator>,
+}
+
+/// why running a program, or reading one of its values, failed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    Parse(Vec<String>),
+    /// a macro called by the program failed or didn't return a quote
+    Macro(String),
+    /// the program changes constants, it is rejected before it runs
+    Resolve(Vec<ResolveError>),
+    /// the calls being executed when the error happened, innermost last
+    Runtime {
+        message: String,
+        trace: Vec<Frame>,
+    },
+    /// a value doesn't have the type the application asked for
+    Type(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(errors) => write!(f, "parser errors: {}", errors.join(", ")),
+            Error::Macro(message) => write!(f, "macro error: {}", message),
+            Error::Resolve(errors) => {
+                let errors: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+                write!(f, "resolver errors: {}", errors.join(", "))
+            }
+            Error::Runtime { message, .. } => write!(f, "runtime error: {}", message),
+            Error::Type(message) => write!(f, "type error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    fn runtime(message: String, trace: Vec<Frame>) -> Self {
+        Error::Runtime { message, trace }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter {
+            env: Environment::new(),
+            resolver: Resolver::new(),
+            macros: Environment::new(),
+            limits: Limits::default(),
+            operators: Vec::new(),
+        }
+    }
+
+    /// set the limits of the programs evaluated from now on
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// add the operator to the grammar of the programs evaluated from now on,
+    /// see `Parser::register_operator`. the function it calls is looked up when it runs
+    pub fn register_operator(&mut self, operator: Operator) -> Result<&mut Self, Error> {
+        self.operators.push(operator);
+        if let Err(err) = self.parser("") {
+            self.operators.pop();
+            return Err(err);
+        }
+        Ok(self)
+    }
+
+    fn parser(&self, source: &str) -> Result<Parser, Error> {
+        let mut parser = Parser::new(Lexer::new(source.to_string()));
+        for operator in &self.operators {
+            parser
+                .register_operator(operator.clone())
+                .map_err(|err| Error::Parse(vec![err]))?;
+        }
+        Ok(parser)
+    }
+
+    /// parse the program, expand its macros and evaluate it,
+    /// returning the value of its last statement
+    pub fn eval(&mut self, source: &str) -> Result<Object, Error> {
+        let mut parser = self.parser(source)?;
+        let program = parser.parse_program();
+        let mut program = match program {
+            Some(program) if parser.errors().is_empty() => program,
+            _ => return Err(Error::Parse(parser.errors().to_vec())),
+        };
+        limits::set_limits(self.limits.clone());
+        define_macros(&mut program, &self.macros);
+        let program = expand_macros(&program, &self.macros).map_err(Error::Macro)?;
+        self.resolver.resolve(&program).map_err(Error::Resolve)?;
+
+        match limits::grow_stack(|| eval_program(&program, &self.env)) {
+            Object::Error(message) => Err(Error::runtime(message, trace::take())),
+            value => Ok(value),
+        }
+    }
+
+    /// the warnings about the last program evaluated, such as match expressions
+    /// which may match no arm
+    pub fn warnings(&self) -> &[ResolveError] {
+        self.resolver.warnings()
+    }
+
+    /// call the function bound to the name with the arguments
+    pub fn call(&mut self, name: &str, args: Vec<Object>) -> Result<Object, Error> {
+        let function = self.get_global::<Object>(name)?;
+        limits::set_limits(self.limits.clone());
+        limits::start_run();
+        trace::clear();
+        match limits::grow_stack(|| apply_function(function, args)) {
+            Object::Error(message) => Err(Error::runtime(message, trace::take())),
+            value => Ok(value),
+        }
+    }
+
+    /// bind a Rust function under the name. its arguments are converted from Lingo values
+    /// with `FromValue` and its result with `IntoValue`, it can fail by returning an `Err`
+    pub fn register_fn<Args, F>(&mut self, name: &str, func: F) -> &mut Self
+    where
+        F: HostFn<Args> + 'static,
+    {
+        self.register_raw_fn(name, move |args| func.call(args))
+    }
+
+    /// bind a Rust function taking the Lingo values it is called with as they are,
+    /// for functions which accept any number of arguments
+    pub fn register_raw_fn<F>(&mut self, name: &str, func: F) -> &mut Self
+    where
+        F: Fn(Vec<Object>) -> Result<Object, String> + 'static,
+    {
+        let function = HostFunction {
+            name: name.to_string(),
+            func: Box::new(func),
+        };
+        self.env
+            .borrow_mut()
+            .set(name, Object::HostFunction(Rc::new(function)));
+        self
+    }
+
+    pub fn set_global(&mut self, name: &str, value: impl IntoValue) -> &mut Self {
+        self.env.borrow_mut().set(name, value.into_value());
+        self
+    }
+
+    pub fn get_global<T: FromValue>(&self, name: &str) -> Result<T, Error> {
+        let value = self.env.borrow().get(name);
+        match value {
+            Some(value) => T::from_value(&value).map_err(Error::Type),
+            None => Err(Error::runtime(
+                format!("identifier not found: {}", name),
+                Vec::new(),
+            )),
+        }
+    }
+}
+
+/// a Rust type Lingo values can be converted to
+pub trait FromValue: Sized {
+    fn from_value(value: &Object) -> Result<Self, String>;
+}
+
+/// a Rust type which can be converted to a Lingo value
+pub trait IntoValue {
+    fn into_value(self) -> Object;
+}
+
+fn expected<T>(object_type: &str, value: &Object) -> Result<T, String> {
+    Err(format!(
+        "expected {}, got {}",
+        object_type,
+        value.object_type()
+    ))
+}
+
+impl FromValue for Object {
+    fn from_value(value: &Object) -> Result<Self, String> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Object) -> Result<Self, String> {
+        match value {
+            Object::Integer(value) => Ok(*value),
+            _ => expected("INTEGER", value),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Object) -> Result<Self, String> {
+        match value {
+            Object::Boolean(value) => Ok(*value),
+            _ => expected("BOOLEAN", value),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Object) -> Result<Self, String> {
+        match value {
+            Object::String(value) => Ok(value.to_string()),
+            _ => expected("STRING", value),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Object) -> Result<Self, String> {
+        match value {
+            Object::Array(elements) => elements
+                .iter()
+                .enumerate()
+                .map(|(index, element)| {
+                    T::from_value(element).map_err(|err| format!("element {}: {}", index, err))
+                })
+                .collect(),
+            Object::Range(start, end) => (*start..*end)
+                .map(|element| T::from_value(&Object::Integer(element)))
+                .collect(),
+            _ => expected("ARRAY", value),
+        }
+    }
+}
+
+/// `null` converts to `None`
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Object) -> Result<Self, String> {
+        match value {
+            Object::Null => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl IntoValue for Object {
+    fn into_value(self) -> Object {
+        self
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Object {
+        Object::Integer(self)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Object {
+        Object::Boolean(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Object {
+        Object::String(self.into())
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Object {
+        Object::String(self.into())
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Object {
+        Object::Null
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Object {
+        Object::Array(Rc::new(self.into_iter().map(T::into_value).collect()))
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Object {
+        self.map_or(Object::Null, T::into_value)
+    }
+}
+
+/// converts the arguments and the result of a host function, or a global read by `get_global`,
+/// with serde, see `serde_value::to_value`. a struct can for example be passed as a hash
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Serde<T>(pub T);
+
+impl<T: DeserializeOwned> FromValue for Serde<T> {
+    fn from_value(value: &Object) -> Result<Self, String> {
+        from_value(value).map(Serde).map_err(|err| err.to_string())
+    }
+}
+
+impl<T: Serialize> IntoHostResult for Serde<T> {
+    fn into_host_result(self) -> Result<Object, String> {
+        to_value(&self.0).map_err(|err| err.to_string())
+    }
+}
+
+/// what a host function returns, either a value or a `Result` whose error
+/// becomes a runtime error
+pub trait IntoHostResult {
+    fn into_host_result(self) -> Result<Object, String>;
+}
+
+impl<T: IntoValue> IntoHostResult for T {
+    fn into_host_result(self) -> Result<Object, String> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue, E: fmt::Display> IntoHostResult for Result<T, E> {
+    fn into_host_result(self) -> Result<Object, String> {
+        self.map(T::into_value).map_err(|err| err.to_string())
+    }
+}
+
+/// a Rust closure which can be registered with `Interpreter::register_fn`,
+/// `Args` is the tuple of its argument types
+pub trait HostFn<Args> {
+    fn call(&self, args: Vec<Object>) -> Result<Object, String>;
+}
+
+macro_rules! impl_host_fn {
+    ($count:expr $(, $arg:ident $value:ident)*) => {
+        impl<F, R, $($arg),*> HostFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R,
+            R: IntoHostResult,
+            $($arg: FromValue,)*
+        {
+            #[allow(unused_mut, unused_variables)]
+            fn call(&self, args: Vec<Object>) -> Result<Object, String> {
+                if args.len() != $count {
+                    return Err(format!(
+                        "wrong number of arguments: want={}, got={}",
+                        $count,
+                        args.len()
+                    ));
+                }
+                let mut args = args.iter().enumerate();
+                $(
+                    let (index, value) = args.next().unwrap();
+                    let $value = $arg::from_value(value)
+                        .map_err(|err| format!("argument {}: {}", index + 1, err))?;
+                )*
+                (self)($($value),*).into_host_result()
+            }
+        }
+    };
+}
+
+impl_host_fn!(0);
+impl_host_fn!(1, A a);
+impl_host_fn!(2, A a, B b);
+impl_host_fn!(3, A a, B b, C c);
+impl_host_fn!(4, A a, B b, C c, D d);
+impl_host_fn!(5, A a, B b, C c, D d, E e);
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{Error, Interpreter, Serde};
+    use crate::object::Object;
+    use crate::parser::{Associativity, Operator, PRODUCT, SUM};
+    use crate::serde_value::to_value;
+    use crate::trace::Frame;
+    use serde::{Deserialize, Serialize};
+    use std::thread;
+
+    fn eval(interpreter: &mut Interpreter, input: &str) -> String {
+        match interpreter.eval(input) {
+            Ok(value) => value.inspect(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_register_fn() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .register_fn("add", |a: i64, b: i64| a + b)
+            .register_fn("greet", |name: String| format!("hello, {}", name))
+            .register_fn("sum", |numbers: Vec<i64>| numbers.iter().sum::<i64>())
+            .register_fn("answer", || 42)
+            .register_fn("or_zero", |value: Option<i64>| value.unwrap_or(0))
+            .register_fn("half", |n: i64| {
+                if n % 2 == 0 {
+                    Ok(n / 2)
+                } else {
+                    Err(format!("{} is odd", n))
+                }
+            })
+            .register_raw_fn("count", |args| Ok(Object::Integer(args.len() as i64)));
+
+        let tests = vec![
+            ("add(1, 2)", "3"),
+            ("let twice = fn(f, x) { f(f(x, x), x) }; twice(add, 1)", "3"),
+            ("greet(\"lingo\")", "hello, lingo"),
+            ("sum([1, 2, 3])", "6"),
+            ("answer()", "42"),
+            ("or_zero(if (false) { 1 })", "0"),
+            ("half(4)", "2"),
+            ("count(1, true, \"three\")", "3"),
+            ("add", "host function add"),
+            ("half(3)", "runtime error: half: 3 is odd"),
+            (
+                "add(1)",
+                "runtime error: add: wrong number of arguments: want=2, got=1",
+            ),
+            (
+                "add(1, \"2\")",
+                "runtime error: add: argument 2: expected INTEGER, got STRING",
+            ),
+            (
+                "sum([1, true])",
+                "runtime error: sum: argument 1: element 1: expected INTEGER, got BOOLEAN",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(eval(&mut interpreter, input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_register_operator() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .register_fn("concat", |a: Vec<i64>, b: Vec<i64>| [a, b].concat())
+            .register_operator(Operator::infix("++", SUM, Associativity::Left, "concat"))
+            .unwrap()
+            .register_operator(Operator::prefix("#", "len"))
+            .unwrap();
+        assert_eq!(
+            eval(
+                &mut interpreter,
+                "let pow = fn(a, b) { if (b == 0) { 1 } else { a * pow(a, b - 1) } }; 1"
+            ),
+            "1"
+        );
+        interpreter
+            .register_operator(Operator::infix(
+                "^^",
+                PRODUCT + 1,
+                Associativity::Right,
+                "pow",
+            ))
+            .unwrap();
+
+        let tests = vec![
+            ("[1] ++ [2, 3] ++ [4]", "[1, 2, 3, 4]"),
+            ("#([1, 2] ++ [3])", "3"),
+            ("2 ^^ 3 ^^ 2", "512"),
+            ("2 * 2 ^^ 2", "8"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(eval(&mut interpreter, input), expected, "{}", input);
+        }
+
+        assert_eq!(
+            interpreter
+                .register_operator(Operator::prefix("-", "negate"))
+                .err(),
+            Some(Error::Parse(vec![
+                "operator - is already defined".to_string()
+            ]))
+        );
+        assert_eq!(eval(&mut interpreter, "-#[1]"), "-1");
+    }
+
+    #[test]
+    fn test_globals() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .set_global("name", "lingo")
+            .set_global("numbers", vec![1_i64, 2, 3]);
+        assert_eq!(
+            eval(&mut interpreter, "let count = len(numbers); name + \"!\""),
+            "lingo!"
+        );
+        assert_eq!(interpreter.get_global::<i64>("count"), Ok(3));
+        assert_eq!(
+            interpreter.get_global::<Vec<i64>>("numbers"),
+            Ok(vec![1, 2, 3])
+        );
+        assert_eq!(eval(&mut interpreter, "let digits = range(4);"), "null");
+        assert_eq!(
+            interpreter.get_global::<Vec<i64>>("digits"),
+            Ok(vec![0, 1, 2, 3])
+        );
+        assert_eq!(
+            interpreter.get_global::<Serde<Vec<i64>>>("digits"),
+            Ok(Serde(vec![0, 1, 2, 3]))
+        );
+        assert_eq!(
+            interpreter.get_global::<bool>("name"),
+            Err(Error::Type("expected BOOLEAN, got STRING".to_string()))
+        );
+        assert_eq!(
+            interpreter.get_global::<i64>("missing"),
+            Err(Error::Runtime {
+                message: "identifier not found: missing".to_string(),
+                trace: vec![],
+            })
+        );
+        assert_eq!(
+            eval(&mut interpreter, "let x = ;"),
+            "parser errors: no prefix parse function for ; found"
+        );
+        assert_eq!(eval(&mut interpreter, "const limit = 10;"), "null");
+        assert_eq!(
+            eval(&mut interpreter, "limit += 1"),
+            "resolver errors: cannot assign to constant limit at line 1, column 1"
+        );
+        assert_eq!(eval(&mut interpreter, "let id = macro(x) { x };"), "null");
+        assert_eq!(eval(&mut interpreter, "id(limit * 2)"), "20");
+        assert!(interpreter.warnings().is_empty());
+        assert_eq!(
+            eval(&mut interpreter, "match (limit) { 10 => \"ten\" }"),
+            "ten"
+        );
+        assert_eq!(interpreter.warnings().len(), 1);
+        assert_eq!(
+            eval(&mut interpreter, "id()"),
+            "macro error: wrong number of arguments: want=1, got=0 in macro id called at line 1, column 3"
+        );
+    }
+
+    #[test]
+    fn test_runtime_error_traces() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_fn("check", |n: i64| {
+            if n > 0 {
+                Ok(n)
+            } else {
+                Err("must be positive")
+            }
+        });
+        let frame = |function: &str, line, column| Frame {
+            function: function.to_string(),
+            line,
+            column,
+        };
+        let program = "let validate = fn(n) { check(n) };\nlet run = fn() { validate(0) };\nrun()";
+        assert_eq!(
+            interpreter.eval(program).map(|value| value.inspect()),
+            Err(Error::Runtime {
+                message: "check: must be positive".to_string(),
+                trace: vec![frame("run", 3, 4), frame("validate", 2, 26)],
+            })
+        );
+        // calls made by the host have no call site
+        assert_eq!(
+            interpreter
+                .call("validate", vec![Object::Integer(-1)])
+                .map(|value| value.inspect()),
+            Err(Error::Runtime {
+                message: "check: must be positive".to_string(),
+                trace: vec![frame("validate", 0, 0)],
+            })
+        );
+    }
+
+    #[test]
+    fn test_deep_recursion_on_a_small_stack() {
+        let result = thread::spawn(|| {
+            let mut interpreter = Interpreter::new();
+            let evaluated = eval(&mut interpreter, "let f = fn() { f() }; f()");
+            let called = interpreter.call("f", vec![]).map(|value| value.inspect());
+            (evaluated, called.map_err(|err| err.to_string()))
+        })
+        .join()
+        .expect("the interpreter thread crashed");
+        let message = "runtime error: maximum call depth of 1000 exceeded";
+        assert_eq!(result, (message.to_string(), Err(message.to_string())));
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Config {
+        name: String,
+        retries: i64,
+        hosts: Vec<String>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Plan {
+        label: String,
+        attempts: i64,
+        first: Option<String>,
+    }
+
+    #[test]
+    fn test_serde_conversions() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .register_fn("load", |name: String| {
+                Serde(Config {
+                    name,
+                    retries: 2,
+                    hosts: vec![],
+                })
+            })
+            .register_fn("retries", |config: Serde<Config>| config.0.retries);
+        interpreter
+            .eval(
+                "let plan = fn(config) {
+                   {\"label\": config[\"name\"] + \"!\", \"attempts\": config[\"retries\"] + 1,
+                    \"first\": first(config[\"hosts\"])}
+                 };",
+            )
+            .unwrap();
+
+        let config = Config {
+            name: "deploy".to_string(),
+            retries: 3,
+            hosts: vec!["a".to_string(), "b".to_string()],
+        };
+        let plan = interpreter
+            .call("plan", vec![to_value(&config).unwrap()])
+            .unwrap();
+        assert_eq!(
+            crate::serde_value::from_value::<Plan>(&plan),
+            Ok(Plan {
+                label: "deploy!".to_string(),
+                attempts: 4,
+                first: Some("a".to_string()),
+            })
+        );
+
+        assert_eq!(
+            eval(
+                &mut interpreter,
+                "let loaded = load(\"test\"); retries(loaded)"
+            ),
+            "2"
+        );
+        assert_eq!(
+            interpreter.get_global::<Serde<Config>>("loaded"),
+            Ok(Serde(Config {
+                name: "test".to_string(),
+                retries: 2,
+                hosts: vec![],
+            }))
+        );
+        assert_eq!(
+            eval(&mut interpreter, "retries({\"name\": \"x\"})"),
+            "runtime error: retries: argument 1: missing field `retries`"
+        );
+        assert_eq!(
+            interpreter
+                .call("missing", vec![])
+                .map(|value| value.inspect()),
+            Err(Error::Runtime {
+                message: "identifier not found: missing".to_string(),
+                trace: vec![],
+            })
+        );
+        assert!(matches!(
+            interpreter.call("plan", vec![Object::Integer(1)]),
+            Err(Error::Runtime { .. })
+        ));
+    }
+}
@@ -1,6 +1,4 @@
 use crate::token::*;
-use std::ascii;
-use std::ops::Add;
 
 /// the lexer only supports ASCII characters instead of the full Unicode range
 /// in oder to keep things simple and concentrate on the essential parts of our interpreter.
@@ -14,6 +12,12 @@ pub struct Lexer {
     read_position: usize,
     /// current char under examination
     ch: u8,
+    /// line of the current char, starting at 1
+    line: usize,
+    /// column of the current char, starting at 1
+    column: usize,
+    /// the symbols of the operators added to the grammar, longest first
+    operators: Vec<TokenType>,
 }
 
 impl Lexer {
@@ -23,6 +27,9 @@ impl Lexer {
             position: 0,
             read_position: 0,
             ch: 0,
+            line: 1,
+            column: 0,
+            operators: Vec::new(),
         };
         lex.read_char();
         lex
@@ -30,6 +37,14 @@ impl Lexer {
 
     /// give us the next character and advance our position in the input string
     pub fn read_char(&mut self) {
+        // keep track of the location of the char we are moving to
+        if self.ch == b'\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
         // check whether we have reached the end of input
         if self.read_position >= self.input.len() {
             self.ch = 0;
@@ -44,58 +59,78 @@ impl Lexer {
     pub fn next_token(&mut self) -> Token {
         self.skip_whitespace();
 
+        let start = self.position.min(self.input.len());
+        let line = self.line;
+        let column = self.column;
+
         // check whether to advance our position in the input string after match a token
         // if match letter or digit, it should not advance the position because the position had already advanced when read entire literal.
         let mut char_advance = true;
 
-        let token = match self.ch {
-            b'=' => {
-                // composed of two characters ==
-                if self.peek_char() == b'=' {
-                    let mut literal = String::new();
-                    literal.push(self.ch as char);
-                    self.read_char();
-                    literal.push(self.ch as char);
-
-                    Token {
-                        token_type: EQ,
-                        literal,
-                    }
-                } else {
-                    Token::new(ASSIGN, self.ch)
-                }
-            }
-            b'!' => {
-                // composed of two characters !=
-                if self.peek_char() == b'=' {
-                    let mut literal = String::new();
-                    literal.push(self.ch as char);
-                    self.read_char();
-                    literal.push(self.ch as char);
-
-                    Token {
-                        token_type: NOT_EQ,
-                        literal,
-                    }
-                } else {
-                    Token::new(BANG, self.ch)
-                }
-            }
+        let mut token = match self.ch {
+            // the added operators go first, so that they can extend a built-in one, like `<=>` does `<`
+            _ if self.operator().is_some() => self.read_operator(),
+            // composed of two characters ==
+            b'=' if self.peek_char() == b'=' => self.read_two_char_token(EQ),
+            // the arrow of match arms =>
+            b'=' if self.peek_char() == b'>' => self.read_two_char_token(FAT_ARROW),
+            b'=' => Token::new(ASSIGN, self.ch),
+            // composed of two characters !=
+            b'!' if self.peek_char() == b'=' => self.read_two_char_token(NOT_EQ),
+            b'!' => Token::new(BANG, self.ch),
+            // compound assignments +=, -=, *= and /=
+            b'+' if self.peek_char() == b'=' => self.read_two_char_token(PLUS_ASSIGN),
+            b'-' if self.peek_char() == b'=' => self.read_two_char_token(MINUS_ASSIGN),
+            b'*' if self.peek_char() == b'=' => self.read_two_char_token(ASTERISK_ASSIGN),
+            b'/' if self.peek_char() == b'=' => self.read_two_char_token(SLASH_ASSIGN),
             b'+' => Token::new(PLUS, self.ch),
             b'-' => Token::new(MINUS, self.ch),
             b'/' => Token::new(SLASH, self.ch),
+            // exponentiation **
+            b'*' if self.peek_char() == b'*' => self.read_two_char_token(POWER),
             b'*' => Token::new(ASTERISK, self.ch),
+            b'%' => Token::new(PERCENT, self.ch),
+            // the shifts << and >>
+            b'<' if self.peek_char() == b'<' => self.read_two_char_token(SHIFT_LEFT),
+            b'>' if self.peek_char() == b'>' => self.read_two_char_token(SHIFT_RIGHT),
             b'>' => Token::new(GT, self.ch),
             b'<' => Token::new(LT, self.ch),
+            b'&' => Token::new(AMPERSAND, self.ch),
+            b'^' => Token::new(CARET, self.ch),
+            b'~' => Token::new(TILDE, self.ch),
             b';' => Token::new(SEMICOLON, self.ch),
             b',' => Token::new(COMMA, self.ch),
             b'(' => Token::new(LPAREN, self.ch),
             b')' => Token::new(RPAREN, self.ch),
             b'{' => Token::new(LBRACE, self.ch),
             b'}' => Token::new(RBRACE, self.ch),
+            b'[' => Token::new(LBRACKET, self.ch),
+            b']' => Token::new(RBRACKET, self.ch),
+            b':' => Token::new(COLON, self.ch),
+            // the pipeline operator |>, and the bitwise or | which also encloses the parameters of a lambda |x|
+            b'|' if self.peek_char() == b'>' => self.read_two_char_token(PIPE),
+            b'|' => Token::new(BAR, self.ch),
+            // the rest of an array pattern ..rest, and of the parameters of a function ...rest
+            b'.' if self.peek_char() == b'.' => {
+                let mut token = self.read_two_char_token(DOT_DOT);
+                if self.peek_char() == b'.' {
+                    self.read_char();
+                    token.token_type = ELLIPSIS;
+                    token.literal.push('.');
+                }
+                token
+            }
+            // the access to a field p.x
+            b'.' => Token::new(DOT, self.ch),
+            b'"' => Token {
+                token_type: STRING,
+                literal: self.read_string().to_string(),
+                span: Span::default(),
+            },
             0 => Token {
                 token_type: EOF,
                 literal: "".to_string(),
+                span: Span::default(),
             },
             _ => {
                 if is_letter(self.ch) {
@@ -106,6 +141,7 @@ impl Lexer {
                     Token {
                         token_type,
                         literal: literal.to_string(),
+                        span: Span::default(),
                     }
                 } else if is_digit(self.ch) {
                     char_advance = false;
@@ -113,6 +149,7 @@ impl Lexer {
                     Token {
                         token_type: INT,
                         literal: self.read_number().to_string(),
+                        span: Span::default(),
                     }
                 } else {
                     Token::new(ILLEGAL, self.ch)
@@ -122,9 +159,72 @@ impl Lexer {
         if char_advance {
             self.read_char();
         }
+        token.span = Span {
+            line,
+            column,
+            start,
+            end: self.position.min(self.input.len()),
+        };
         token
     }
 
+    /// reads in an operator made of the current and the next character,
+    /// leaving the lexer on the second one
+    fn read_two_char_token(&mut self, token_type: TokenType) -> Token {
+        let mut literal = String::new();
+        literal.push(self.ch as char);
+        self.read_char();
+        literal.push(self.ch as char);
+
+        Token {
+            token_type,
+            literal,
+            span: Span::default(),
+        }
+    }
+
+    /// the symbol of the added operator starting at the current char
+    fn operator(&self) -> Option<TokenType> {
+        let rest = self.input.get(self.position..)?;
+        self.operators
+            .iter()
+            .find(|symbol| rest.starts_with(**symbol))
+            .copied()
+    }
+
+    /// reads in an added operator, leaving the lexer on its last character
+    fn read_operator(&mut self) -> Token {
+        let symbol = self.operator().unwrap();
+        for _ in 1..symbol.len() {
+            self.read_char();
+        }
+
+        Token {
+            token_type: symbol,
+            literal: symbol.to_string(),
+            span: Span::default(),
+        }
+    }
+
+    /// lex the symbol as a single token from now on
+    pub fn add_operator(&mut self, symbol: TokenType) {
+        if !self.operators.contains(&symbol) {
+            self.operators.push(symbol);
+            self.operators
+                .sort_by_key(|symbol| std::cmp::Reverse(symbol.len()));
+        }
+    }
+
+    /// continue lexing at the start of the token, so that the tokens after it
+    /// are lexed again with the operators added since
+    pub fn rewind(&mut self, span: Span) {
+        self.read_position = span.start;
+        self.line = span.line;
+        self.column = span.column - 1;
+        self.ch = 0;
+        self.read_char();
+    }
+
     /// reads in an identifier and advances our lexer's positions
     /// until it encounters a non-letter-character
     fn read_identifier(&mut self) -> &str {
@@ -135,6 +235,19 @@ impl Lexer {
         &self.input[pos..self.position]
     }
 
+    /// reads in a string literal up to the closing double quote or the end of input.
+    /// the lexer is left on the closing double quote, which is skipped as the current char
+    fn read_string(&mut self) -> &str {
+        let pos = self.position + 1;
+        loop {
+            self.read_char();
+            if self.ch == b'"' || self.ch == 0 {
+                break;
+            }
+        }
+        &self.input[pos..self.position]
+    }
+
     fn read_number(&mut self) -> &str {
         let pos = self.position;
         while is_digit(self.ch) {
@@ -154,12 +267,7 @@ impl Lexer {
         if self.read_position >= self.input.len() {
             0
         } else {
-            let pos_char = &self.input[self.read_position..self.read_position + 1]
-                .as_bytes()
-                .get(0)
-                .unwrap()
-                .to_owned();
-            *pos_char
+            self.input.as_bytes()[self.read_position]
         }
     }
 }
@@ -167,7 +275,7 @@ impl Lexer {
 /// check whether the given argument is a letter
 /// wh treat `_` as a letter and allow it in identifiers and keywords
 fn is_letter(ch: u8) -> bool {
-    (b'a' <= ch && ch <= b'z') || (b'A' <= ch && ch <= b'Z') || ch == b'_'
+    ch.is_ascii_lowercase() || ch.is_ascii_uppercase() || ch == b'_'
 }
 
 fn is_whitespace(ch: u8) -> bool {
@@ -175,7 +283,7 @@ fn is_whitespace(ch: u8) -> bool {
 }
 
 fn is_digit(ch: u8) -> bool {
-    b'0' <= ch && ch <= b'9'
+    ch.is_ascii_digit()
 }
 
 #[cfg(test)]
@@ -223,6 +331,10 @@ mod tests {
             
             10 == 10;
             10 != 9;
+            "foobar"
+            "foo bar"
+            [1, 2];
+            {"foo": "bar"}
             "#;
         let lex = Lexer::new(input.to_string());
 
@@ -300,10 +412,203 @@ mod tests {
             (NOT_EQ, "!="),
             (INT, "9"),
             (SEMICOLON, ";"),
+            (STRING, "foobar"),
+            (STRING, "foo bar"),
+            (LBRACKET, "["),
+            (INT, "1"),
+            (COMMA, ","),
+            (INT, "2"),
+            (RBRACKET, "]"),
+            (SEMICOLON, ";"),
+            (LBRACE, "{"),
+            (STRING, "foo"),
+            (COLON, ":"),
+            (STRING, "bar"),
+            (RBRACE, "}"),
+            (EOF, ""),
+        ];
+
+        walk_through_input_token(lex, tests);
+
+        let input = "x += 1 -= 2 *= 3 /= 4 = 5";
+        let lex = Lexer::new(input.to_string());
+        let tests = vec![
+            (IDENT, "x"),
+            (PLUS_ASSIGN, "+="),
+            (INT, "1"),
+            (MINUS_ASSIGN, "-="),
+            (INT, "2"),
+            (ASTERISK_ASSIGN, "*="),
+            (INT, "3"),
+            (SLASH_ASSIGN, "/="),
+            (INT, "4"),
+            (ASSIGN, "="),
+            (INT, "5"),
+            (EOF, ""),
+        ];
+
+        walk_through_input_token(lex, tests);
+
+        let input = "while for x in break continue";
+        let lex = Lexer::new(input.to_string());
+        let tests = vec![
+            (WHILE, "while"),
+            (FOR, "for"),
+            (IDENT, "x"),
+            (IN, "in"),
+            (BREAK, "break"),
+            (CONTINUE, "continue"),
+            (EOF, ""),
+        ];
+
+        walk_through_input_token(lex, tests);
+
+        let input = "match [_, ..rest] => x";
+        let lex = Lexer::new(input.to_string());
+        let tests = vec![
+            (MATCH, "match"),
+            (LBRACKET, "["),
+            (IDENT, "_"),
+            (COMMA, ","),
+            (DOT_DOT, ".."),
+            (IDENT, "rest"),
+            (RBRACKET, "]"),
+            (FAT_ARROW, "=>"),
+            (IDENT, "x"),
             (EOF, ""),
         ];
 
         walk_through_input_token(lex, tests);
+
+        let input = "fn(x, y = 1, ...rest) .. ...";
+        let lex = Lexer::new(input.to_string());
+        let tests = vec![
+            (FUNCTION, "fn"),
+            (LPAREN, "("),
+            (IDENT, "x"),
+            (COMMA, ","),
+            (IDENT, "y"),
+            (ASSIGN, "="),
+            (INT, "1"),
+            (COMMA, ","),
+            (ELLIPSIS, "..."),
+            (IDENT, "rest"),
+            (RPAREN, ")"),
+            (DOT_DOT, ".."),
+            (ELLIPSIS, "..."),
+            (EOF, ""),
+        ];
+
+        walk_through_input_token(lex, tests);
+
+        let input = "struct impl p.x.y .. ...";
+        let lex = Lexer::new(input.to_string());
+        let tests = vec![
+            (STRUCT, "struct"),
+            (IMPL, "impl"),
+            (IDENT, "p"),
+            (DOT, "."),
+            (IDENT, "x"),
+            (DOT, "."),
+            (IDENT, "y"),
+            (DOT_DOT, ".."),
+            (ELLIPSIS, "..."),
+            (EOF, ""),
+        ];
+
+        walk_through_input_token(lex, tests);
+
+        let input = "a % b ** c * d *= e";
+        let lex = Lexer::new(input.to_string());
+        let tests = vec![
+            (IDENT, "a"),
+            (PERCENT, "%"),
+            (IDENT, "b"),
+            (POWER, "**"),
+            (IDENT, "c"),
+            (ASTERISK, "*"),
+            (IDENT, "d"),
+            (ASTERISK_ASSIGN, "*="),
+            (IDENT, "e"),
+            (EOF, ""),
+        ];
+
+        walk_through_input_token(lex, tests);
+
+        let input = "a & b | ~c ^ d << 2 >> 1 < >";
+        let lex = Lexer::new(input.to_string());
+        let tests = vec![
+            (IDENT, "a"),
+            (AMPERSAND, "&"),
+            (IDENT, "b"),
+            (BAR, "|"),
+            (TILDE, "~"),
+            (IDENT, "c"),
+            (CARET, "^"),
+            (IDENT, "d"),
+            (SHIFT_LEFT, "<<"),
+            (INT, "2"),
+            (SHIFT_RIGHT, ">>"),
+            (INT, "1"),
+            (LT, "<"),
+            (GT, ">"),
+            (EOF, ""),
+        ];
+
+        walk_through_input_token(lex, tests);
+
+        let input = "xs |> map(|x| x) || 1";
+        let lex = Lexer::new(input.to_string());
+        let tests = vec![
+            (IDENT, "xs"),
+            (PIPE, "|>"),
+            (IDENT, "map"),
+            (LPAREN, "("),
+            (BAR, "|"),
+            (IDENT, "x"),
+            (BAR, "|"),
+            (IDENT, "x"),
+            (RPAREN, ")"),
+            (BAR, "|"),
+            (BAR, "|"),
+            (INT, "1"),
+            (EOF, ""),
+        ];
+
+        walk_through_input_token(lex, tests);
+    }
+
+    #[test]
+    fn test_token_spans() {
+        let input = "let five = 5;\n  five == 10;";
+        let mut lex = Lexer::new(input.to_string());
+
+        let tests = vec![
+            (LET, 1, 1, 0, 3),
+            (IDENT, 1, 5, 4, 8),
+            (ASSIGN, 1, 10, 9, 10),
+            (INT, 1, 12, 11, 12),
+            (SEMICOLON, 1, 13, 12, 13),
+            (IDENT, 2, 3, 16, 20),
+            (EQ, 2, 8, 21, 23),
+            (INT, 2, 11, 24, 26),
+            (SEMICOLON, 2, 13, 26, 27),
+            (EOF, 2, 14, 27, 27),
+        ];
+
+        for (expected_type, line, column, start, end) in tests {
+            let token = lex.next_token();
+            assert_eq!(token.token_type, expected_type);
+            assert_eq!(
+                token.span,
+                Span {
+                    line,
+                    column,
+                    start,
+                    end
+                }
+            );
+        }
     }
 
     fn walk_through_input_token(mut lex: Lexer, expected_tokens: Vec<(TokenType, &str)>) {
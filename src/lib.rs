@@ -0,0 +1,23 @@
+pub mod ast;
+pub mod builtins;
+pub mod bytecode_file;
+pub mod code;
+pub mod compiler;
+pub mod disassembler;
+pub mod dump;
+pub mod environment;
+pub mod evaluator;
+pub mod gc;
+pub mod interpreter;
+pub mod lexer;
+pub mod limits;
+pub mod macro_expansion;
+pub mod object;
+pub mod parser;
+pub mod repl;
+pub mod resolver;
+pub mod serde_value;
+pub mod symbol_table;
+pub mod token;
+pub mod trace;
+pub mod vm;
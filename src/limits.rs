@@ -0,0 +1,370 @@
+use crate::gc;
+use crate::object::Object;
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// how deep calls can nest by default
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// how deeply expressions and blocks can nest, in the source of a program
+/// and in the expressions it evaluates
+pub const MAX_NESTING_DEPTH: usize = 1000;
+
+/// when less native stack than this is left, a call of the evaluator
+/// continues on a new segment of stack
+const STACK_RED_ZONE: usize = 256 << 10;
+
+/// the size of the segments of stack allocated for deep recursion
+const STACK_SEGMENT_SIZE: usize = 4 << 20;
+
+/// how many steps are taken between two checks of the cancellation flag
+const CHECK_INTERVAL: u64 = 1024;
+
+thread_local! {
+    static BUDGET: RefCell<Budget> = RefCell::new(Budget::default());
+}
+
+/// what a program may use while it runs on the current thread, see `set_limits`
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// how many steps a run can take: an expression or statement evaluated by the evaluator,
+    /// an instruction executed by the VM
+    pub fuel: Option<u64>,
+    pub max_call_depth: usize,
+    /// the most memory the program can keep alive, as estimated by the garbage collector
+    pub max_heap_bytes: Option<usize>,
+    /// set by the host, from any thread, to stop the program
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            fuel: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            max_heap_bytes: None,
+            cancel: None,
+        }
+    }
+}
+
+/// the runtime error raised when a program goes over one of its limits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitError {
+    OutOfFuel(u64),
+    CallDepth(usize),
+    NestingDepth(usize),
+    HeapSize(usize),
+    Cancelled,
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::OutOfFuel(fuel) => write!(f, "out of fuel after {} steps", fuel),
+            LimitError::CallDepth(depth) => write!(f, "maximum call depth of {} exceeded", depth),
+            LimitError::NestingDepth(depth) => {
+                write!(f, "maximum nesting depth of {} exceeded", depth)
+            }
+            LimitError::HeapSize(bytes) => {
+                write!(f, "heap size limit of {} bytes exceeded", bytes)
+            }
+            LimitError::Cancelled => write!(f, "execution cancelled"),
+        }
+    }
+}
+
+impl From<LimitError> for Object {
+    fn from(err: LimitError) -> Self {
+        Object::Error(err.to_string())
+    }
+}
+
+impl From<LimitError> for String {
+    fn from(err: LimitError) -> Self {
+        err.to_string()
+    }
+}
+
+/// what is left of the limits during the current run
+#[derive(Default)]
+struct Budget {
+    limits: Limits,
+    steps: u64,
+    depth: usize,
+    /// how deeply the expression being evaluated is nested in the body of its function
+    nesting: usize,
+    /// the nesting in the callers of the function being evaluated
+    outer_nesting: Vec<usize>,
+}
+
+/// set the limits of the programs run on the current thread from now on
+pub fn set_limits(limits: Limits) {
+    BUDGET.with(|budget| budget.borrow_mut().limits = limits);
+}
+
+/// refill the fuel at the start of a run
+pub fn start_run() {
+    BUDGET.with(|budget| {
+        let mut budget = budget.borrow_mut();
+        budget.steps = 0;
+        budget.depth = 0;
+        budget.nesting = 0;
+        budget.outer_nesting.clear();
+    });
+}
+
+/// use up one step of fuel, checking the cancellation flag every now and then
+pub fn step() -> Result<(), LimitError> {
+    BUDGET.with(|budget| {
+        let mut budget = budget.borrow_mut();
+        budget.steps += 1;
+        if let Some(fuel) = budget.limits.fuel {
+            if budget.steps > fuel {
+                return Err(LimitError::OutOfFuel(fuel));
+            }
+        }
+        if budget.steps % CHECK_INTERVAL == 0 {
+            if let Some(cancel) = &budget.limits.cancel {
+                if cancel.load(Ordering::Relaxed) {
+                    return Err(LimitError::Cancelled);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// check that a call `depth` levels deep is allowed
+pub fn check_call_depth(depth: usize) -> Result<(), LimitError> {
+    let max = BUDGET.with(|budget| budget.borrow().limits.max_call_depth);
+    if depth > max {
+        return Err(LimitError::CallDepth(max));
+    }
+    Ok(())
+}
+
+/// enter a call of the evaluator, which must be followed by `exit_call` when it returns
+pub fn enter_call() -> Result<(), LimitError> {
+    let depth = BUDGET.with(|budget| {
+        let mut budget = budget.borrow_mut();
+        budget.depth += 1;
+        let nesting = budget.nesting;
+        budget.outer_nesting.push(nesting);
+        budget.nesting = 0;
+        budget.depth
+    });
+    check_call_depth(depth).inspect_err(|_| exit_call())
+}
+
+pub fn exit_call() {
+    BUDGET.with(|budget| {
+        let mut budget = budget.borrow_mut();
+        budget.depth -= 1;
+        budget.nesting = budget.outer_nesting.pop().unwrap_or(0);
+    });
+}
+
+/// enter an expression or a block of the evaluator, which must be followed by `exit_nesting`
+/// when it is evaluated. the nesting starts over in the body of each function called
+pub fn enter_nesting() -> Result<(), LimitError> {
+    let nesting = BUDGET.with(|budget| {
+        let mut budget = budget.borrow_mut();
+        budget.nesting += 1;
+        budget.nesting
+    });
+    if nesting > MAX_NESTING_DEPTH {
+        exit_nesting();
+        return Err(LimitError::NestingDepth(MAX_NESTING_DEPTH));
+    }
+    Ok(())
+}
+
+pub fn exit_nesting() {
+    BUDGET.with(|budget| budget.borrow_mut().nesting -= 1);
+}
+
+/// run `f`, on a new segment of native stack if the current one is almost used up.
+/// deep recursion in Lingo is deep recursion in Rust as well,
+/// this lets it reach the call depth and nesting limits on a thread with a small stack
+pub fn grow_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, f)
+}
+
+/// check that `bytes` more can be allocated without going over the heap size limit,
+/// collecting garbage first if they can't, and record the allocation
+pub fn allocate(bytes: usize) -> Result<(), LimitError> {
+    check_heap(bytes)?;
+    gc::allocate(bytes);
+    Ok(())
+}
+
+/// check that the memory kept alive, with `bytes` more, stays under the heap size limit
+pub fn check_heap(bytes: usize) -> Result<(), LimitError> {
+    let max = match BUDGET.with(|budget| budget.borrow().limits.max_heap_bytes) {
+        Some(max) => max,
+        None => return Ok(()),
+    };
+    if gc::stats().live_bytes + bytes <= max {
+        return Ok(());
+    }
+    // values held by the interpreter are found by the collector without being given as roots
+    gc::collect(&[]);
+    if gc::stats().live_bytes + bytes <= max {
+        return Ok(());
+    }
+    Err(LimitError::HeapSize(max))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Expression, IntegerLiteral, PrefixExpression};
+    use crate::compiler::Compiler;
+    use crate::environment::Environment;
+    use crate::evaluator::{eval_expression, eval_program};
+    use crate::lexer::Lexer;
+    use crate::limits::{set_limits, start_run, Limits, MAX_NESTING_DEPTH};
+    use crate::parser::Parser;
+    use crate::token::{Token, INT, MINUS};
+    use crate::vm::Vm;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    /// run the program with both back ends under the limits,
+    /// on a new thread with the default stack size
+    fn run_limited(input: &str, limits: Limits) -> (String, String) {
+        let input = input.to_string();
+        thread::spawn(move || {
+            set_limits(limits);
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program().unwrap();
+            let evaluated = eval_program(&program, &Environment::new()).inspect();
+            let bytecode = Compiler::new().compile(&program).unwrap();
+            let executed = match Vm::new().run(&bytecode) {
+                Ok(result) => result.inspect(),
+                Err(err) => format!("ERROR: {}", err),
+            };
+            (evaluated, executed)
+        })
+        .join()
+        .unwrap()
+    }
+
+    fn assert_limited(input: &str, limits: Limits, expected: &str) {
+        let (evaluated, executed) = run_limited(input, limits);
+        assert_eq!(evaluated, expected, "evaluator, input: {}", input);
+        assert_eq!(executed, expected, "vm, input: {}", input);
+    }
+
+    #[test]
+    fn test_call_depth() {
+        assert_limited(
+            "let f = fn() { f() }; f()",
+            Limits::default(),
+            "ERROR: maximum call depth of 1000 exceeded",
+        );
+        let count = "let count = fn(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } };";
+        let limits = Limits {
+            max_call_depth: 10,
+            ..Limits::default()
+        };
+        assert_limited(&format!("{} count(9)", count), limits.clone(), "9");
+        assert_limited(
+            &format!("{} count(10)", count),
+            limits,
+            "ERROR: maximum call depth of 10 exceeded",
+        );
+    }
+
+    #[test]
+    fn test_fuel() {
+        let limits = Limits {
+            fuel: Some(5000),
+            ..Limits::default()
+        };
+        assert_limited("1 + 2 * 3", limits.clone(), "7");
+        assert_limited(
+            "let loop = fn(n) { if (n > 0) { loop(n - 1) } else { 0 } }; loop(900)",
+            limits,
+            "ERROR: out of fuel after 5000 steps",
+        );
+    }
+
+    #[test]
+    fn test_heap_size() {
+        let limits = Limits {
+            max_heap_bytes: Some(1 << 20),
+            ..Limits::default()
+        };
+        let grow = "let grow = fn(s, n) { if (n == 0) { len(s) } else { grow(s + s, n - 1) } };";
+        assert_limited(
+            &format!("{} grow(\"ab\", 10)", grow),
+            limits.clone(),
+            "2048",
+        );
+        assert_limited(
+            &format!("{} grow(\"ab\", 30)", grow),
+            limits.clone(),
+            "ERROR: heap size limit of 1048576 bytes exceeded",
+        );
+        // memory that is no longer reachable doesn't count
+        let leak =
+            "let leak = fn(n) { let s = [n, n, n, n, n, n, n, n]; if (n > 0) { leak(n - 1) } };";
+        assert_limited(
+            &format!("{} let loop = fn(n) {{ leak(50); if (n > 0) {{ loop(n - 1) }} else {{ 0 }} }}; loop(200)", leak),
+            limits,
+            "0",
+        );
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let cancel = Arc::new(AtomicBool::new(true));
+        let limits = Limits {
+            cancel: Some(cancel.clone()),
+            ..Limits::default()
+        };
+        assert_limited("1 + 1", limits.clone(), "2");
+        assert_limited(
+            "let loop = fn(n) { if (n > 0) { loop(n - 1) } else { 0 } }; loop(900)",
+            limits.clone(),
+            "ERROR: execution cancelled",
+        );
+        cancel.store(false, Ordering::Relaxed);
+        assert_limited(
+            "let loop = fn(n) { if (n > 0) { loop(n - 1) } else { 0 } }; loop(900)",
+            limits,
+            "0",
+        );
+    }
+
+    #[test]
+    fn test_nesting_depth() {
+        let depth = MAX_NESTING_DEPTH - 10;
+        let nested = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert_limited(&format!("len({})", nested), Limits::default(), "1");
+
+        // the parser rejects deeper programs, an expression nested deeper is built by hand
+        let evaluated = thread::spawn(|| {
+            let mut expression: Box<dyn Expression> = Box::new(IntegerLiteral {
+                token: Token::new(INT, b'1'),
+                value: 1,
+            });
+            for _ in 0..MAX_NESTING_DEPTH {
+                expression = Box::new(PrefixExpression {
+                    token: Token::new(MINUS, b'-'),
+                    operator: "-".to_string(),
+                    right: expression,
+                });
+            }
+            start_run();
+            eval_expression(expression.as_ref(), &Environment::new()).inspect()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(evaluated, "ERROR: maximum nesting depth of 1000 exceeded");
+    }
+}
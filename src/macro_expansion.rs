@@ -0,0 +1,830 @@
+use crate::ast::{
+    ArrayLiteral, ArrayPattern, AssignStatement, BlockStatement, Boolean, CallExpression,
+    Expression, ExpressionStatement, ForStatement, FunctionLiteral, HashLiteral, HashPattern,
+    Identifier, IfExpression, ImplStatement, IndexExpression, InfixExpression, IntegerLiteral,
+    LetStatement, MacroLiteral, MatchArm, MatchExpression, MemberExpression, Parameter, Pattern,
+    PrefixExpression, Program, ReturnStatement, Statement, StringLiteral, StructLiteral,
+    StructStatement, WhileStatement,
+};
+use crate::environment::{Env, Environment};
+use crate::evaluator::{bind_arguments, destructure, eval_block_statement, eval_expression};
+use crate::limits;
+use crate::object::{Function, Object, Signature};
+use crate::token::{Span, Token, TokenType, FALSE, INT, LBRACE, LBRACKET, MINUS, STRING, TRUE};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+thread_local! {
+    /// how many names were generated so far, which makes every generated name different
+    static GENERATED_NAMES: Cell<usize> = const { Cell::new(0) };
+}
+
+/// choose which parts of the code `modify_expression` and `modify_statement` replace
+pub trait Modifier {
+    /// the replacement of the expression, `None` to keep it and modify its children instead
+    fn expression(&mut self, expression: &dyn Expression) -> Option<Box<dyn Expression>>;
+
+    /// the replacement of a name bound by `let`, `for`, a parameter or a pattern
+    fn binding(&mut self, name: &Identifier) -> Identifier {
+        name.clone()
+    }
+}
+
+/// copy the expression, asking the modifier about every expression from the outside in
+pub fn modify_expression(
+    expression: &dyn Expression,
+    modifier: &mut dyn Modifier,
+) -> Box<dyn Expression> {
+    limits::grow_stack(|| modify_nested_expression(expression, modifier))
+}
+
+fn modify_nested_expression(
+    expression: &dyn Expression,
+    modifier: &mut dyn Modifier,
+) -> Box<dyn Expression> {
+    if let Some(replacement) = modifier.expression(expression) {
+        return replacement;
+    }
+    let any = expression.as_any();
+    if let Some(prefix) = any.downcast_ref::<PrefixExpression>() {
+        Box::new(PrefixExpression {
+            token: prefix.token.clone(),
+            operator: prefix.operator.clone(),
+            right: modify_expression(prefix.right.as_ref(), modifier),
+        })
+    } else if let Some(infix) = any.downcast_ref::<InfixExpression>() {
+        Box::new(InfixExpression {
+            token: infix.token.clone(),
+            left: modify_expression(infix.left.as_ref(), modifier),
+            operator: infix.operator.clone(),
+            right: modify_expression(infix.right.as_ref(), modifier),
+        })
+    } else if let Some(if_expression) = any.downcast_ref::<IfExpression>() {
+        Box::new(IfExpression {
+            token: if_expression.token.clone(),
+            condition: modify_expression(if_expression.condition.as_ref(), modifier),
+            consequence: modify_block(&if_expression.consequence, modifier),
+            alternative: if_expression
+                .alternative
+                .as_ref()
+                .map(|alternative| modify_block(alternative, modifier)),
+        })
+    } else if let Some(literal) = any.downcast_ref::<FunctionLiteral>() {
+        Box::new(FunctionLiteral {
+            token: literal.token.clone(),
+            parameters: Rc::new(
+                literal
+                    .parameters
+                    .iter()
+                    .map(|param| Parameter {
+                        pattern: modify_pattern(param.pattern.as_ref(), modifier),
+                        default: modify_optional_expression(&param.default, modifier),
+                    })
+                    .collect(),
+            ),
+            rest: literal.rest.as_ref().map(|rest| modifier.binding(rest)),
+            body: Rc::new(modify_block(&literal.body, modifier)),
+        })
+    } else if let Some(call) = any.downcast_ref::<CallExpression>() {
+        Box::new(CallExpression {
+            token: call.token.clone(),
+            function: modify_expression(call.function.as_ref(), modifier),
+            arguments: modify_expressions(&call.arguments, modifier),
+            named_arguments: call
+                .named_arguments
+                .iter()
+                .map(|(name, value)| (name.clone(), modify_expression(value.as_ref(), modifier)))
+                .collect(),
+        })
+    } else if let Some(array) = any.downcast_ref::<ArrayLiteral>() {
+        Box::new(ArrayLiteral {
+            token: array.token.clone(),
+            elements: modify_expressions(&array.elements, modifier),
+        })
+    } else if let Some(index) = any.downcast_ref::<IndexExpression>() {
+        Box::new(IndexExpression {
+            token: index.token.clone(),
+            left: modify_expression(index.left.as_ref(), modifier),
+            index: modify_expression(index.index.as_ref(), modifier),
+        })
+    } else if let Some(hash) = any.downcast_ref::<HashLiteral>() {
+        Box::new(HashLiteral {
+            token: hash.token.clone(),
+            pairs: hash
+                .pairs
+                .iter()
+                .map(|(key, value)| {
+                    (
+                        modify_expression(key.as_ref(), modifier),
+                        modify_expression(value.as_ref(), modifier),
+                    )
+                })
+                .collect(),
+        })
+    } else if let Some(literal) = any.downcast_ref::<StructLiteral>() {
+        Box::new(StructLiteral {
+            token: literal.token.clone(),
+            name: modify_name(&literal.name, modifier),
+            fields: literal
+                .fields
+                .iter()
+                .map(|(name, value)| (name.clone(), modify_expression(value.as_ref(), modifier)))
+                .collect(),
+        })
+    } else if let Some(member) = any.downcast_ref::<MemberExpression>() {
+        Box::new(MemberExpression {
+            token: member.token.clone(),
+            object: modify_expression(member.object.as_ref(), modifier),
+            member: member.member.clone(),
+        })
+    } else if let Some(expression) = any.downcast_ref::<MatchExpression>() {
+        Box::new(MatchExpression {
+            token: expression.token.clone(),
+            subject: modify_expression(expression.subject.as_ref(), modifier),
+            arms: expression
+                .arms
+                .iter()
+                .map(|arm| MatchArm {
+                    pattern: modify_pattern(arm.pattern.as_ref(), modifier),
+                    guard: modify_optional_expression(&arm.guard, modifier),
+                    body: modify_block(&arm.body, modifier),
+                })
+                .collect(),
+        })
+    } else {
+        expression.clone_expression()
+    }
+}
+
+/// copy a name referring to a binding, which is modified like an identifier expression would be
+fn modify_name(name: &Identifier, modifier: &mut dyn Modifier) -> Identifier {
+    let modified = modify_expression(name, modifier);
+    match modified.as_any().downcast_ref::<Identifier>() {
+        Some(modified) => modified.clone(),
+        None => name.clone(),
+    }
+}
+
+/// copy the pattern, the names it binds are passed to `Modifier::binding`
+fn modify_pattern(pattern: &dyn Pattern, modifier: &mut dyn Modifier) -> Box<dyn Pattern> {
+    let any = pattern.as_any();
+    if let Some(ident) = any.downcast_ref::<Identifier>() {
+        Box::new(modifier.binding(ident))
+    } else if let Some(array) = any.downcast_ref::<ArrayPattern>() {
+        Box::new(ArrayPattern {
+            token: array.token.clone(),
+            elements: array
+                .elements
+                .iter()
+                .map(|element| modify_pattern(element.as_ref(), modifier))
+                .collect(),
+            rest: array.rest.as_ref().map(|rest| modifier.binding(rest)),
+        })
+    } else if let Some(hash) = any.downcast_ref::<HashPattern>() {
+        Box::new(HashPattern {
+            token: hash.token.clone(),
+            pairs: hash
+                .pairs
+                .iter()
+                .map(|(key, pattern)| (key.clone(), modify_pattern(pattern.as_ref(), modifier)))
+                .collect(),
+        })
+    } else {
+        pattern.clone_pattern()
+    }
+}
+
+fn modify_expressions(
+    expressions: &[Box<dyn Expression>],
+    modifier: &mut dyn Modifier,
+) -> Vec<Box<dyn Expression>> {
+    expressions
+        .iter()
+        .map(|expression| modify_expression(expression.as_ref(), modifier))
+        .collect()
+}
+
+fn modify_optional_expression(
+    expression: &Option<Box<dyn Expression>>,
+    modifier: &mut dyn Modifier,
+) -> Option<Box<dyn Expression>> {
+    expression
+        .as_ref()
+        .map(|expression| modify_expression(expression.as_ref(), modifier))
+}
+
+/// copy the statement, asking the modifier about every expression in it
+pub fn modify_statement(stat: &dyn Statement, modifier: &mut dyn Modifier) -> Box<dyn Statement> {
+    let any = stat.as_any();
+    if let Some(stat) = any.downcast_ref::<ExpressionStatement>() {
+        Box::new(ExpressionStatement {
+            token: stat.token.clone(),
+            expression: modify_optional_expression(&stat.expression, modifier),
+        })
+    } else if let Some(stat) = any.downcast_ref::<LetStatement>() {
+        Box::new(LetStatement {
+            token: stat.token.clone(),
+            pattern: modify_pattern(stat.pattern.as_ref(), modifier),
+            constant: stat.constant,
+            value: modify_optional_expression(&stat.value, modifier),
+        })
+    } else if let Some(stat) = any.downcast_ref::<ReturnStatement>() {
+        Box::new(ReturnStatement {
+            token: stat.token.clone(),
+            return_value: modify_optional_expression(&stat.return_value, modifier),
+        })
+    } else if let Some(block) = any.downcast_ref::<BlockStatement>() {
+        Box::new(modify_block(block, modifier))
+    } else if let Some(stat) = any.downcast_ref::<AssignStatement>() {
+        Box::new(AssignStatement {
+            token: stat.token.clone(),
+            target: modify_expression(stat.target.as_ref(), modifier),
+            operator: stat.operator.clone(),
+            value: modify_expression(stat.value.as_ref(), modifier),
+        })
+    } else if let Some(stat) = any.downcast_ref::<WhileStatement>() {
+        Box::new(WhileStatement {
+            token: stat.token.clone(),
+            condition: modify_expression(stat.condition.as_ref(), modifier),
+            body: modify_block(&stat.body, modifier),
+        })
+    } else if let Some(stat) = any.downcast_ref::<ForStatement>() {
+        Box::new(ForStatement {
+            token: stat.token.clone(),
+            variable: modifier.binding(&stat.variable),
+            iterable: modify_expression(stat.iterable.as_ref(), modifier),
+            body: modify_block(&stat.body, modifier),
+        })
+    } else if let Some(stat) = any.downcast_ref::<StructStatement>() {
+        Box::new(StructStatement {
+            token: stat.token.clone(),
+            name: modifier.binding(&stat.name),
+            fields: stat.fields.clone(),
+        })
+    } else if let Some(stat) = any.downcast_ref::<ImplStatement>() {
+        Box::new(ImplStatement {
+            token: stat.token.clone(),
+            name: modify_name(&stat.name, modifier),
+            methods: stat
+                .methods
+                .iter()
+                .map(|(name, method)| {
+                    let modified = modify_expression(method, modifier);
+                    match modified.as_any().downcast_ref::<FunctionLiteral>() {
+                        Some(modified) => (name.clone(), modified.clone()),
+                        None => (name.clone(), method.clone()),
+                    }
+                })
+                .collect(),
+        })
+    } else {
+        stat.clone_statement()
+    }
+}
+
+fn modify_block(block: &BlockStatement, modifier: &mut dyn Modifier) -> BlockStatement {
+    BlockStatement {
+        token: block.token.clone(),
+        statements: block
+            .statements
+            .iter()
+            .map(|stat| limits::grow_stack(|| modify_statement(stat.as_ref(), modifier)))
+            .collect(),
+    }
+}
+
+/// move the macros the program defines at its top level, with `let name = macro(...) { ... };`,
+/// out of the program and into the environment of the macro expansion
+pub fn define_macros(program: &mut Program, env: &Env) {
+    program.statements.retain(|stat| {
+        let Some(stat) = stat.as_any().downcast_ref::<LetStatement>() else {
+            return true;
+        };
+        let Some(literal) = stat
+            .value
+            .as_ref()
+            .and_then(|value| value.as_any().downcast_ref::<MacroLiteral>())
+        else {
+            return true;
+        };
+        let Some(name) = stat.name() else {
+            return true;
+        };
+        let definition = Object::Macro(Rc::new(Function {
+            name: Some(name.value.clone()),
+            parameters: Rc::new(
+                literal
+                    .parameters
+                    .iter()
+                    .map(|param| Parameter {
+                        pattern: Box::new(param.clone()),
+                        default: None,
+                    })
+                    .collect(),
+            ),
+            rest: None,
+            signature: Signature {
+                names: literal.parameters.iter().map(|p| p.value.clone()).collect(),
+                required: literal.parameters.len(),
+                // errors of the expansion already name the macro
+                ..Signature::default()
+            },
+            body: literal.body.clone(),
+            env: env.clone(),
+        }));
+        env.borrow_mut().set(&name.value, definition);
+        false
+    });
+}
+
+/// replace the calls of the macros defined in the environment by the code they return.
+/// a macro is called with its arguments quoted, without evaluating them,
+/// and must return a quote
+pub fn expand_macros(program: &Program, env: &Env) -> Result<Program, String> {
+    limits::start_run();
+    let mut expander = Expander { env, error: None };
+    let statements = program
+        .statements
+        .iter()
+        .map(|stat| modify_statement(stat.as_ref(), &mut expander))
+        .collect();
+    match expander.error {
+        Some(err) => Err(err),
+        None => Ok(Program { statements }),
+    }
+}
+
+/// replaces macro calls by their expansion, remembering the first error
+struct Expander<'a> {
+    env: &'a Env,
+    error: Option<String>,
+}
+
+impl Modifier for Expander<'_> {
+    fn expression(&mut self, expression: &dyn Expression) -> Option<Box<dyn Expression>> {
+        if self.error.is_some() {
+            return Some(expression.clone_expression());
+        }
+        let call = expression.as_any().downcast_ref::<CallExpression>()?;
+        let name = call.function.as_any().downcast_ref::<Identifier>()?;
+        let Some(Object::Macro(definition)) = self.env.borrow().get(&name.value) else {
+            return None;
+        };
+        match expand_macro_call(&definition, call) {
+            Ok(expansion) => Some(expansion),
+            Err(message) => {
+                self.error = Some(format!(
+                    "{} in macro {} called at line {}, column {}",
+                    message, name.value, call.token.span.line, call.token.span.column
+                ));
+                Some(expression.clone_expression())
+            }
+        }
+    }
+}
+
+fn expand_macro_call(
+    definition: &Function,
+    call: &CallExpression,
+) -> Result<Box<dyn Expression>, String> {
+    let quote = |arg: &dyn Expression| Object::Quote(Rc::from(arg.clone_expression()));
+    let args = call
+        .arguments
+        .iter()
+        .map(|arg| quote(arg.as_ref()))
+        .collect();
+    let named = call
+        .named_arguments
+        .iter()
+        .map(|(name, arg)| (name.value.clone(), quote(arg.as_ref())))
+        .collect();
+    let values = bind_arguments(&definition.signature, args, named)?;
+    let env = Environment::new_enclosed(definition.env.clone());
+    // the parameters of a macro have no default values, every one of them has an argument
+    for (param, value) in definition
+        .parameters
+        .iter()
+        .zip(values.into_iter().flatten())
+    {
+        if let Err(Object::Error(message)) = destructure(param.pattern.as_ref(), &value, &env) {
+            return Err(message);
+        }
+    }
+    match eval_block_statement(&definition.body, &env) {
+        Object::ReturnValue(value) => match *value {
+            Object::Quote(node) => Ok(node.clone_expression()),
+            other => Err(format!(
+                "macro must return a quote, got {}",
+                other.object_type()
+            )),
+        },
+        Object::Quote(node) => Ok(node.clone_expression()),
+        Object::Error(message) => Err(message),
+        other => Err(format!(
+            "macro must return a quote, got {}",
+            other.object_type()
+        )),
+    }
+}
+
+/// the value of `quote(expression)`: the code of the expression, in which the `unquote` calls
+/// are replaced by the code of the value of their argument
+pub fn quote(call: &CallExpression, env: &Env) -> Object {
+    if call.arguments.len() != 1 {
+        return Object::Error(format!(
+            "wrong number of arguments to `quote`. got={}, want=1",
+            call.arguments.len()
+        ));
+    }
+    let quoted = rename_bindings(call.arguments[0].as_ref());
+    let mut unquoter = Unquoter { env, error: None };
+    let quoted = modify_expression(quoted.as_ref(), &mut unquoter);
+    match unquoter.error {
+        Some(err) => err,
+        None => Object::Quote(Rc::from(quoted)),
+    }
+}
+
+/// the `unquote(...)` call the expression is, if any
+fn as_unquote_call(expression: &dyn Expression) -> Option<&CallExpression> {
+    let call = expression.as_any().downcast_ref::<CallExpression>()?;
+    let function = call.function.as_any().downcast_ref::<Identifier>()?;
+    (function.value == "unquote").then_some(call)
+}
+
+/// evaluates the `unquote` calls in quoted code, remembering the first error
+struct Unquoter<'a> {
+    env: &'a Env,
+    error: Option<Object>,
+}
+
+impl Modifier for Unquoter<'_> {
+    fn expression(&mut self, expression: &dyn Expression) -> Option<Box<dyn Expression>> {
+        if self.error.is_some() {
+            return Some(expression.clone_expression());
+        }
+        let call = as_unquote_call(expression)?;
+        if call.arguments.len() != 1 {
+            self.error = Some(Object::Error(format!(
+                "wrong number of arguments to `unquote`. got={}, want=1",
+                call.arguments.len()
+            )));
+            return Some(expression.clone_expression());
+        }
+        let value = eval_expression(call.arguments[0].as_ref(), self.env);
+        if value.is_error() {
+            self.error = Some(value);
+            return Some(expression.clone_expression());
+        }
+        match value_to_expression(&value, call.token.span) {
+            Ok(expression) => Some(expression),
+            Err(message) => {
+                self.error = Some(Object::Error(message));
+                Some(expression.clone_expression())
+            }
+        }
+    }
+}
+
+/// the code which evaluates to the value, located at `span`
+fn value_to_expression(value: &Object, span: Span) -> Result<Box<dyn Expression>, String> {
+    let token = |token_type: TokenType, literal: String| Token {
+        token_type,
+        literal,
+        span,
+    };
+    match value {
+        Object::Integer(value) if *value < 0 => Ok(Box::new(PrefixExpression {
+            token: token(MINUS, "-".to_string()),
+            operator: "-".to_string(),
+            right: Box::new(IntegerLiteral {
+                token: token(INT, value.unsigned_abs().to_string()),
+                value: value.unsigned_abs() as usize,
+            }),
+        })),
+        Object::Integer(value) => Ok(Box::new(IntegerLiteral {
+            token: token(INT, value.to_string()),
+            value: *value as usize,
+        })),
+        Object::Boolean(value) => Ok(Box::new(Boolean {
+            token: token(if *value { TRUE } else { FALSE }, value.to_string()),
+            value: *value,
+        })),
+        Object::String(value) => Ok(Box::new(StringLiteral {
+            token: token(STRING, value.to_string()),
+            value: value.to_string(),
+        })),
+        Object::Array(elements) => Ok(Box::new(ArrayLiteral {
+            token: token(LBRACKET, "[".to_string()),
+            elements: elements
+                .iter()
+                .map(|element| value_to_expression(element, span))
+                .collect::<Result<_, _>>()?,
+        })),
+        Object::Hash(pairs) => Ok(Box::new(HashLiteral {
+            token: token(LBRACE, "{".to_string()),
+            pairs: pairs
+                .values()
+                .map(|pair| {
+                    Ok((
+                        value_to_expression(&pair.key, span)?,
+                        value_to_expression(&pair.value, span)?,
+                    ))
+                })
+                .collect::<Result<_, String>>()?,
+        })),
+        Object::Quote(node) => Ok(node.clone_expression()),
+        other => Err(format!("cannot unquote {}", other.object_type())),
+    }
+}
+
+/// for hygiene, the names bound in quoted code are renamed to names a program can't contain,
+/// so that the code generated by a macro neither sees nor hides the bindings around its call.
+/// every use of such a name in the quoted code is renamed with it,
+/// except in the code spliced in by `unquote`
+fn rename_bindings(expression: &dyn Expression) -> Box<dyn Expression> {
+    let mut collector = BindingCollector::default();
+    modify_expression(expression, &mut collector);
+    if collector.names.is_empty() {
+        return expression.clone_expression();
+    }
+    let mut renamer = Renamer {
+        names: collector
+            .names
+            .into_iter()
+            .map(|name| {
+                let generated = generate_name(&name);
+                (name, generated)
+            })
+            .collect(),
+    };
+    modify_expression(expression, &mut renamer)
+}
+
+/// a name no identifier of a program can be equal to, since identifiers don't contain digits
+fn generate_name(name: &str) -> String {
+    let count = GENERATED_NAMES.with(|count| {
+        count.set(count.get() + 1);
+        count.get()
+    });
+    format!("{}_{}", name, count)
+}
+
+/// the names bound in quoted code, in the order they are bound
+#[derive(Default)]
+struct BindingCollector {
+    names: Vec<String>,
+}
+
+impl Modifier for BindingCollector {
+    fn expression(&mut self, expression: &dyn Expression) -> Option<Box<dyn Expression>> {
+        as_unquote_call(expression).map(|_| expression.clone_expression())
+    }
+
+    fn binding(&mut self, name: &Identifier) -> Identifier {
+        if !self.names.contains(&name.value) {
+            self.names.push(name.value.clone());
+        }
+        name.clone()
+    }
+}
+
+struct Renamer {
+    names: HashMap<String, String>,
+}
+
+impl Renamer {
+    fn rename(&self, ident: &Identifier) -> Option<Identifier> {
+        let value = self.names.get(&ident.value)?;
+        Some(Identifier {
+            token: ident.token.clone(),
+            value: value.clone(),
+        })
+    }
+}
+
+impl Modifier for Renamer {
+    fn expression(&mut self, expression: &dyn Expression) -> Option<Box<dyn Expression>> {
+        if as_unquote_call(expression).is_some() {
+            return Some(expression.clone_expression());
+        }
+        let ident = expression.as_any().downcast_ref::<Identifier>()?;
+        Some(Box::new(self.rename(ident)?))
+    }
+
+    fn binding(&mut self, name: &Identifier) -> Identifier {
+        self.rename(name).unwrap_or_else(|| name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Program;
+    use crate::compiler::Compiler;
+    use crate::environment::{Env, Environment};
+    use crate::evaluator::eval_program;
+    use crate::lexer::Lexer;
+    use crate::macro_expansion::{define_macros, expand_macros};
+    use crate::object::Object;
+    use crate::parser::Parser;
+    use crate::vm::Vm;
+
+    fn parse(input: &str) -> Program {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program().unwrap();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        program
+    }
+
+    fn expand(input: &str) -> Result<Program, String> {
+        let mut program = parse(input);
+        let env = Environment::new();
+        define_macros(&mut program, &env);
+        expand_macros(&program, &env)
+    }
+
+    #[test]
+    fn test_quote_unquote() {
+        let tests = vec![
+            ("quote(5)", "QUOTE(5)"),
+            ("quote(foobar + barfoo)", "QUOTE((foobar + barfoo))"),
+            ("quote(unquote(4 + 4))", "QUOTE(8)"),
+            ("quote(8 + unquote(4 + 4))", "QUOTE((8 + 8))"),
+            ("let foobar = 8; quote(unquote(foobar))", "QUOTE(8)"),
+            ("quote(unquote(true == false))", "QUOTE(false)"),
+            ("quote(unquote(quote(4 + 4)))", "QUOTE((4 + 4))"),
+            (
+                "let q = quote(4 + 4); quote(unquote(4 + 4) + unquote(q))",
+                "QUOTE((8 + (4 + 4)))",
+            ),
+            ("quote(unquote(0 - 3))", "QUOTE((-3))"),
+            (
+                "quote(unquote([1, \"a\", {true: 2}]))",
+                "QUOTE([1, a, {true:2}])",
+            ),
+            (
+                "quote(unquote(fn(x) { x }))",
+                "ERROR: cannot unquote FUNCTION",
+            ),
+            (
+                "quote(unquote(missing))",
+                "ERROR: identifier not found: missing",
+            ),
+            (
+                "quote(1, 2)",
+                "ERROR: wrong number of arguments to `quote`. got=2, want=1",
+            ),
+            (
+                "quote(unquote())",
+                "ERROR: wrong number of arguments to `unquote`. got=0, want=1",
+            ),
+        ];
+        for (input, expected) in tests {
+            let evaluated = eval_program(&parse(input), &Environment::new());
+            assert_eq!(evaluated.inspect(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_define_macros() {
+        let mut program = parse(
+            "let number = 1;
+let function = fn(x, y) { x + y };
+let mymacro = macro(x, y) { x + y; };",
+        );
+        let env: Env = Environment::new();
+        define_macros(&mut program, &env);
+
+        assert_eq!(program.statements.len(), 2);
+        assert!(env.borrow().get("number").is_none());
+        assert!(env.borrow().get("function").is_none());
+        let mymacro = env.borrow().get("mymacro").unwrap();
+        assert!(matches!(mymacro, Object::Macro(_)));
+        assert_eq!(mymacro.inspect(), "macro(x, y) {\n(x + y)\n}");
+    }
+
+    #[test]
+    fn test_expand_macros() {
+        let tests = vec![
+            (
+                "let infixExpression = macro() { quote(1 + 2); };
+infixExpression();",
+                "(1 + 2)",
+            ),
+            (
+                "let reverse = macro(a, b) { quote(unquote(b) - unquote(a)); };
+reverse(2 + 2, 10 - 5);",
+                "((10 - 5) - (2 + 2))",
+            ),
+            (
+                "let unless = macro(condition, consequence, alternative) {
+  quote(if (!(unquote(condition))) {
+    unquote(consequence);
+  } else {
+    unquote(alternative);
+  });
+};
+unless(10 > 5, puts(\"not greater\"), puts(\"greater\"));",
+                "if(!(10 > 5)) puts(not greater)else puts(greater)",
+            ),
+            // a macro returns code without evaluating its arguments
+            (
+                "let first = macro(a, b) { a }; first(missing, 1 / 0)",
+                "missing",
+            ),
+            // named arguments are quoted too
+            (
+                "let reverse = macro(a, b) { quote(unquote(b) - unquote(a)); }; reverse(b: x, a: y)",
+                "(x - y)",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                expand(input).unwrap().format(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_macro_errors() {
+        let tests = vec![
+            (
+                "let m = macro() { 1 }; m()",
+                "macro must return a quote, got INTEGER in macro m called at line 1, column 25",
+            ),
+            (
+                "let m = macro(a) { quote(unquote(a)) };\nm(1, 2)",
+                "wrong number of arguments: want=1, got=2 in macro m called at line 2, column 2",
+            ),
+            (
+                "let m = macro(a) { quote(unquote(a) + unquote(b)) }; [m(1)]",
+                "identifier not found: b in macro m called at line 1, column 56",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                expand(input).err().as_deref(),
+                Some(expected),
+                "input: {}",
+                input
+            );
+        }
+
+        let program = parse("let f = fn() { macro(x) { x } }; f()");
+        assert_eq!(
+            eval_program(&program, &Environment::new()).inspect(),
+            "ERROR: macros must be defined by a let statement at the top level"
+        );
+        assert_eq!(
+            Compiler::new().compile(&program).err().as_deref(),
+            Some("macros must be defined by a let statement at the top level")
+        );
+        assert_eq!(
+            Compiler::new().compile(&parse("quote(1)")).err().as_deref(),
+            Some("quote is only supported by the evaluator and in macros")
+        );
+    }
+
+    #[test]
+    fn test_hygiene() {
+        let input = "let orElse = macro(value, fallback) {
+  quote(fn() {
+    let result = unquote(value);
+    if (result) { result } else { unquote(fallback) }
+  }())
+};
+let result = \"outer\";
+orElse(false, result)";
+        let program = expand(input).unwrap();
+        // the binding made by the macro doesn't capture the `result` passed to it
+        assert!(!program.format().contains("let result = false"));
+        assert_eq!(
+            eval_program(&program, &Environment::new()).inspect(),
+            "outer"
+        );
+
+        // the expanded program runs on the virtual machine as well
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        assert_eq!(Vm::new().run(&bytecode).unwrap().inspect(), "outer");
+
+        // every expansion binds names of its own
+        let program = expand(
+            "let twice = macro(x) { quote(fn(n) { n + n }(unquote(x))) };
+twice(1) + twice(2)",
+        )
+        .unwrap();
+        let formatted = program.format();
+        let names: Vec<&str> = formatted
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|word| word.starts_with("n_"))
+            .collect();
+        assert_eq!(names.len(), 6);
+        assert_eq!(names[0], names[2]);
+        assert_ne!(names[0], names[3]);
+        assert_eq!(eval_program(&program, &Environment::new()).inspect(), "6");
+    }
+}
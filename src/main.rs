@@ -1,14 +1,309 @@
 extern crate core;
 
-mod ast;
-mod lexer;
-mod parser;
-mod repl;
-mod token;
+use lingo::ast::Program;
+use lingo::bytecode_file;
+use lingo::compiler::{Bytecode, Compiler};
+use lingo::disassembler::disassemble;
+use lingo::dump::{self, DumpError, DumpMode};
+use lingo::environment::Environment;
+use lingo::evaluator;
+use lingo::lexer::Lexer;
+use lingo::limits::{self, Limits};
+use lingo::macro_expansion::{define_macros, expand_macros};
+use lingo::object::Object;
+use lingo::parser::Parser;
+use lingo::repl;
+use lingo::resolver::Resolver;
+use lingo::trace;
+use lingo::vm::Vm;
+use std::path::Path;
+use std::process::exit;
+use std::{env, fs};
+
+const USAGE: &str = "\
+usage: lingo                                     start the REPL
+       lingo [--vm] [<limits>] (<file> | -e <source>)
+                                                 run a program, with the evaluator or the bytecode VM
+       lingo [<limits>] <file.lingoc>            run a compiled program on the bytecode VM
+       lingo --compile [--strip] [-o <out>] (<file> | -e <source>)
+                                                 compile a program to <file>.lingoc, --strip
+                                                 leaves out the line tables
+       lingo (--tokens | --ast | --ast-json | --dump-bytecode) <file>
+       lingo (--tokens | --ast | --ast-json | --dump-bytecode) -e <source>
+
+limits: --fuel <steps>      stop the program after this many evaluation steps
+        --max-depth <calls> how deep calls can nest, 1000 by default
+        --max-heap <bytes>  how much memory the program can keep alive";
+
+/// what the command line asks for
+enum Action {
+    Dump(DumpMode),
+    /// run the program under the limits, on the bytecode VM if `use_vm` is set
+    Run {
+        use_vm: bool,
+        limits: Limits,
+    },
+    /// compile the program to a `.lingoc` file, with line tables if `debug_info` is set
+    Compile {
+        output: String,
+        debug_info: bool,
+    },
+}
+
+/// the program given on the command line
+enum Input {
+    Source(String),
+    /// a `.lingoc` file
+    Compiled(Bytecode),
+}
 
 fn main() {
-    println!("Hello! This is the Lingo programming language!");
-    println!("Feel free to type in commands");
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        println!("Hello! This is the Lingo programming language!");
+        println!("Feel free to type in commands");
+
+        repl::start();
+        return;
+    }
+
+    let (action, input) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(msg) => {
+            eprintln!("{}\n{}", msg, USAGE);
+            exit(2);
+        }
+    };
+
+    let (mode, source) = match (action, input) {
+        (Action::Run { limits, .. }, Input::Compiled(bytecode)) => {
+            limits::set_limits(limits);
+            run_bytecode(&bytecode)
+        }
+        (Action::Dump(DumpMode::Bytecode), Input::Compiled(bytecode)) => {
+            print!("{}", disassemble(&bytecode));
+            return;
+        }
+        (_, Input::Compiled(_)) => {
+            eprintln!(
+                "compiled programs can only be run or dumped with --dump-bytecode\n{}",
+                USAGE
+            );
+            exit(2);
+        }
+        (Action::Run { use_vm, limits }, Input::Source(source)) => {
+            limits::set_limits(limits);
+            run(&source, use_vm)
+        }
+        (Action::Compile { output, debug_info }, Input::Source(source)) => {
+            let bytecode = compile(&parse(&source));
+            if let Err(err) = fs::write(&output, bytecode_file::encode(&bytecode, debug_info)) {
+                eprintln!("could not write {}: {}", output, err);
+                exit(1);
+            }
+            return;
+        }
+        (Action::Dump(mode), Input::Source(source)) => (mode, source),
+    };
+
+    match dump::dump(&source, mode) {
+        Ok(out) => print!("{}", out),
+        Err(DumpError::Parser(errors)) => {
+            for err in errors {
+                eprintln!("parser error: {}", err);
+            }
+            exit(1);
+        }
+        Err(DumpError::Compiler(err)) => {
+            eprintln!("compile error: {}", err);
+            exit(1);
+        }
+    }
+}
+
+/// parse the program, expand its macros and resolve it, exiting with the errors
+/// if it can't be parsed, if a macro fails or if it changes constants.
+/// the warnings of the resolver are printed as well
+fn parse(source: &str) -> Program {
+    let mut parser = Parser::new(Lexer::new(source.to_string()));
+    let mut program = match parser.parse_program() {
+        Some(program) if parser.errors().is_empty() => program,
+        _ => {
+            for err in parser.errors() {
+                eprintln!("parser error: {}", err);
+            }
+            exit(1);
+        }
+    };
+    let macros = Environment::new();
+    define_macros(&mut program, &macros);
+    let program = match expand_macros(&program, &macros) {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("macro error: {}", err);
+            exit(1);
+        }
+    };
+    let mut resolver = Resolver::new();
+    if let Err(errors) = resolver.resolve(&program) {
+        for err in errors {
+            eprintln!("error: {}", err);
+        }
+        exit(1);
+    }
+    for warning in resolver.warnings() {
+        eprintln!("warning: {}", warning);
+    }
+    program
+}
+
+fn compile(program: &Program) -> Bytecode {
+    match Compiler::new().compile(program) {
+        Ok(bytecode) => bytecode,
+        Err(err) => {
+            eprintln!("compile error: {}", err);
+            exit(1);
+        }
+    }
+}
+
+/// run the program and exit, reporting runtime errors and their stack trace on stderr
+fn run(source: &str, use_vm: bool) -> ! {
+    let program = parse(source);
+    if use_vm {
+        run_bytecode(&compile(&program));
+    }
+    if let Object::Error(message) = evaluator::eval_program(&program, &Environment::new()) {
+        eprintln!("{}", Object::Error(message).inspect());
+        eprint!("{}", trace::format_trace(&trace::take()));
+        exit(1);
+    }
+    exit(0);
+}
+
+fn run_bytecode(bytecode: &Bytecode) -> ! {
+    if let Err(err) = Vm::new().run(bytecode) {
+        eprintln!("{}", Object::Error(err).inspect());
+        eprint!("{}", trace::format_trace(&trace::take()));
+        exit(1);
+    }
+    exit(0);
+}
+
+/// parse the command line into an action and the program it applies to
+fn parse_args(args: &[String]) -> Result<(Action, Input), String> {
+    let mut mode = None;
+    let mut use_vm = false;
+    let mut compile = false;
+    let mut strip = false;
+    let mut output = None;
+    let mut input = None;
+    let mut path = None;
+    let mut limits = Limits::default();
+    let mut limited = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let next_mode = match arg.as_str() {
+            "--tokens" => DumpMode::Tokens,
+            "--ast" => DumpMode::Ast,
+            "--ast-json" => DumpMode::AstJson,
+            "--dump-bytecode" => DumpMode::Bytecode,
+            "--vm" => {
+                use_vm = true;
+                continue;
+            }
+            "--compile" => {
+                compile = true;
+                continue;
+            }
+            "--strip" => {
+                strip = true;
+                continue;
+            }
+            "-o" => {
+                let file = iter.next().ok_or("-o expects a file argument")?;
+                output = Some(file.clone());
+                continue;
+            }
+            "--fuel" => {
+                limits.fuel = Some(parse_limit(arg, iter.next())?);
+                limited = true;
+                continue;
+            }
+            "--max-depth" => {
+                limits.max_call_depth = parse_limit(arg, iter.next())?;
+                limited = true;
+                continue;
+            }
+            "--max-heap" => {
+                limits.max_heap_bytes = Some(parse_limit(arg, iter.next())?);
+                limited = true;
+                continue;
+            }
+            "-e" => {
+                let code = iter.next().ok_or("-e expects a source argument")?;
+                input = Some(Input::Source(code.clone()));
+                continue;
+            }
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+            file => {
+                input = Some(read_input(file)?);
+                path = Some(file);
+                continue;
+            }
+        };
+        if mode.replace(next_mode).is_some() {
+            return Err("only one dump mode can be given".to_string());
+        }
+    }
+
+    let input = input.ok_or("missing file or -e <source>")?;
+    if (strip || output.is_some()) && !compile {
+        return Err("--strip and -o can only be used with --compile".to_string());
+    }
+    if limited && (mode.is_some() || compile) {
+        return Err("limits can only be given when running a program".to_string());
+    }
+    let action = match (mode, compile) {
+        (Some(_), _) if use_vm || compile => {
+            return Err("--vm and --compile can't be combined with a dump mode".to_string())
+        }
+        (Some(mode), _) => Action::Dump(mode),
+        (None, true) => {
+            let output = match (output, path) {
+                (Some(output), _) => output,
+                (None, Some(path)) => Path::new(path)
+                    .with_extension(bytecode_file::EXTENSION)
+                    .to_string_lossy()
+                    .into_owned(),
+                (None, None) => return Err("--compile with -e needs -o <file>".to_string()),
+            };
+            Action::Compile {
+                output,
+                debug_info: !strip,
+            }
+        }
+        (None, false) => Action::Run { use_vm, limits },
+    };
+    Ok((action, input))
+}
+
+fn parse_limit<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{} expects a positive number", flag))
+}
 
-    repl::start();
+/// read a source file, or load it as a compiled program if it starts with the `.lingoc` magic bytes
+fn read_input(path: &str) -> Result<Input, String> {
+    let bytes = fs::read(path).map_err(|err| format!("could not read {}: {}", path, err))?;
+    if bytes.starts_with(bytecode_file::MAGIC) {
+        return bytecode_file::decode(&bytes)
+            .map(Input::Compiled)
+            .map_err(|err| format!("could not load {}: {}", path, err));
+    }
+    String::from_utf8(bytes)
+        .map(Input::Source)
+        .map_err(|_| format!("could not read {}: not valid UTF-8", path))
 }
@@ -0,0 +1,359 @@
+use crate::ast::{BlockStatement, Expression, FunctionLiteral, Identifier, Node, Parameter};
+use crate::code::{Instructions, LineTable};
+use crate::environment::Env;
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub type ObjectType = &'static str;
+
+pub const INTEGER_OBJ: ObjectType = "INTEGER";
+pub const BOOLEAN_OBJ: ObjectType = "BOOLEAN";
+pub const STRING_OBJ: ObjectType = "STRING";
+pub const NULL_OBJ: ObjectType = "NULL";
+pub const RETURN_VALUE_OBJ: ObjectType = "RETURN_VALUE";
+pub const BREAK_OBJ: ObjectType = "BREAK";
+pub const CONTINUE_OBJ: ObjectType = "CONTINUE";
+pub const ERROR_OBJ: ObjectType = "ERROR";
+pub const FUNCTION_OBJ: ObjectType = "FUNCTION";
+pub const BUILTIN_OBJ: ObjectType = "BUILTIN";
+pub const ARRAY_OBJ: ObjectType = "ARRAY";
+pub const HASH_OBJ: ObjectType = "HASH";
+pub const COMPILED_FUNCTION_OBJ: ObjectType = "COMPILED_FUNCTION";
+pub const QUOTE_OBJ: ObjectType = "QUOTE";
+pub const MACRO_OBJ: ObjectType = "MACRO";
+pub const STRUCT_OBJ: ObjectType = "STRUCT";
+pub const INSTANCE_OBJ: ObjectType = "INSTANCE";
+pub const RANGE_OBJ: ObjectType = "RANGE";
+
+/// every value produced while evaluating a Lingo program
+#[derive(Clone)]
+pub enum Object {
+    Integer(i64),
+    Boolean(bool),
+    String(Rc<str>),
+    Null,
+    /// wraps the value of a `return` statement so that it can bubble up through nested blocks
+    ReturnValue(Box<Object>),
+    /// produced by `break` and `continue`, bubbling up through nested blocks to the enclosing loop
+    Break,
+    Continue,
+    /// a runtime error, which stops the evaluation of the program
+    Error(String),
+    Function(Rc<Function>),
+    Builtin(Builtin),
+    Array(Rc<Vec<Object>>),
+    Hash(Rc<IndexMap<HashKey, HashPair>>),
+    /// the integers from the start up to but not including the end, which is never below the start.
+    /// created by `range`, the integers are produced as they are iterated over
+    Range(i64, i64),
+    /// the bytecode of a function literal, only found in the constant pool of the compiler
+    CompiledFunction(Rc<CompiledFunction>),
+    /// a function created by the virtual machine
+    Closure(Rc<Closure>),
+    /// a function of the application embedding Lingo, see `Interpreter::register_fn`
+    HostFunction(Rc<HostFunction>),
+    /// code produced by `quote`, only found in the evaluator
+    Quote(Rc<dyn Expression>),
+    /// a macro defined with `let`, only found in the environment of the macro expansion
+    Macro(Rc<Function>),
+    /// a struct declared with `struct`
+    Struct(Rc<Struct>),
+    /// a value of a struct, created by a struct literal
+    Instance(Rc<Instance>),
+    /// a method read from an instance, which is called with the instance as its first argument
+    BoundMethod(Rc<BoundMethod>),
+}
+
+impl Object {
+    pub fn object_type(&self) -> ObjectType {
+        match self {
+            Object::Integer(_) => INTEGER_OBJ,
+            Object::Boolean(_) => BOOLEAN_OBJ,
+            Object::String(_) => STRING_OBJ,
+            Object::Null => NULL_OBJ,
+            Object::ReturnValue(_) => RETURN_VALUE_OBJ,
+            Object::Break => BREAK_OBJ,
+            Object::Continue => CONTINUE_OBJ,
+            Object::Error(_) => ERROR_OBJ,
+            Object::Function(_) => FUNCTION_OBJ,
+            Object::Builtin(_) | Object::HostFunction(_) => BUILTIN_OBJ,
+            Object::Array(_) => ARRAY_OBJ,
+            Object::Hash(_) => HASH_OBJ,
+            Object::Range(..) => RANGE_OBJ,
+            Object::CompiledFunction(_) => COMPILED_FUNCTION_OBJ,
+            // closures are what function literals evaluate to in the virtual machine
+            Object::Closure(_) => FUNCTION_OBJ,
+            Object::Quote(_) => QUOTE_OBJ,
+            Object::Macro(_) => MACRO_OBJ,
+            Object::Struct(_) => STRUCT_OBJ,
+            Object::Instance(_) => INSTANCE_OBJ,
+            Object::BoundMethod(_) => FUNCTION_OBJ,
+        }
+    }
+
+    /// print the value the way the REPL shows it to the user
+    pub fn inspect(&self) -> String {
+        match self {
+            Object::Integer(value) => value.to_string(),
+            Object::Boolean(value) => value.to_string(),
+            Object::String(value) => value.to_string(),
+            Object::Null => "null".to_string(),
+            Object::ReturnValue(value) => value.inspect(),
+            Object::Break => "break".to_string(),
+            Object::Continue => "continue".to_string(),
+            Object::Error(message) => format!("ERROR: {}", message),
+            Object::Function(function) => {
+                let mut params: Vec<String> =
+                    function.parameters.iter().map(|p| p.format()).collect();
+                if let Some(rest) = &function.rest {
+                    params.push(format!("...{}", rest.value));
+                }
+                format!(
+                    "fn({}) {{\n{}\n}}",
+                    params.join(", "),
+                    function.body.format()
+                )
+            }
+            Object::Builtin(builtin) => format!("builtin function {}", builtin.name),
+            Object::HostFunction(function) => format!("host function {}", function.name),
+            Object::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.inspect()).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Object::Hash(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .values()
+                    .map(|pair| format!("{}: {}", pair.key.inspect(), pair.value.inspect()))
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+            Object::Range(start, end) => format!("range({}, {})", start, end),
+            Object::CompiledFunction(function) => format!(
+                "compiled function {}({})",
+                function.name.as_deref().unwrap_or("<anonymous>"),
+                function.format_parameters()
+            ),
+            Object::Closure(closure) => {
+                format!(
+                    "fn({}) {{ <compiled> }}",
+                    closure.function.format_parameters()
+                )
+            }
+            Object::Quote(node) => format!("QUOTE({})", node.format()),
+            Object::Macro(function) => {
+                let params: Vec<String> = function.parameters.iter().map(|p| p.format()).collect();
+                format!(
+                    "macro({}) {{\n{}\n}}",
+                    params.join(", "),
+                    function.body.format()
+                )
+            }
+            Object::Struct(definition) => {
+                format!(
+                    "struct {} {{ {} }}",
+                    definition.name,
+                    definition.fields.join(", ")
+                )
+            }
+            Object::Instance(instance) => {
+                let fields: Vec<String> = instance
+                    .definition
+                    .fields
+                    .iter()
+                    .zip(&instance.values)
+                    .map(|(field, value)| format!("{}: {}", field, value.inspect()))
+                    .collect();
+                format!("{} {{{}}}", instance.definition.name, fields.join(", "))
+            }
+            Object::BoundMethod(method) => {
+                format!("method {}.{}", method.receiver.definition.name, method.name)
+            }
+        }
+    }
+
+    /// the key under which the value is stored in a hash,
+    /// `None` if the value can't be used as a hash key
+    pub fn hash_key(&self) -> Option<HashKey> {
+        match self {
+            Object::Integer(value) => Some(HashKey::Integer(*value)),
+            Object::Boolean(value) => Some(HashKey::Boolean(*value)),
+            Object::String(value) => Some(HashKey::String(value.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, Object::Error(_))
+    }
+
+    /// whether the value stops the evaluation of the expression it is an operand of:
+    /// an error, or the value of a `return`, `break` or `continue` made inside of the operand
+    pub fn stops_evaluation(&self) -> bool {
+        matches!(
+            self,
+            Object::Error(_) | Object::ReturnValue(_) | Object::Break | Object::Continue
+        )
+    }
+
+    /// `null` and `false` are falsy, every other value is truthy
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Object::Null | Object::Boolean(false))
+    }
+}
+
+pub struct Struct {
+    pub name: String,
+    pub fields: Vec<String>,
+    /// added by `impl` statements, the instances created before one see its methods as well
+    pub methods: RefCell<IndexMap<String, Object>>,
+}
+
+/// the fields of an instance can't be assigned to once it is created
+pub struct Instance {
+    pub definition: Rc<Struct>,
+    /// the value of every field, in the order the struct declares them
+    pub values: Vec<Object>,
+}
+
+pub struct BoundMethod {
+    pub receiver: Rc<Instance>,
+    pub name: String,
+    pub method: Object,
+}
+
+pub struct Function {
+    /// the name the function was bound to with `let`, if any
+    pub name: Option<String>,
+    pub parameters: Rc<Vec<Parameter>>,
+    pub rest: Option<Identifier>,
+    pub signature: Signature,
+    pub body: Rc<BlockStatement>,
+    /// the environment the function was defined in, which makes functions closures
+    pub env: Env,
+}
+
+/// what both back ends need to know about the parameters of a function
+/// to match them with the arguments of a call
+#[derive(Clone, Default)]
+pub struct Signature {
+    /// the names of the parameters, a destructured parameter is named after its pattern
+    /// which no named argument can be
+    pub names: Vec<String>,
+    /// how many parameters, from the first one, have no default value
+    pub required: usize,
+    /// whether the arguments after the parameters are collected into an array
+    pub rest: bool,
+    /// where the function is defined, 0 when it is unknown
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Signature {
+    pub fn new(literal: &FunctionLiteral) -> Signature {
+        Signature {
+            names: literal
+                .parameters
+                .iter()
+                .map(|param| param.pattern.format())
+                .collect(),
+            required: literal
+                .parameters
+                .iter()
+                .take_while(|param| param.default.is_none())
+                .count(),
+            rest: literal.rest.is_some(),
+            line: literal.token.span.line,
+            column: literal.token.span.column,
+        }
+    }
+}
+
+/// a function lowered to bytecode by the compiler
+pub struct CompiledFunction {
+    /// the name the function was bound to with `let`, if any
+    pub name: Option<String>,
+    pub instructions: Instructions,
+    pub lines: LineTable,
+    /// the names of the local bindings indexed by their slot, parameters come first
+    pub locals: Vec<String>,
+    pub signature: Signature,
+}
+
+impl CompiledFunction {
+    pub fn parameters(&self) -> &[String] {
+        &self.signature.names
+    }
+
+    /// the parameters separated by commas, followed by the rest parameter if there is one
+    pub fn format_parameters(&self) -> String {
+        let mut params = self.parameters().to_vec();
+        if self.signature.rest {
+            // the rest parameter is the local right after the others
+            params.push(format!("...{}", self.locals[params.len()]));
+        }
+        params.join(", ")
+    }
+}
+
+/// the local bindings of a function call in the virtual machine.
+/// they live on the heap so that closures created during the call keep them alive,
+/// the same way functions keep the environment they were defined in
+pub struct Locals {
+    pub function: Rc<CompiledFunction>,
+    /// `None` until the binding is set
+    pub slots: RefCell<Vec<Option<Object>>>,
+    /// the locals of the call the function was created in
+    pub outer: Option<Rc<Locals>>,
+}
+
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    pub env: Option<Rc<Locals>>,
+}
+
+pub type BuiltinFunction = fn(Vec<Object>) -> Object;
+
+#[derive(Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub func: BuiltinFunction,
+}
+
+/// a Rust function called with the arguments of the call,
+/// its errors are reported as runtime errors mentioning its name
+pub struct HostFunction {
+    pub name: String,
+    pub func: Box<dyn Fn(Vec<Object>) -> Result<Object, String>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(Rc<str>),
+}
+
+/// hashes keep the original key next to the value so that they can be printed
+#[derive(Clone)]
+pub struct HashPair {
+    pub key: Object,
+    pub value: Object,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::object::Object;
+
+    #[test]
+    fn test_string_hash_key() {
+        let hello1 = Object::String("Hello World".into());
+        let hello2 = Object::String("Hello World".into());
+        let diff1 = Object::String("My name is johnny".into());
+
+        assert!(hello1.hash_key() == hello2.hash_key());
+        assert!(hello1.hash_key() != diff1.hash_key());
+        assert!(Object::Integer(1).hash_key() != Object::Boolean(true).hash_key());
+        assert!(Object::Null.hash_key().is_none());
+    }
+}
@@ -1,29 +1,146 @@
 use crate::ast::{
-    Boolean, Expression, ExpressionStatement, Identifier, InfixExpression, IntegerLiteral,
-    LetStatement, PrefixExpression, Program, ReturnStatement, Statement,
+    ArrayLiteral, ArrayPattern, AssignStatement, BlockStatement, Boolean, BreakStatement,
+    CallExpression, ContinueStatement, Expression, ExpressionStatement, ForStatement,
+    FunctionLiteral, HashLiteral, HashPattern, Identifier, IfExpression, ImplStatement,
+    IndexExpression, InfixExpression, IntegerLiteral, LetStatement, LiteralPattern, MacroLiteral,
+    MatchArm, MatchExpression, MemberExpression, Parameter, Pattern, PrefixExpression, Program,
+    ReturnStatement, Statement, StringLiteral, StructLiteral, StructStatement, WhileStatement,
+    WildcardPattern,
 };
 use crate::lexer::Lexer;
+use crate::limits::{self, MAX_NESTING_DEPTH};
 use crate::token::{
-    Token, TokenType, ASSIGN, ASTERISK, BANG, EOF, EQ, FALSE, GT, IDENT, INT, LET, LPAREN, LT,
-    MINUS, NOT_EQ, PLUS, RETURN, RPAREN, SEMICOLON, SLASH, TRUE,
+    Token, TokenType, AMPERSAND, ASSIGN, ASTERISK, ASTERISK_ASSIGN, BANG, BAR, BREAK, CARET, COLON,
+    COMMA, CONST, CONTINUE, DOT, DOT_DOT, ELLIPSIS, ELSE, EOF, EQ, FALSE, FAT_ARROW, FOR, FUNCTION,
+    GT, IDENT, IF, IMPL, IN, INT, LBRACE, LBRACKET, LET, LPAREN, LT, MACRO, MATCH, MINUS,
+    MINUS_ASSIGN, NOT_EQ, PERCENT, PIPE, PLUS, PLUS_ASSIGN, POWER, RBRACE, RBRACKET, RETURN,
+    RPAREN, SEMICOLON, SHIFT_LEFT, SHIFT_RIGHT, SLASH, SLASH_ASSIGN, STRING, STRUCT, SYMBOLS,
+    TILDE, TRUE, WHILE,
 };
 use iota::iota;
-use phf::phf_map;
 use std::collections::HashMap;
-use std::num::ParseIntError;
+use std::rc::Rc;
 
+// the precedences of the operators, from the loosest to the tightest binding.
+// they are powers of two, which leaves room for the operators added by `Parser::register_operator`
 iota! {
-    const LOWEST: u8 = 1 << iota;
+    pub const LOWEST: u16 = 1 << iota;
+        , PIPELINE // x |> f
+        , BIT_OR // |
+        , BIT_XOR // ^
+        , BIT_AND // &
         , EQUALS  // ==
         , LESSGREATER // > or <
+        , SHIFT // << or >>
         , SUM // +
-        , PRODUCT // *
-        , PREFIX // -X or !X
+        , PRODUCT // * or %
+        , EXPONENT // **
+        , PREFIX // -X, !X or ~X
         , CALL // myFunction(X)
+        , INDEX // array[index]
+        , MEMBER // instance.field
 }
 
-type PrefixParseFn = fn(&mut Parser) -> Box<dyn Expression>;
-type InfixParseFn = fn(&mut Parser, Box<dyn Expression>) -> Box<dyn Expression>;
+/// the operators of assignment statements, `x = 1` and the compound ones like `x += 1`
+const ASSIGNMENT_OPERATORS: [TokenType; 5] = [
+    ASSIGN,
+    PLUS_ASSIGN,
+    MINUS_ASSIGN,
+    ASTERISK_ASSIGN,
+    SLASH_ASSIGN,
+];
+
+/// only bindings and the elements of the collections they hold can be assigned to.
+/// the fields of an instance can't, a changed instance is created with a struct literal instead
+fn is_assignable(target: &dyn Expression) -> bool {
+    let any = target.as_any();
+    match any.downcast_ref::<IndexExpression>() {
+        Some(index) => is_assignable(index.left.as_ref()),
+        None => any.is::<Identifier>(),
+    }
+}
+
+/// how a chain of infix operators of the same precedence is grouped,
+/// `a - b - c` is `(a - b) - c` as `-` is left associative
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+/// whether an operator comes before its operand, or between its two operands
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fixity {
+    Prefix,
+    Infix,
+}
+
+/// an operator added to the grammar of Lingo, which is parsed as a call of the function.
+/// `a <> b` is `function(a, b)` for an infix operator `<>`, `@a` is `function(a)` for a prefix one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Operator {
+    /// made of punctuation, for example `<>`
+    pub symbol: TokenType,
+    pub fixity: Fixity,
+    /// how tightly the operator binds its operands, such as `SUM` or `PRODUCT + 1`
+    pub precedence: u16,
+    pub associativity: Associativity,
+    /// the name of the function called with the operands
+    pub function: String,
+}
+
+impl Operator {
+    pub fn infix(
+        symbol: TokenType,
+        precedence: u16,
+        associativity: Associativity,
+        function: &str,
+    ) -> Self {
+        Operator {
+            symbol,
+            fixity: Fixity::Infix,
+            precedence,
+            associativity,
+            function: function.to_string(),
+        }
+    }
+
+    /// the operand binds like the one of `-x` and `!x`
+    pub fn prefix(symbol: TokenType, function: &str) -> Self {
+        Operator {
+            symbol,
+            fixity: Fixity::Prefix,
+            precedence: PREFIX,
+            associativity: Associativity::Right,
+            function: function.to_string(),
+        }
+    }
+}
+
+/// the call an operator added with `Parser::register_operator` stands for
+fn operator_call(
+    token: Token,
+    function: String,
+    arguments: Vec<Box<dyn Expression>>,
+) -> CallExpression {
+    CallExpression {
+        function: Box::new(Identifier {
+            token: Token {
+                token_type: IDENT,
+                literal: function.clone(),
+                span: token.span,
+            },
+            value: function,
+        }),
+        token,
+        arguments,
+        named_arguments: Vec::new(),
+    }
+}
+
+/// parse functions return `None` after recording an error in `Parser::errors`
+type PrefixParseFn = fn(&mut Parser) -> Option<Box<dyn Expression>>;
+type InfixParseFn = fn(&mut Parser, Box<dyn Expression>) -> Option<Box<dyn Expression>>;
 
 pub struct Parser {
     lexer: Lexer,
@@ -35,11 +152,26 @@ pub struct Parser {
     /// called when we encounter the associated token type in infix position
     infix_parse_fns: HashMap<TokenType, InfixParseFn>,
 
-    precedences: HashMap<TokenType, u8>,
+    precedences: HashMap<TokenType, u16>,
+    /// whether an identifier followed by `{` starts a struct literal
+    struct_literals: bool,
+    /// the operators which are not left associative, like `**`
+    associativities: HashMap<TokenType, Associativity>,
+    /// the prefix operators added with `register_operator`
+    prefix_operators: HashMap<TokenType, Operator>,
+    /// the infix operators added with `register_operator`
+    infix_operators: HashMap<TokenType, Operator>,
+    /// how many loops enclose the current token inside the function being parsed,
+    /// `break` and `continue` are only allowed inside of a loop
+    loop_depth: usize,
+    /// how deeply the expressions and blocks enclosing the current token are nested
+    nesting: usize,
+    /// set when the input nests deeper than `MAX_NESTING_DEPTH`, the rest of it is skipped
+    too_deep: bool,
 }
 
 impl Parser {
-    fn new(mut lexer: Lexer) -> Self {
+    pub fn new(mut lexer: Lexer) -> Self {
         let cur_token = lexer.next_token();
         let peek_token = lexer.next_token();
 
@@ -48,99 +180,315 @@ impl Parser {
         precedences.insert(NOT_EQ, EQUALS);
         precedences.insert(LT, LESSGREATER);
         precedences.insert(GT, LESSGREATER);
+        precedences.insert(BAR, BIT_OR);
+        precedences.insert(CARET, BIT_XOR);
+        precedences.insert(AMPERSAND, BIT_AND);
+        precedences.insert(SHIFT_LEFT, SHIFT);
+        precedences.insert(SHIFT_RIGHT, SHIFT);
         precedences.insert(PLUS, SUM);
         precedences.insert(MINUS, SUM);
         precedences.insert(SLASH, PRODUCT);
         precedences.insert(ASTERISK, PRODUCT);
+        precedences.insert(PERCENT, PRODUCT);
+        precedences.insert(POWER, EXPONENT);
+
+        let mut associativities = HashMap::new();
+        associativities.insert(POWER, Associativity::Right);
+        precedences.insert(LPAREN, CALL);
+        precedences.insert(LBRACKET, INDEX);
+        precedences.insert(DOT, MEMBER);
 
-        let parse_identifier_fn: fn(&mut Parser) -> Box<dyn Expression> = |parser: &mut Parser| {
-            Box::new(Identifier {
+        let parse_identifier_fn: PrefixParseFn = |parser: &mut Parser| {
+            let ident = Identifier {
                 token: parser.cur_token.clone(),
                 value: parser.cur_token.literal.clone(),
-            })
+            };
+            if parser.struct_literals && parser.peek_token_is(LBRACE) {
+                return Some(Box::new(parser.parse_struct_literal(ident)?));
+            }
+            Some(Box::new(ident))
         };
 
-        let parse_integer_literal_fn: fn(&mut Parser) -> Box<dyn Expression> =
-            |parser: &mut Parser| {
-                let token = parser.cur_token.clone();
-                let literal = parser
-                    .cur_token
-                    .literal
-                    .parse::<usize>()
-                    .expect("could not parse input as usize");
+        let parse_integer_literal_fn: PrefixParseFn = |parser: &mut Parser| {
+            let token = parser.cur_token.clone();
+            match parser.cur_token.literal.parse::<usize>() {
+                Ok(value) => Some(Box::new(IntegerLiteral { token, value })),
+                Err(_) => {
+                    let msg = format!("could not parse {} as integer", token.literal);
+                    parser.errors.push(msg);
+                    None
+                }
+            }
+        };
 
-                Box::new(IntegerLiteral {
-                    token,
-                    value: literal,
-                })
-            };
+        let parse_string_literal_fn: PrefixParseFn = |parser: &mut Parser| {
+            Some(Box::new(StringLiteral {
+                token: parser.cur_token.clone(),
+                value: parser.cur_token.literal.clone(),
+            }))
+        };
 
-        let parse_prefix_expression_fn: fn(&mut Parser) -> Box<dyn Expression> =
-            |parser: &mut Parser| {
-                let token = parser.cur_token.clone();
-                let operator = parser.cur_token.literal.clone();
+        let parse_prefix_expression_fn: PrefixParseFn = |parser: &mut Parser| {
+            let token = parser.cur_token.clone();
+            let operator = parser.cur_token.literal.clone();
+
+            parser.next_token();
+
+            // `**` binds tighter than the prefix operators, `-2 ** 2` is `-(2 ** 2)` as in math
+            let right = parser.parse_expression(EXPONENT - 1)?;
+
+            Some(Box::new(PrefixExpression {
+                token,
+                operator,
+                right,
+            }))
+        };
+
+        let parse_prefix_boolean_fn: PrefixParseFn = |parser: &mut Parser| {
+            Some(Box::new(Boolean {
+                token: parser.cur_token.clone(),
+                value: parser.cur_token_is(TRUE),
+            }))
+        };
+
+        let parse_prefix_grouped_expression_fn: PrefixParseFn = |parser: &mut Parser| {
+            parser.next_token();
+
+            let struct_literals = std::mem::replace(&mut parser.struct_literals, true);
+            let expression = parser.parse_expression(LOWEST);
+            parser.struct_literals = struct_literals;
+            let expression = expression?;
+
+            if !parser.expect_peek(RPAREN) {
+                return None;
+            }
+            Some(expression)
+        };
+
+        let parse_if_expression_fn: PrefixParseFn = |parser: &mut Parser| {
+            let token = parser.cur_token.clone();
+
+            if !parser.expect_peek(LPAREN) {
+                return None;
+            }
+            parser.next_token();
+            let condition = parser.parse_expression(LOWEST)?;
+            if !parser.expect_peek(RPAREN) || !parser.expect_peek(LBRACE) {
+                return None;
+            }
+            let consequence = parser.parse_block_statement()?;
 
+            let mut alternative = None;
+            if parser.peek_token_is(ELSE) {
                 parser.next_token();
+                if !parser.expect_peek(LBRACE) {
+                    return None;
+                }
+                alternative = Some(parser.parse_block_statement()?);
+            }
 
-                let right = parser
-                    .parse_expression(PREFIX)
-                    .expect("could not parse next token as Expression");
+            Some(Box::new(IfExpression {
+                token,
+                condition,
+                consequence,
+                alternative,
+            }))
+        };
 
-                Box::new(PrefixExpression {
-                    token,
-                    operator,
-                    right,
-                })
-            };
+        let parse_function_literal_fn: PrefixParseFn = |parser: &mut Parser| {
+            let token = parser.cur_token.clone();
+            Some(Box::new(parser.parse_function_literal(token)?))
+        };
 
-        let parse_prefix_boolean_fn: fn(&mut Parser) -> Box<dyn Expression> =
-            |parser: &mut Parser| {
-                Box::new(Boolean {
-                    token: parser.cur_token.clone(),
-                    value: parser.cur_token_is(TRUE),
-                })
-            };
+        let parse_macro_literal_fn: PrefixParseFn = |parser: &mut Parser| {
+            let token = parser.cur_token.clone();
+
+            if !parser.expect_peek(LPAREN) {
+                return None;
+            }
+            // the arguments of a macro are quoted code, which can't be destructured or left out
+            let (function_parameters, rest) = parser.parse_function_parameters(RPAREN)?;
+            if let Some(rest) = rest {
+                parser.errors.push(format!(
+                    "macro parameters must be identifiers, got ...{}",
+                    rest.value
+                ));
+                return None;
+            }
+            let mut parameters = Vec::new();
+            for parameter in function_parameters {
+                match parameter.pattern.as_any().downcast_ref::<Identifier>() {
+                    Some(ident) if parameter.default.is_none() => parameters.push(ident.clone()),
+                    _ => {
+                        parser.errors.push(format!(
+                            "macro parameters must be identifiers, got {}",
+                            parameter.format()
+                        ));
+                        return None;
+                    }
+                }
+            }
+            if !parser.expect_peek(LBRACE) {
+                return None;
+            }
+            let loop_depth = std::mem::replace(&mut parser.loop_depth, 0);
+            let body = parser.parse_block_statement();
+            parser.loop_depth = loop_depth;
+            let body = body?;
+
+            Some(Box::new(MacroLiteral {
+                token,
+                parameters: Rc::new(parameters),
+                body: Rc::new(body),
+            }))
+        };
 
-        let parse_prefix_grouped_expression_fn: fn(&mut Parser) -> Box<dyn Expression> =
-            |parser: &mut Parser| {
+        let parse_match_expression_fn: PrefixParseFn = |parser: &mut Parser| {
+            let token = parser.cur_token.clone();
+
+            parser.next_token();
+            // the `{` after the subject opens the arms, not a struct literal
+            let struct_literals = std::mem::replace(&mut parser.struct_literals, false);
+            let subject = parser.parse_expression(LOWEST);
+            parser.struct_literals = struct_literals;
+            let subject = subject?;
+            if !parser.expect_peek(LBRACE) {
+                return None;
+            }
+            let mut arms = Vec::new();
+            while !parser.peek_token_is(RBRACE) {
                 parser.next_token();
+                arms.push(parser.parse_match_arm()?);
+                // arms are separated by commas, which can be left out after a block
+                if parser.peek_token_is(COMMA) {
+                    parser.next_token();
+                } else if parser.peek_token_is(EOF) {
+                    parser
+                        .errors
+                        .push("expected } to close match, got EOF".to_string());
+                    return None;
+                } else if !parser.peek_token_is(RBRACE) && !parser.cur_token_is(RBRACE) {
+                    parser.peek_error(COMMA);
+                    return None;
+                }
+            }
+            parser.next_token();
+
+            Some(Box::new(MatchExpression {
+                token,
+                subject,
+                arms,
+            }))
+        };
 
-                let expression = parser.parse_expression(LOWEST).unwrap();
+        let parse_array_literal_fn: PrefixParseFn = |parser: &mut Parser| {
+            let token = parser.cur_token.clone();
+            let elements = parser.parse_expression_list(RBRACKET)?;
+            Some(Box::new(ArrayLiteral { token, elements }))
+        };
+
+        let parse_hash_literal_fn: PrefixParseFn = |parser: &mut Parser| {
+            let token = parser.cur_token.clone();
+            let mut pairs = Vec::new();
 
-                if !parser.expect_peek(RPAREN) {
-                    eprintln!("expect RPAREN error.")
+            while !parser.peek_token_is(RBRACE) {
+                parser.next_token();
+                let key = parser.parse_expression(LOWEST)?;
+                if !parser.expect_peek(COLON) {
+                    return None;
                 }
-                expression
-            };
+                parser.next_token();
+                let value = parser.parse_expression(LOWEST)?;
+                pairs.push((key, value));
+
+                if !parser.peek_token_is(RBRACE) && !parser.expect_peek(COMMA) {
+                    return None;
+                }
+            }
+            if !parser.expect_peek(RBRACE) {
+                return None;
+            }
+
+            Some(Box::new(HashLiteral { token, pairs }))
+        };
 
         let mut prefix_parse_fns = HashMap::new();
         prefix_parse_fns.insert(IDENT, parse_identifier_fn);
         prefix_parse_fns.insert(INT, parse_integer_literal_fn);
+        prefix_parse_fns.insert(STRING, parse_string_literal_fn);
         prefix_parse_fns.insert(BANG, parse_prefix_expression_fn);
         prefix_parse_fns.insert(MINUS, parse_prefix_expression_fn);
+        prefix_parse_fns.insert(TILDE, parse_prefix_expression_fn);
         prefix_parse_fns.insert(TRUE, parse_prefix_boolean_fn);
         prefix_parse_fns.insert(FALSE, parse_prefix_boolean_fn);
         prefix_parse_fns.insert(LPAREN, parse_prefix_grouped_expression_fn);
-
-        let parse_infix_expression_fn: fn(&mut Parser, Box<dyn Expression>) -> Box<dyn Expression> =
+        prefix_parse_fns.insert(IF, parse_if_expression_fn);
+        prefix_parse_fns.insert(FUNCTION, parse_function_literal_fn);
+        prefix_parse_fns.insert(MACRO, parse_macro_literal_fn);
+        prefix_parse_fns.insert(MATCH, parse_match_expression_fn);
+        prefix_parse_fns.insert(LBRACKET, parse_array_literal_fn);
+        prefix_parse_fns.insert(LBRACE, parse_hash_literal_fn);
+
+        let parse_infix_expression_fn: InfixParseFn =
             |parser: &mut Parser, left: Box<dyn Expression>| {
                 let token = parser.cur_token.clone();
                 let operator = parser.cur_token.literal.clone();
 
-                let precedence = parser.cur_precedence();
+                let precedence = parser.right_precedence();
 
                 parser.next_token();
 
-                let right = parser
-                    .parse_expression(precedence)
-                    .expect("could not parse next token as Expression");
+                let right = parser.parse_expression(precedence)?;
 
-                Box::new(InfixExpression {
+                Some(Box::new(InfixExpression {
                     token,
                     left,
                     operator,
                     right,
-                })
+                }))
+            };
+
+        let parse_call_expression_fn: InfixParseFn =
+            |parser: &mut Parser, function: Box<dyn Expression>| {
+                let token = parser.cur_token.clone();
+                let (arguments, named_arguments) = parser.parse_call_arguments()?;
+                Some(Box::new(CallExpression {
+                    token,
+                    function,
+                    arguments,
+                    named_arguments,
+                }))
+            };
+
+        let parse_index_expression_fn: InfixParseFn =
+            |parser: &mut Parser, left: Box<dyn Expression>| {
+                let token = parser.cur_token.clone();
+
+                parser.next_token();
+                let index = parser.parse_expression(LOWEST)?;
+                if !parser.expect_peek(RBRACKET) {
+                    return None;
+                }
+
+                Some(Box::new(IndexExpression { token, left, index }))
+            };
+
+        let parse_member_expression_fn: InfixParseFn =
+            |parser: &mut Parser, object: Box<dyn Expression>| {
+                let token = parser.cur_token.clone();
+                if !parser.expect_peek(IDENT) {
+                    return None;
+                }
+                let member = Identifier {
+                    token: parser.cur_token.clone(),
+                    value: parser.cur_token.literal.clone(),
+                };
+
+                Some(Box::new(MemberExpression {
+                    token,
+                    object,
+                    member,
+                }))
             };
 
         let mut infix_parse_fns = HashMap::new();
@@ -148,12 +496,22 @@ impl Parser {
         infix_parse_fns.insert(MINUS, parse_infix_expression_fn);
         infix_parse_fns.insert(SLASH, parse_infix_expression_fn);
         infix_parse_fns.insert(ASTERISK, parse_infix_expression_fn);
+        infix_parse_fns.insert(PERCENT, parse_infix_expression_fn);
+        infix_parse_fns.insert(POWER, parse_infix_expression_fn);
+        infix_parse_fns.insert(BAR, parse_infix_expression_fn);
+        infix_parse_fns.insert(CARET, parse_infix_expression_fn);
+        infix_parse_fns.insert(AMPERSAND, parse_infix_expression_fn);
+        infix_parse_fns.insert(SHIFT_LEFT, parse_infix_expression_fn);
+        infix_parse_fns.insert(SHIFT_RIGHT, parse_infix_expression_fn);
         infix_parse_fns.insert(EQ, parse_infix_expression_fn);
         infix_parse_fns.insert(NOT_EQ, parse_infix_expression_fn);
         infix_parse_fns.insert(LT, parse_infix_expression_fn);
         infix_parse_fns.insert(GT, parse_infix_expression_fn);
+        infix_parse_fns.insert(LPAREN, parse_call_expression_fn);
+        infix_parse_fns.insert(LBRACKET, parse_index_expression_fn);
+        infix_parse_fns.insert(DOT, parse_member_expression_fn);
 
-        Self {
+        let mut parser = Self {
             lexer,
             cur_token,
             peek_token,
@@ -161,7 +519,87 @@ impl Parser {
             prefix_parse_fns,
             infix_parse_fns,
             precedences,
-        }
+            struct_literals: true,
+            associativities,
+            prefix_operators: HashMap::new(),
+            infix_operators: HashMap::new(),
+            loop_depth: 0,
+            nesting: 0,
+            too_deep: false,
+        };
+
+        // `|x, y| x + y` is shorthand for `fn(x, y) { x + y }`
+        let parse_lambda_fn: PrefixParseFn = |parser: &mut Parser| {
+            let token = Token {
+                token_type: FUNCTION,
+                literal: "fn".to_string(),
+                span: parser.cur_token.span,
+            };
+
+            let (parameters, rest) = parser.parse_function_parameters(BAR)?;
+            // the loops around the lambda can't be left from inside of it
+            let loop_depth = std::mem::replace(&mut parser.loop_depth, 0);
+            let body = if parser.peek_token_is(LBRACE) {
+                parser.next_token();
+                parser.parse_block_statement()
+            } else {
+                parser.next_token();
+                let statement_token = parser.cur_token.clone();
+                parser
+                    .parse_expression(LOWEST)
+                    .map(|expression| BlockStatement {
+                        token: statement_token.clone(),
+                        statements: vec![Box::new(ExpressionStatement {
+                            token: statement_token,
+                            expression: Some(expression),
+                        }) as Box<dyn Statement>],
+                    })
+            };
+            parser.loop_depth = loop_depth;
+            let body = body?;
+
+            Some(Box::new(FunctionLiteral {
+                token,
+                parameters: Rc::new(parameters),
+                rest,
+                body: Rc::new(body),
+            }))
+        };
+
+        // `x |> f(y)` is `f(x, y)`, and `x |> f` is `f(x)`
+        let parse_pipeline_fn: InfixParseFn = |parser: &mut Parser, left: Box<dyn Expression>| {
+            let token = parser.cur_token.clone();
+            let precedence = parser.cur_precedence();
+
+            parser.next_token();
+
+            let right = parser.parse_expression(precedence)?;
+
+            match right.as_any().downcast_ref::<CallExpression>() {
+                Some(call) => {
+                    let mut arguments = vec![left];
+                    arguments.extend(call.arguments.iter().cloned());
+                    Some(Box::new(CallExpression {
+                        token,
+                        function: call.function.clone(),
+                        arguments,
+                        named_arguments: call.named_arguments.clone(),
+                    }))
+                }
+                None => Some(Box::new(CallExpression {
+                    token,
+                    function: right,
+                    arguments: vec![left],
+                    named_arguments: Vec::new(),
+                })),
+            }
+        };
+
+        parser.register_prefix(BAR, parse_lambda_fn);
+        parser.precedences.insert(PIPE, PIPELINE);
+        parser.register_infix(PIPE, parse_pipeline_fn);
+
+        parser
     }
 
     fn next_token(&mut self) {
@@ -177,7 +615,83 @@ impl Parser {
         self.infix_parse_fns.insert(token_type, infix_fn);
     }
 
-    fn parse_program(&mut self) -> Option<Program> {
+    /// extend the grammar with the operator, which has to be done before parsing the program.
+    /// fails when the symbol isn't made of punctuation, or the operator already exists.
+    /// the symbol can extend a built-in one, like `<=>` does `<`, but it can't be one
+    /// or the start of one, `*` would split `**` and `x *= 2` in two
+    pub fn register_operator(&mut self, operator: Operator) -> Result<(), String> {
+        let symbol = operator.symbol;
+        if symbol.is_empty()
+            || !symbol
+                .bytes()
+                .all(|ch| ch.is_ascii_punctuation() && !b"\"()[]{},;".contains(&ch))
+        {
+            return Err(format!(
+                "operator symbol {:?} is not made of punctuation",
+                symbol
+            ));
+        }
+        let defined = match operator.fixity {
+            Fixity::Prefix => self.prefix_parse_fns.contains_key(symbol),
+            Fixity::Infix => self.infix_parse_fns.contains_key(symbol),
+        };
+        if defined {
+            return Err(format!("operator {} is already defined", symbol));
+        }
+        if let Some(builtin) = SYMBOLS.iter().find(|builtin| builtin.starts_with(symbol)) {
+            return Err(format!(
+                "operator {} clashes with the built-in token {}",
+                symbol, builtin
+            ));
+        }
+
+        match operator.fixity {
+            Fixity::Prefix => {
+                let parse_prefix_operator_fn: PrefixParseFn = |parser: &mut Parser| {
+                    let token = parser.cur_token.clone();
+                    let operator = &parser.prefix_operators[token.token_type];
+                    let (function, precedence) = (operator.function.clone(), operator.precedence);
+
+                    parser.next_token();
+
+                    let operand = parser.parse_expression(precedence)?;
+
+                    Some(Box::new(operator_call(token, function, vec![operand])))
+                };
+                self.prefix_operators.insert(symbol, operator);
+                self.register_prefix(symbol, parse_prefix_operator_fn);
+            }
+            Fixity::Infix => {
+                let parse_infix_operator_fn: InfixParseFn =
+                    |parser: &mut Parser, left: Box<dyn Expression>| {
+                        let token = parser.cur_token.clone();
+                        let function = parser.infix_operators[token.token_type].function.clone();
+                        let precedence = parser.right_precedence();
+
+                        parser.next_token();
+
+                        let right = parser.parse_expression(precedence)?;
+
+                        Some(Box::new(operator_call(token, function, vec![left, right])))
+                    };
+                self.precedences.insert(symbol, operator.precedence);
+                if operator.associativity != Associativity::Left {
+                    self.associativities.insert(symbol, operator.associativity);
+                }
+                self.infix_operators.insert(symbol, operator);
+                self.register_infix(symbol, parse_infix_operator_fn);
+            }
+        }
+
+        // the tokens read ahead may contain the new operator
+        self.lexer.add_operator(symbol);
+        self.lexer.rewind(self.cur_token.span);
+        self.cur_token = self.lexer.next_token();
+        self.peek_token = self.lexer.next_token();
+        Ok(())
+    }
+
+    pub fn parse_program(&mut self) -> Option<Program> {
         let mut statements: Vec<Box<dyn Statement>> = Vec::new();
         while self.cur_token.token_type != EOF {
             let stat = self.parse_statement();
@@ -191,7 +705,7 @@ impl Parser {
 
     fn parse_statement(&mut self) -> Option<Box<dyn Statement>> {
         match self.cur_token.token_type {
-            token_let if token_let == LET => {
+            token_let if token_let == LET || token_let == CONST => {
                 let let_stat = self.parse_let_statement();
                 match let_stat {
                     None => None,
@@ -205,12 +719,23 @@ impl Parser {
                     Some(return_stat) => Some(Box::new(return_stat)),
                 }
             }
+            token_while if token_while == WHILE => Some(Box::new(self.parse_while_statement()?)),
+            token_struct if token_struct == STRUCT => {
+                Some(Box::new(self.parse_struct_statement()?))
+            }
+            token_impl if token_impl == IMPL => Some(Box::new(self.parse_impl_statement()?)),
+            token_for if token_for == FOR => Some(Box::new(self.parse_for_statement()?)),
+            token_break if token_break == BREAK || token_break == CONTINUE => {
+                self.parse_loop_control_statement()
+            }
             _ => {
-                let expression_stat = self.parse_expression_statement();
-                match expression_stat {
-                    None => None,
-                    Some(expression_stat) => Some(Box::new(expression_stat)),
+                let expression_stat = self.parse_expression_statement()?;
+                if !ASSIGNMENT_OPERATORS.contains(&self.peek_token.token_type) {
+                    return Some(Box::new(expression_stat));
                 }
+                // the expression turns out to be the target of an assignment
+                let target = expression_stat.expression?;
+                Some(Box::new(self.parse_assign_statement(target)?))
             }
         }
     }
@@ -218,28 +743,25 @@ impl Parser {
     fn parse_let_statement(&mut self) -> Option<LetStatement> {
         let cur_token = self.cur_token.clone();
 
-        if !self.expect_peek(IDENT) {
-            return None;
-        }
-
-        let ident_name = Identifier {
-            token: self.cur_token.clone(),
-            value: self.cur_token.literal.clone(),
-        };
+        let pattern = self.parse_binding()?;
 
         if !self.expect_peek(ASSIGN) {
             return None;
         }
 
-        // TODO: we're skipping the expressions until we encounter a semicolon
-        while !self.cur_token_is(SEMICOLON) {
+        self.next_token();
+
+        let value = self.parse_expression(LOWEST);
+
+        if self.peek_token_is(SEMICOLON) {
             self.next_token();
         }
 
         Some(LetStatement {
+            constant: cur_token.token_type == CONST,
             token: cur_token,
-            name: ident_name,
-            value: None, // TODO
+            pattern,
+            value,
         })
     }
 
@@ -248,14 +770,15 @@ impl Parser {
 
         self.next_token();
 
-        // TODO: we're skipping the expressions until we encounter a semicolon
-        while !self.cur_token_is(SEMICOLON) {
+        let return_value = self.parse_expression(LOWEST);
+
+        if self.peek_token_is(SEMICOLON) {
             self.next_token();
         }
 
         Some(ReturnStatement {
             token: cur_token,
-            return_value: None, //TODO
+            return_value,
         })
     }
 
@@ -271,115 +794,755 @@ impl Parser {
 
         Some(ExpressionStatement {
             token: cur_token,
-            expression: expression,
+            expression,
         })
     }
 
-    fn parse_expression(&mut self, precedence: u8) -> Option<Box<dyn Expression>> {
-        let prefix_fn = self.prefix_parse_fns.get(&self.cur_token.token_type);
-        match prefix_fn {
-            None => {
-                self.no_prefix_parse_fn_error(self.cur_token.token_type);
-                None
-            }
-            Some(prefix_fn) => {
-                let mut expression: Option<Box<dyn Expression>>;
-                let left_expression: Box<dyn Expression> = prefix_fn(self);
-                expression = Some(left_expression);
+    /// parse the assignment to the target, the current token is the end of the target
+    fn parse_assign_statement(&mut self, target: Box<dyn Expression>) -> Option<AssignStatement> {
+        self.next_token();
+        let token = self.cur_token.clone();
+        let operator = token.literal.clone();
 
-                while !self.peek_token_is(SEMICOLON) && precedence < self.peek_precedence() {
-                    let infix_fn = self.infix_parse_fns.get(self.peek_token.token_type);
-                    if let Some(infix_fn) = infix_fn {
-                        // TODO why blow code for function call can not compile
-                        // self.next_token();
+        self.next_token();
+        let value = self.parse_expression(LOWEST)?;
 
-                        self.cur_token = self.peek_token.clone();
-                        self.peek_token = self.lexer.next_token();
+        if self.peek_token_is(SEMICOLON) {
+            self.next_token();
+        }
+        // the value is parsed first so that the parsing goes on after the statement
+        if !is_assignable(target.as_ref()) {
+            self.errors
+                .push(format!("cannot assign to {}", target.format()));
+            return None;
+        }
 
-                        let infix_expression: Box<dyn Expression> =
-                            infix_fn(self, expression.unwrap());
-                        expression = Some(infix_expression);
-                    }
-                }
+        Some(AssignStatement {
+            token,
+            target,
+            operator,
+            value,
+        })
+    }
 
-                expression
-            }
+    fn parse_while_statement(&mut self) -> Option<WhileStatement> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(LPAREN) {
+            return None;
+        }
+        self.next_token();
+        let condition = self.parse_expression(LOWEST)?;
+        if !self.expect_peek(RPAREN) || !self.expect_peek(LBRACE) {
+            return None;
+        }
+        let body = self.parse_loop_body()?;
+        if self.peek_token_is(SEMICOLON) {
+            self.next_token();
         }
+
+        Some(WhileStatement {
+            token,
+            condition,
+            body,
+        })
     }
 
-    fn parse_identifier(&self) -> Box<dyn Expression> {
-        Box::new(Identifier {
+    fn parse_for_statement(&mut self) -> Option<ForStatement> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(LPAREN) || !self.expect_peek(IDENT) {
+            return None;
+        }
+        let variable = Identifier {
             token: self.cur_token.clone(),
             value: self.cur_token.literal.clone(),
+        };
+        if !self.expect_peek(IN) {
+            return None;
+        }
+        self.next_token();
+        let iterable = self.parse_expression(LOWEST)?;
+        if !self.expect_peek(RPAREN) || !self.expect_peek(LBRACE) {
+            return None;
+        }
+        let body = self.parse_loop_body()?;
+        if self.peek_token_is(SEMICOLON) {
+            self.next_token();
+        }
+
+        Some(ForStatement {
+            token,
+            variable,
+            iterable,
+            body,
         })
     }
 
-    fn parse_boolean(&self) -> Box<dyn Expression> {
-        Box::new(Boolean {
+    /// parse `struct <name> { <field>, <field> }`
+    fn parse_struct_statement(&mut self) -> Option<StructStatement> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(IDENT) {
+            return None;
+        }
+        let name = Identifier {
             token: self.cur_token.clone(),
-            value: self.cur_token_is(TRUE),
-        })
-    }
+            value: self.cur_token.literal.clone(),
+        };
+        if !self.expect_peek(LBRACE) {
+            return None;
+        }
+        let mut fields: Vec<Identifier> = Vec::new();
+        while !self.peek_token_is(RBRACE) {
+            if !self.expect_peek(IDENT) {
+                return None;
+            }
+            let field = Identifier {
+                token: self.cur_token.clone(),
+                value: self.cur_token.literal.clone(),
+            };
+            if fields.iter().any(|other| other.value == field.value) {
+                self.errors.push(format!(
+                    "duplicate field {} in struct {}",
+                    field.value, name.value
+                ));
+                return None;
+            }
+            fields.push(field);
+            if !self.peek_token_is(RBRACE) && !self.expect_peek(COMMA) {
+                return None;
+            }
+        }
+        self.next_token();
+        if self.peek_token_is(SEMICOLON) {
+            self.next_token();
+        }
 
-    fn cur_token_is(&self, token_type: TokenType) -> bool {
-        self.cur_token.token_type == token_type
+        Some(StructStatement {
+            token,
+            name,
+            fields,
+        })
     }
 
-    fn peek_token_is(&self, token_type: TokenType) -> bool {
-        self.peek_token.token_type == token_type
-    }
+    /// parse `<name> { <field>: <expression>, ... }`, the current token is the name
+    fn parse_struct_literal(&mut self, name: Identifier) -> Option<StructLiteral> {
+        self.next_token();
+        let token = self.cur_token.clone();
 
-    /// enforce the correctness of the order of tokens by checking the type of the next token
-    fn expect_peek(&mut self, token_type: TokenType) -> bool {
-        if self.peek_token_is(token_type) {
+        let mut fields: Vec<(Identifier, Box<dyn Expression>)> = Vec::new();
+        while !self.peek_token_is(RBRACE) {
+            if !self.expect_peek(IDENT) {
+                return None;
+            }
+            let field = Identifier {
+                token: self.cur_token.clone(),
+                value: self.cur_token.literal.clone(),
+            };
+            if fields.iter().any(|(other, _)| other.value == field.value) {
+                self.errors.push(format!(
+                    "field {} of {} is given more than once",
+                    field.value, name.value
+                ));
+                return None;
+            }
+            if !self.expect_peek(COLON) {
+                return None;
+            }
             self.next_token();
-            true
-        } else {
-            self.peek_error(token_type);
-            false
+            let value = self.parse_expression(LOWEST)?;
+            fields.push((field, value));
+            if !self.peek_token_is(RBRACE) && !self.expect_peek(COMMA) {
+                return None;
+            }
         }
-    }
-
-    fn errors(&self) -> &[String] {
-        self.errors.as_slice()
-    }
+        self.next_token();
 
-    fn peek_error(&mut self, token_type: TokenType) {
-        let msg = format!(
-            "expected next token to be {}, got {} instead",
-            token_type, self.peek_token.token_type
-        );
-        self.errors.push(msg)
+        Some(StructLiteral {
+            token,
+            name,
+            fields,
+        })
     }
 
-    fn no_prefix_parse_fn_error(&mut self, token_type: TokenType) {
-        let msg = format!("no prefix parse function for {} found", token_type);
-        self.errors.push(msg)
-    }
+    /// parse `impl <name> { fn <method>(<parameters>) { ... } ... }`
+    fn parse_impl_statement(&mut self) -> Option<ImplStatement> {
+        let token = self.cur_token.clone();
 
-    fn peek_precedence(&self) -> u8 {
-        match self.precedences.get(self.peek_token.token_type) {
-            None => LOWEST,
-            Some(precedence) => *precedence,
+        if !self.expect_peek(IDENT) {
+            return None;
         }
-    }
-
-    fn cur_precedence(&self) -> u8 {
+        let name = Identifier {
+            token: self.cur_token.clone(),
+            value: self.cur_token.literal.clone(),
+        };
+        if !self.expect_peek(LBRACE) {
+            return None;
+        }
+        let mut methods: Vec<(Identifier, FunctionLiteral)> = Vec::new();
+        while !self.peek_token_is(RBRACE) {
+            if !self.expect_peek(FUNCTION) {
+                return None;
+            }
+            let function_token = self.cur_token.clone();
+            if !self.expect_peek(IDENT) {
+                return None;
+            }
+            let method = Identifier {
+                token: self.cur_token.clone(),
+                value: self.cur_token.literal.clone(),
+            };
+            if methods.iter().any(|(other, _)| other.value == method.value) {
+                self.errors.push(format!(
+                    "duplicate method {} in impl {}",
+                    method.value, name.value
+                ));
+                return None;
+            }
+            let function = self.parse_function_literal(function_token)?;
+            methods.push((method, function));
+            if self.peek_token_is(SEMICOLON) {
+                self.next_token();
+            }
+        }
+        self.next_token();
+        if self.peek_token_is(SEMICOLON) {
+            self.next_token();
+        }
+
+        Some(ImplStatement {
+            token,
+            name,
+            methods,
+        })
+    }
+
+    /// parse `(<parameters>) { ... }` after the `fn` token, which is passed in
+    fn parse_function_literal(&mut self, token: Token) -> Option<FunctionLiteral> {
+        if !self.expect_peek(LPAREN) {
+            return None;
+        }
+        let (parameters, rest) = self.parse_function_parameters(RPAREN)?;
+        if !self.expect_peek(LBRACE) {
+            return None;
+        }
+        // the loops around the literal can't be left from inside of the function
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.parse_block_statement();
+        self.loop_depth = loop_depth;
+        let body = body?;
+
+        Some(FunctionLiteral {
+            token,
+            parameters: Rc::new(parameters),
+            rest,
+            body: Rc::new(body),
+        })
+    }
+
+    /// parse the block of a loop, inside of which `break` and `continue` are allowed
+    fn parse_loop_body(&mut self) -> Option<BlockStatement> {
+        self.loop_depth += 1;
+        let body = self.parse_block_statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    /// parse `break` or `continue`, which must be inside of a loop
+    fn parse_loop_control_statement(&mut self) -> Option<Box<dyn Statement>> {
+        let token = self.cur_token.clone();
+        if self.peek_token_is(SEMICOLON) {
+            self.next_token();
+        }
+        if self.loop_depth == 0 {
+            self.errors
+                .push(format!("{} outside of a loop", token.literal));
+            return None;
+        }
+        if token.token_type == BREAK {
+            Some(Box::new(BreakStatement { token }))
+        } else {
+            Some(Box::new(ContinueStatement { token }))
+        }
+    }
+
+    /// parse the statements between `{` and `}`, the current token is the `{`
+    fn parse_block_statement(&mut self) -> Option<BlockStatement> {
+        let nesting = self.nesting;
+        let block = if self.enter_nesting() {
+            self.parse_nested_block_statement()
+        } else {
+            None
+        };
+        self.nesting = nesting;
+        block
+    }
+
+    fn parse_nested_block_statement(&mut self) -> Option<BlockStatement> {
+        let token = self.cur_token.clone();
+        let mut statements: Vec<Box<dyn Statement>> = Vec::new();
+
+        self.next_token();
+
+        while !self.cur_token_is(RBRACE) {
+            if self.too_deep {
+                return None;
+            }
+            if self.cur_token_is(EOF) {
+                self.errors
+                    .push("expected } to close block, got EOF".to_string());
+                return None;
+            }
+            if let Some(stat) = self.parse_statement() {
+                statements.push(stat);
+            }
+            self.next_token();
+        }
+
+        Some(BlockStatement { token, statements })
+    }
+
+    /// parse `(<parameter>, <parameter> = <default>, ...<rest>)`, the current token is the `(`.
+    /// the parameters with a default value come after the others, the rest comes last.
+    /// the parameters of a lambda are parsed the same way, but are closed by a `|` instead of `)`.
+    /// their default values are parsed at the precedence of a bitwise or, so they end at the first `|`:
+    /// `|x = a | b| x` has the default value `a` and the body `b | x`
+    fn parse_function_parameters(
+        &mut self,
+        end: TokenType,
+    ) -> Option<(Vec<Parameter>, Option<Identifier>)> {
+        let mut parameters: Vec<Parameter> = Vec::new();
+        let mut rest = None;
+
+        if self.peek_token_is(end) {
+            self.next_token();
+            return Some((parameters, rest));
+        }
+
+        loop {
+            if self.peek_token_is(ELLIPSIS) {
+                self.next_token();
+                if !self.expect_peek(IDENT) {
+                    return None;
+                }
+                rest = Some(Identifier {
+                    token: self.cur_token.clone(),
+                    value: self.cur_token.literal.clone(),
+                });
+                if !self.peek_token_is(end) {
+                    self.errors
+                        .push("the rest parameter must come last".to_string());
+                    return None;
+                }
+                break;
+            }
+            let pattern = self.parse_binding()?;
+            let mut default = None;
+            if self.peek_token_is(ASSIGN) {
+                self.next_token();
+                self.next_token();
+                // the `|` closing the parameters of a lambda is not a bitwise or
+                let precedence = if end == BAR { BIT_OR } else { LOWEST };
+                default = Some(self.parse_expression(precedence)?);
+            } else if parameters.last().is_some_and(|last| last.default.is_some()) {
+                self.errors.push(format!(
+                    "parameter {} without a default value follows one with a default value",
+                    pattern.format()
+                ));
+                return None;
+            }
+            parameters.push(Parameter { pattern, default });
+            if !self.peek_token_is(COMMA) {
+                break;
+            }
+            self.next_token();
+        }
+
+        if !self.expect_peek(end) {
+            return None;
+        }
+        Some((parameters, rest))
+    }
+
+    /// parse `(<expression>, <identifier>: <expression>)`, the current token is the `(`.
+    /// the named arguments come after the others
+    #[allow(clippy::type_complexity)]
+    fn parse_call_arguments(
+        &mut self,
+    ) -> Option<(
+        Vec<Box<dyn Expression>>,
+        Vec<(Identifier, Box<dyn Expression>)>,
+    )> {
+        let mut arguments = Vec::new();
+        let mut named_arguments: Vec<(Identifier, Box<dyn Expression>)> = Vec::new();
+        while !self.peek_token_is(RPAREN) {
+            self.next_token();
+            if self.cur_token_is(IDENT) && self.peek_token_is(COLON) {
+                let name = Identifier {
+                    token: self.cur_token.clone(),
+                    value: self.cur_token.literal.clone(),
+                };
+                self.next_token();
+                self.next_token();
+                named_arguments.push((name, self.parse_expression(LOWEST)?));
+            } else if let Some((name, _)) = named_arguments.last() {
+                self.errors.push(format!(
+                    "positional argument follows the named argument {}",
+                    name.value
+                ));
+                return None;
+            } else {
+                arguments.push(self.parse_expression(LOWEST)?);
+            }
+            if !self.peek_token_is(RPAREN) && !self.expect_peek(COMMA) {
+                return None;
+            }
+        }
+        self.next_token();
+        Some((arguments, named_arguments))
+    }
+
+    /// parse the identifier, or the array or hash pattern, bound by a `let` or a parameter.
+    /// the peek token is its first token
+    fn parse_binding(&mut self) -> Option<Box<dyn Pattern>> {
+        if self.peek_token_is(LBRACKET) || self.peek_token_is(LBRACE) {
+            self.next_token();
+            return self.parse_pattern();
+        }
+        if !self.expect_peek(IDENT) {
+            return None;
+        }
+        Some(Box::new(Identifier {
+            token: self.cur_token.clone(),
+            value: self.cur_token.literal.clone(),
+        }))
+    }
+
+    /// parse `<pattern> [if <guard>] => <body>`, the current token is the first one of the pattern.
+    /// a body which is not a block is wrapped in one
+    fn parse_match_arm(&mut self) -> Option<MatchArm> {
+        let pattern = self.parse_pattern()?;
+        let mut guard = None;
+        if self.peek_token_is(IF) {
+            self.next_token();
+            self.next_token();
+            guard = Some(self.parse_expression(LOWEST)?);
+        }
+        if !self.expect_peek(FAT_ARROW) {
+            return None;
+        }
+        self.next_token();
+        let body = if self.cur_token_is(LBRACE) {
+            self.parse_block_statement()?
+        } else {
+            let token = self.cur_token.clone();
+            let expression = self.parse_expression(LOWEST)?;
+            BlockStatement {
+                token: token.clone(),
+                statements: vec![Box::new(ExpressionStatement {
+                    token,
+                    expression: Some(expression),
+                })],
+            }
+        };
+        Some(MatchArm {
+            pattern,
+            guard,
+            body,
+        })
+    }
+
+    /// parse the pattern starting at the current token
+    fn parse_pattern(&mut self) -> Option<Box<dyn Pattern>> {
+        let token = self.cur_token.clone();
+        match token.token_type {
+            IDENT if token.literal == "_" => Some(Box::new(WildcardPattern { token })),
+            IDENT => Some(Box::new(Identifier {
+                value: token.literal.clone(),
+                token,
+            })),
+            LBRACKET => self.parse_array_pattern(),
+            LBRACE => self.parse_hash_pattern(),
+            _ => {
+                let value = self.parse_literal()?;
+                Some(Box::new(LiteralPattern { token, value }))
+            }
+        }
+    }
+
+    /// parse an integer, a negative integer, a string or a boolean
+    fn parse_literal(&mut self) -> Option<Box<dyn Expression>> {
+        match self.cur_token.token_type {
+            INT | STRING | TRUE | FALSE => {
+                let prefix_fn = self.prefix_parse_fns[self.cur_token.token_type];
+                prefix_fn(self)
+            }
+            MINUS => {
+                let token = self.cur_token.clone();
+                if !self.expect_peek(INT) {
+                    return None;
+                }
+                let prefix_fn = self.prefix_parse_fns[INT];
+                Some(Box::new(PrefixExpression {
+                    operator: token.literal.clone(),
+                    token,
+                    right: prefix_fn(self)?,
+                }))
+            }
+            token_type => {
+                self.errors
+                    .push(format!("expected a pattern, got {} instead", token_type));
+                None
+            }
+        }
+    }
+
+    /// parse `[<patterns>, ..<identifier>]`, the current token is the `[`
+    fn parse_array_pattern(&mut self) -> Option<Box<dyn Pattern>> {
+        let token = self.cur_token.clone();
+        let mut elements = Vec::new();
+        let mut rest = None;
+        while !self.peek_token_is(RBRACKET) {
+            self.next_token();
+            if self.cur_token_is(DOT_DOT) {
+                if !self.expect_peek(IDENT) {
+                    return None;
+                }
+                rest = Some(Identifier {
+                    token: self.cur_token.clone(),
+                    value: self.cur_token.literal.clone(),
+                });
+                if !self.peek_token_is(RBRACKET) {
+                    self.errors
+                        .push("the rest of an array pattern must come last".to_string());
+                    return None;
+                }
+                break;
+            }
+            elements.push(self.parse_pattern()?);
+            if !self.peek_token_is(RBRACKET) && !self.expect_peek(COMMA) {
+                return None;
+            }
+        }
+        self.next_token();
+        Some(Box::new(ArrayPattern {
+            token,
+            elements,
+            rest,
+        }))
+    }
+
+    /// parse `{<key>: <pattern>, <identifier>, ...}`, the current token is the `{`
+    fn parse_hash_pattern(&mut self) -> Option<Box<dyn Pattern>> {
+        let token = self.cur_token.clone();
+        let mut pairs: Vec<(Box<dyn Expression>, Box<dyn Pattern>)> = Vec::new();
+        while !self.peek_token_is(RBRACE) {
+            self.next_token();
+            if self.cur_token_is(IDENT) {
+                // `{name}` binds the value of the `"name"` key to `name`
+                let name = self.cur_token.literal.clone();
+                if self.peek_token_is(COLON) {
+                    self.errors.push(format!(
+                        "the keys of a hash pattern must be literals, got {} instead",
+                        name
+                    ));
+                    return None;
+                }
+                pairs.push((
+                    Box::new(StringLiteral {
+                        token: self.cur_token.clone(),
+                        value: name.clone(),
+                    }),
+                    Box::new(Identifier {
+                        token: self.cur_token.clone(),
+                        value: name,
+                    }),
+                ));
+                if !self.peek_token_is(RBRACE) && !self.expect_peek(COMMA) {
+                    return None;
+                }
+                continue;
+            }
+            let key = self.parse_literal()?;
+            if !self.expect_peek(COLON) {
+                return None;
+            }
+            self.next_token();
+            pairs.push((key, self.parse_pattern()?));
+            if !self.peek_token_is(RBRACE) && !self.expect_peek(COMMA) {
+                return None;
+            }
+        }
+        self.next_token();
+        Some(Box::new(HashPattern { token, pairs }))
+    }
+
+    /// parse comma separated expressions up to the `end` token,
+    /// used by both call arguments and array literals
+    fn parse_expression_list(&mut self, end: TokenType) -> Option<Vec<Box<dyn Expression>>> {
+        let mut list = Vec::new();
+
+        if self.peek_token_is(end) {
+            self.next_token();
+            return Some(list);
+        }
+
+        self.next_token();
+        list.push(self.parse_expression(LOWEST)?);
+
+        while self.peek_token_is(COMMA) {
+            self.next_token();
+            self.next_token();
+            list.push(self.parse_expression(LOWEST)?);
+        }
+
+        if !self.expect_peek(end) {
+            return None;
+        }
+        Some(list)
+    }
+
+    /// parse an expression on a stack deep enough for it,
+    /// the nesting of the operands of its infix operators counts as well
+    fn parse_expression(&mut self, precedence: u16) -> Option<Box<dyn Expression>> {
+        let nesting = self.nesting;
+        let expression = if self.enter_nesting() {
+            limits::grow_stack(|| self.parse_nested_expression(precedence))
+        } else {
+            None
+        };
+        self.nesting = nesting;
+        expression
+    }
+
+    /// go one level deeper, failing if the input nests too deeply
+    fn enter_nesting(&mut self) -> bool {
+        if self.too_deep {
+            return false;
+        }
+        self.nesting += 1;
+        if self.nesting > MAX_NESTING_DEPTH {
+            self.errors.push(format!(
+                "maximum nesting depth of {} exceeded",
+                MAX_NESTING_DEPTH
+            ));
+            self.too_deep = true;
+            while !self.cur_token_is(EOF) {
+                self.next_token();
+            }
+            return false;
+        }
+        true
+    }
+
+    fn parse_nested_expression(&mut self, precedence: u16) -> Option<Box<dyn Expression>> {
+        let prefix_fn = self
+            .prefix_parse_fns
+            .get(&self.cur_token.token_type)
+            .copied();
+        match prefix_fn {
+            None => {
+                self.no_prefix_parse_fn_error(self.cur_token.token_type);
+                None
+            }
+            Some(prefix_fn) => {
+                let mut expression = prefix_fn(self)?;
+
+                while !self.peek_token_is(SEMICOLON) && precedence < self.peek_precedence() {
+                    let infix_fn = self
+                        .infix_parse_fns
+                        .get(self.peek_token.token_type)
+                        .copied();
+                    match infix_fn {
+                        None => return Some(expression),
+                        Some(infix_fn) => {
+                            if !self.enter_nesting() {
+                                return None;
+                            }
+                            self.next_token();
+                            expression = infix_fn(self, expression)?;
+                        }
+                    }
+                }
+
+                Some(expression)
+            }
+        }
+    }
+
+    fn cur_token_is(&self, token_type: TokenType) -> bool {
+        self.cur_token.token_type == token_type
+    }
+
+    fn peek_token_is(&self, token_type: TokenType) -> bool {
+        self.peek_token.token_type == token_type
+    }
+
+    /// enforce the correctness of the order of tokens by checking the type of the next token
+    fn expect_peek(&mut self, token_type: TokenType) -> bool {
+        if self.peek_token_is(token_type) {
+            self.next_token();
+            true
+        } else {
+            self.peek_error(token_type);
+            false
+        }
+    }
+
+    pub fn errors(&self) -> &[String] {
+        self.errors.as_slice()
+    }
+
+    fn peek_error(&mut self, token_type: TokenType) {
+        let msg = format!(
+            "expected next token to be {}, got {} instead",
+            token_type, self.peek_token.token_type
+        );
+        self.errors.push(msg)
+    }
+
+    fn no_prefix_parse_fn_error(&mut self, token_type: TokenType) {
+        let msg = format!("no prefix parse function for {} found", token_type);
+        self.errors.push(msg)
+    }
+
+    fn peek_precedence(&self) -> u16 {
+        match self.precedences.get(self.peek_token.token_type) {
+            None => LOWEST,
+            Some(precedence) => *precedence,
+        }
+    }
+
+    fn cur_precedence(&self) -> u16 {
         match self.precedences.get(self.cur_token.token_type) {
             None => LOWEST,
             Some(precedence) => *precedence,
         }
     }
+
+    /// the precedence the right operand of the current infix operator is parsed with.
+    /// it is lower for a right associative operator, so that the operand takes in
+    /// the following operators of the same precedence
+    fn right_precedence(&self) -> u16 {
+        let precedence = self.cur_precedence();
+        match self.associativities.get(self.cur_token.token_type) {
+            Some(Associativity::Right) => precedence - 1,
+            _ => precedence,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::ast::{
-        Expression, ExpressionStatement, Identifier, InfixExpression, IntegerLiteral, LetStatement,
-        Node, PrefixExpression, Program, ReturnStatement, Statement,
+        CallExpression, Expression, ExpressionStatement, FunctionLiteral, HashLiteral, Identifier,
+        IfExpression, InfixExpression, IntegerLiteral, LetStatement, MacroLiteral, MatchExpression,
+        Node, PrefixExpression, ReturnStatement, Statement, StringLiteral,
     };
     use crate::lexer::Lexer;
-    use crate::parser::Parser;
+    use crate::limits::MAX_NESTING_DEPTH;
+    use crate::parser::{Associativity, Operator, Parser, LESSGREATER, PREFIX, PRODUCT, SUM};
 
     #[test]
     fn test_let_statements() {
@@ -398,7 +1561,10 @@ mod tests {
         let = 10;
         let 838383;
         "#;
-        lingo_source_code_parser(input, 3);
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        parser.parse_program();
+        assert!(!parser.errors().is_empty());
     }
 
     #[test]
@@ -409,7 +1575,7 @@ mod tests {
         return 993322;
         "#;
 
-        let mut lexer = Lexer::new(input.to_string());
+        let lexer = Lexer::new(input.to_string());
         let mut parser = Parser::new(lexer);
 
         let program = parser.parse_program();
@@ -446,7 +1612,7 @@ mod tests {
     fn test_identifier_expression() {
         let code = "foobar;";
 
-        let mut lexer = Lexer::new(code.to_string());
+        let lexer = Lexer::new(code.to_string());
         let mut parser = Parser::new(lexer);
 
         let program = parser.parse_program().unwrap();
@@ -478,7 +1644,7 @@ mod tests {
     fn test_integer_literal_expression() {
         let code = "5;";
 
-        let mut lexer = Lexer::new(code.to_string());
+        let lexer = Lexer::new(code.to_string());
         let mut parser = Parser::new(lexer);
 
         let program = parser.parse_program().unwrap();
@@ -511,7 +1677,7 @@ mod tests {
         let prefixs = vec![("!5;", "!", 5_usize), ("-15;", "-", 15)];
 
         for (input, operator, value) in prefixs {
-            let mut lexer = Lexer::new(input.to_string());
+            let lexer = Lexer::new(input.to_string());
             let mut parser = Parser::new(lexer);
 
             let program = parser.parse_program().unwrap();
@@ -537,7 +1703,7 @@ mod tests {
 
             assert_eq!(expression.operator, operator);
 
-            assert!(test_integer_literal(&expression.right, value));
+            assert!(test_integer_literal(expression.right.as_ref(), value));
         }
     }
 
@@ -555,7 +1721,7 @@ mod tests {
         ];
 
         for (input, left_value, operator, right_value) in infixs {
-            let mut lexer = Lexer::new(input.to_string());
+            let lexer = Lexer::new(input.to_string());
             let mut parser = Parser::new(lexer);
 
             let program = parser.parse_program().unwrap();
@@ -579,11 +1745,11 @@ mod tests {
                 .downcast_ref::<InfixExpression>()
                 .expect("expression not PrefixExpression");
 
-            assert!(test_integer_literal(&expression.left, left_value));
+            assert!(test_integer_literal(expression.left.as_ref(), left_value));
 
             assert_eq!(expression.operator, operator);
 
-            assert!(test_integer_literal(&expression.right, right_value));
+            assert!(test_integer_literal(expression.right.as_ref(), right_value));
         }
     }
 
@@ -608,22 +1774,588 @@ mod tests {
                 "3 + 4 * 5 == 3 * 1 + 4 * 5",
                 "((3 + (4 * 5)) == ((3 * 1) + (4 * 5)))",
             ),
+            ("a + add(b * c) + d", "((a + add((b * c))) + d)"),
+            (
+                "add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8))",
+                "add(a, b, 1, (2 * 3), (4 + 5), add(6, (7 * 8)))",
+            ),
+            (
+                "a * [1, 2, 3, 4][b * c] * d",
+                "((a * ([1, 2, 3, 4][(b * c)])) * d)",
+            ),
+            (
+                "add(a * b[2], b[1], 2 * [1, 2][1])",
+                "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))",
+            ),
+            ("a % b * c", "((a % b) * c)"),
+            ("a + b % c", "(a + (b % c))"),
+            ("a ** b ** c", "(a ** (b ** c))"),
+            ("a * b ** c", "(a * (b ** c))"),
+            ("a ** b * c", "((a ** b) * c)"),
+            ("-a ** b", "(-(a ** b))"),
+            ("-a * b ** c", "((-a) * (b ** c))"),
+            ("(-a) ** b", "((-a) ** b)"),
+            ("~a ** b", "(~(a ** b))"),
+            ("-a[0] ** b.c", "(-((a[0]) ** (b.c)))"),
+            ("a ** -b ** c", "(a ** (-(b ** c)))"),
+            ("a ** b[0] ** c", "(a ** ((b[0]) ** c))"),
+            ("a - b - c ** d ** e", "((a - b) - (c ** (d ** e)))"),
+            ("a | b ^ c & d", "(a | (b ^ (c & d)))"),
+            ("a & b | c ^ d", "((a & b) | (c ^ d))"),
+            ("a & b == c", "(a & (b == c))"),
+            ("a << b + c < d >> e", "((a << (b + c)) < (d >> e))"),
+            ("a << b << c", "((a << b) << c)"),
+            ("~a & ~-b", "((~a) & (~(-b)))"),
+            ("a | b |> f", "f((a | b))"),
         ];
         for (input, expected) in expressions {
-            let mut lexer = Lexer::new(input.to_string());
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            let program = parser.parse_program().unwrap();
+            check_parser_errors(&parser);
+
+            assert_eq!(program.format(), expected);
+        }
+    }
+
+    #[test]
+    fn test_let_statement_values() {
+        let tests = vec![
+            ("let x = 5;", "let x = 5;"),
+            ("let y = true;", "let y = true;"),
+            ("let foobar = y", "let foobar = y;"),
+            ("return x + 1;", "return (x + 1);"),
+        ];
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            let program = parser.parse_program().unwrap();
+            check_parser_errors(&parser);
+
+            assert_eq!(program.statements.len(), 1);
+            assert_eq!(program.format(), expected);
+        }
+    }
+
+    #[test]
+    fn test_if_else_expression() {
+        let input = "if (x < y) { x } else { y }";
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+
+        let program = parser.parse_program().unwrap();
+        check_parser_errors(&parser);
+
+        let expression = first_expression(&program.statements)
+            .as_any()
+            .downcast_ref::<IfExpression>()
+            .expect("expression not IfExpression");
+
+        assert_eq!(expression.condition.format(), "(x < y)");
+        assert_eq!(expression.consequence.format(), "x");
+        assert_eq!(expression.alternative.as_ref().unwrap().format(), "y");
+    }
+
+    #[test]
+    fn test_function_literal_parsing() {
+        let tests = vec![
+            ("fn() {};", vec![]),
+            ("fn(x) {};", vec!["x"]),
+            ("fn(x, y, z) { x + y; }", vec!["x", "y", "z"]),
+            (
+                "fn([a, ..rest], {name, \"k\": [v]}) {}",
+                vec!["[a, ..rest]", "{name, k:[v]}"],
+            ),
+            (
+                "fn(x, y = 10, [a] = [x + 1]) {}",
+                vec!["x", "y = 10", "[a] = [(x + 1)]"],
+            ),
+        ];
+        for (input, expected_params) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            let program = parser.parse_program().unwrap();
+            check_parser_errors(&parser);
+
+            let function = first_expression(&program.statements)
+                .as_any()
+                .downcast_ref::<FunctionLiteral>()
+                .expect("expression not FunctionLiteral");
+
+            let params: Vec<String> = function.parameters.iter().map(|p| p.format()).collect();
+            assert_eq!(params, expected_params);
+        }
+    }
+
+    #[test]
+    fn test_macro_literal_parsing() {
+        let input = "macro(x, y) { x + y; }";
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+
+        let program = parser.parse_program().unwrap();
+        check_parser_errors(&parser);
+
+        let literal = first_expression(&program.statements)
+            .as_any()
+            .downcast_ref::<MacroLiteral>()
+            .expect("expression not MacroLiteral");
+
+        let params: Vec<&str> = literal
+            .parameters
+            .iter()
+            .map(|p| p.value.as_str())
+            .collect();
+        assert_eq!(params, vec!["x", "y"]);
+        assert_eq!(literal.body.format(), "(x + y)");
+    }
+
+    #[test]
+    fn test_call_expression_parsing() {
+        let input = "add(1, 2 * 3, 4 + 5);";
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+
+        let program = parser.parse_program().unwrap();
+        check_parser_errors(&parser);
+
+        let call = first_expression(&program.statements)
+            .as_any()
+            .downcast_ref::<CallExpression>()
+            .expect("expression not CallExpression");
+
+        assert_eq!(call.function.format(), "add");
+        let args: Vec<String> = call.arguments.iter().map(|a| a.format()).collect();
+        assert_eq!(args, vec!["1", "(2 * 3)", "(4 + 5)"]);
+    }
+
+    #[test]
+    fn test_parameters_and_named_arguments_parsing() {
+        let tests = vec![
+            ("fn(x, ...rest) { rest }", "fn(x, ...rest) rest"),
+            ("fn(...rest) { }", "fn(...rest) "),
+            ("fn(x = 1, ...rest) { }", "fn(x = 1, ...rest) "),
+            ("f(1, y: 2 * 3, z: g(a: 1))", "f(1, y: (2 * 3), z: g(a: 1))"),
+            ("f(x: 1,)", "f(x: 1)"),
+            // a hash literal argument is not a named argument
+            ("f({x: 1})", "f({x:1})"),
+        ];
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            let program = parser.parse_program().unwrap();
+            check_parser_errors(&parser);
+
+            assert_eq!(first_expression(&program.statements).format(), expected);
+        }
+    }
+
+    #[test]
+    fn test_pipeline_and_lambda_parsing() {
+        let tests = vec![
+            ("xs |> f", "f(xs)"),
+            ("xs |> filter(f) |> map(g, 1)", "map(filter(xs, f), g, 1)"),
+            ("1 + 2 |> f(y: 3)", "f((1 + 2), y: 3)"),
+            // the pipeline binds looser than any other operator
+            ("a |> f == b", "(f == b)(a)"),
+            ("|x| x * 2", "fn(x) (x * 2)"),
+            ("|| 1", "fn() 1"),
+            ("|x, y = 1, ...rest| { x; y }", "fn(x, y = 1, ...rest) xy"),
+            ("|[a, b]| a", "fn([a, b]) a"),
+            ("|x = 1| x | 1", "fn(x = 1) (x | 1)"),
+            ("|x = a | b| x | 1", "fn(x = a) ((b | x) | 1)"),
+            ("|x = (a | b)| x | 1", "fn(x = (a | b)) (x | 1)"),
+            ("|x = 1| x & 1", "fn(x = 1) (x & 1)"),
+            ("f(|x = 1| x, |y| y | 1)", "f(fn(x = 1) x, fn(y) (y | 1))"),
+            ("xs |> map(|x| x + 1)", "map(xs, fn(x) (x + 1))"),
+            ("|x| x |> f", "fn(x) f(x)"),
+        ];
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            let program = parser.parse_program().unwrap();
+            check_parser_errors(&parser);
+
+            assert_eq!(first_expression(&program.statements).format(), expected);
+        }
+    }
+
+    #[test]
+    fn test_registered_operators() {
+        let operators = || {
+            vec![
+                Operator::infix("<>", SUM, Associativity::Left, "concat"),
+                Operator::infix("::", PRODUCT + 1, Associativity::Right, "cons"),
+                Operator::infix("<=>", LESSGREATER + 1, Associativity::Left, "compare"),
+                Operator::prefix("@", "deref"),
+                Operator::prefix("<>", "flip"),
+            ]
+        };
+        let tests = vec![
+            ("a <> b <> c", "concat(concat(a, b), c)"),
+            ("a <> b * c", "concat(a, (b * c))"),
+            ("a :: b :: c * d", "(cons(a, cons(b, c)) * d)"),
+            ("a < b <=> c", "(a < compare(b, c))"),
+            ("a<=>b + c", "compare(a, (b + c))"),
+            ("@a :: -b", "cons(deref(a), (-b))"),
+            ("<> a <> b", "concat(flip(a), b)"),
+        ];
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input.to_string()));
+            for operator in operators() {
+                parser.register_operator(operator).unwrap();
+            }
+
+            let program = parser.parse_program().unwrap();
+            check_parser_errors(&parser);
+
+            assert_eq!(first_expression(&program.statements).format(), expected);
+        }
+
+        let tests = vec![
+            (
+                Operator::infix("+", SUM, Associativity::Left, "add"),
+                "operator + is already defined",
+            ),
+            (
+                Operator::prefix("*", "f"),
+                "operator * clashes with the built-in token *",
+            ),
+            (
+                Operator::prefix("=", "f"),
+                "operator = clashes with the built-in token =",
+            ),
+            (
+                Operator::infix("!", SUM, Associativity::Left, "f"),
+                "operator ! clashes with the built-in token !",
+            ),
+            (
+                Operator::infix(":", SUM, Associativity::Left, "f"),
+                "operator : clashes with the built-in token :",
+            ),
+            (
+                Operator::infix("..", SUM, Associativity::Left, "f"),
+                "operator .. clashes with the built-in token ..",
+            ),
+            (
+                Operator::prefix("|", "abs"),
+                "operator | is already defined",
+            ),
+            (
+                Operator::prefix("", "f"),
+                "operator symbol \"\" is not made of punctuation",
+            ),
+            (
+                Operator::infix("and", PREFIX, Associativity::Left, "f"),
+                "operator symbol \"and\" is not made of punctuation",
+            ),
+            (
+                Operator::infix("=(", PREFIX, Associativity::Left, "f"),
+                "operator symbol \"=(\" is not made of punctuation",
+            ),
+        ];
+        for (operator, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(String::new()));
+            assert_eq!(
+                parser.register_operator(operator),
+                Err(expected.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_string_and_hash_literal_parsing() {
+        let input = r#"{"one": 1, "two": "hello world"}"#;
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+
+        let program = parser.parse_program().unwrap();
+        check_parser_errors(&parser);
+
+        let hash = first_expression(&program.statements)
+            .as_any()
+            .downcast_ref::<HashLiteral>()
+            .expect("expression not HashLiteral");
+
+        assert_eq!(hash.pairs.len(), 2);
+        let (key, value) = &hash.pairs[1];
+        assert_eq!(key.format(), "two");
+        let value = value
+            .as_any()
+            .downcast_ref::<StringLiteral>()
+            .expect("expression not StringLiteral");
+        assert_eq!(value.value, "hello world");
+    }
+
+    #[test]
+    fn test_assign_statements() {
+        let tests = vec![
+            ("x = 5;", "x = 5;"),
+            ("x += y * 2", "x += (y * 2);"),
+            ("a[0] -= 1; a", "(a[0]) -= 1;a"),
+            ("m[i][j] *= 2;", "((m[i])[j]) *= 2;"),
+            ("h[\"k\"] /= 2;", "(h[k]) /= 2;"),
+        ];
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            let program = parser.parse_program().unwrap();
+            check_parser_errors(&parser);
+
+            assert_eq!(program.format(), expected);
+        }
+    }
+
+    #[test]
+    fn test_loop_parsing() {
+        let tests = vec![
+            ("while (x < 10) { x }", "while(x < 10) x"),
+            ("for (x in [1, 2]) { puts(x); }", "for(x in [1, 2]) puts(x)"),
+            (
+                "while (true) { if (x) { break; } else { continue; } };",
+                "whiletrue ifx break;else continue;",
+            ),
+        ];
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
             let mut parser = Parser::new(lexer);
 
             let program = parser.parse_program().unwrap();
             check_parser_errors(&parser);
 
+            assert_eq!(program.statements.len(), 1);
             assert_eq!(program.format(), expected);
         }
     }
 
+    #[test]
+    fn test_match_expression_parsing() {
+        let tests = vec![
+            (
+                "match (x) { 0 => \"zero\", -1 => x, [a, ..rest] => a, {\"k\": [_, v]} => v, n if n > 10 => n, _ => { 1; 2 } }",
+                "match x { 0 => zero, (-1) => x, [a, ..rest] => a, {k:[_, v]} => v, n if (n > 10) => n, _ => 12 }",
+            ),
+            // the comma after a block can be left out
+            (
+                "match (x) { true => { 1 } false => 2, }",
+                "match x { true => 1, false => 2 }",
+            ),
+            ("match (x) { }", "match x {  }"),
+        ];
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            let program = parser.parse_program().unwrap();
+            check_parser_errors(&parser);
+
+            let expression = first_expression(&program.statements)
+                .as_any()
+                .downcast_ref::<MatchExpression>()
+                .expect("expression not MatchExpression");
+            assert_eq!(expression.format(), expected);
+        }
+    }
+
+    #[test]
+    fn test_destructuring_let_parsing() {
+        let tests = vec![
+            ("let [a, b, ..rest] = xs;", "let [a, b, ..rest] = xs;"),
+            ("const {name, age} = person;", "const {name, age} = person;"),
+            (
+                "let {\"point\": [x, _], 1: one} = h;",
+                "let {point:[x, _], 1:one} = h;",
+            ),
+        ];
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            let program = parser.parse_program().unwrap();
+            check_parser_errors(&parser);
+
+            assert_eq!(program.statements.len(), 1);
+            let stat = program.statements[0]
+                .as_any()
+                .downcast_ref::<LetStatement>()
+                .expect("statement not LetStatement");
+            assert!(stat.name().is_none());
+            assert_eq!(stat.format(), expected);
+        }
+    }
+
+    #[test]
+    fn test_struct_parsing() {
+        let tests = vec![
+            ("struct Point { x, y, }", "struct Point { x, y }"),
+            (
+                "impl Point { fn len(self) { self.x } fn scale(self, k = 2) { Point { x: self.x * k } } }",
+                "impl Point { fn len(self) (self.x) fn scale(self, k = 2) Point {x: ((self.x) * k)} }",
+            ),
+            ("Point { x: 1, y: -2 }", "Point {x: 1, y: (-2)}"),
+            ("p.x.y + q.x", "(((p.x).y) + (q.x))"),
+            ("p.items[0].name", "(((p.items)[0]).name)"),
+            ("-p.len()", "(-(p.len)())"),
+            ("xs[0].x(1)(2)", "((xs[0]).x)(1)(2)"),
+            // a brace after the subject of a `match` opens its arms
+            ("match p { q => q.x }", "match p { q => (q.x) }"),
+            ("match (P { x: 1 }) { q => q }", "match P {x: 1} { q => q }"),
+        ];
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            let program = parser.parse_program().unwrap();
+            check_parser_errors(&parser);
+
+            assert_eq!(program.statements.len(), 1);
+            assert_eq!(program.statements[0].format(), expected);
+        }
+    }
+
+    #[test]
+    fn test_nesting_depth() {
+        let depth = MAX_NESTING_DEPTH - 1;
+        let tests = vec![
+            format!("{}{}", "[".repeat(depth), "]".repeat(depth)),
+            vec!["1"; depth].join(" + "),
+            format!(
+                "{}1{}",
+                "if (x) { ".repeat(depth / 2),
+                " }".repeat(depth / 2)
+            ),
+        ];
+        for input in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program().unwrap();
+            check_parser_errors(&parser);
+        }
+
+        // the rest of the input is skipped after the error
+        let depth = 100_000;
+        let tests = vec![
+            "[".repeat(depth),
+            format!("{}{}", "[".repeat(depth), "]".repeat(depth)),
+            vec!["1"; depth].join(" + "),
+            format!("{}1{}", "if (x) { ".repeat(depth), " }".repeat(depth)),
+            format!("{}1", "-".repeat(depth)),
+        ];
+        for input in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(parser.errors(), ["maximum nesting depth of 1000 exceeded"]);
+        }
+    }
+
+    #[test]
+    fn test_invalid_assignment_targets() {
+        let tests = vec![
+            ("p.x = 5", vec!["cannot assign to (p.x)"]),
+            ("p.x += 1", vec!["cannot assign to (p.x)"]),
+            ("p.xs[0] = 1", vec!["cannot assign to ((p.xs)[0])"]),
+            ("let p = 1; f() += 1; p", vec!["cannot assign to f()"]),
+            (
+                "1 = 2; [a] = [1];",
+                vec!["cannot assign to 1", "cannot assign to [a]"],
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input.to_string()));
+            parser.parse_program();
+            assert_eq!(parser.errors(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_parser_errors_do_not_panic() {
+        let tests = vec![
+            ("1 +", "no prefix parse function for EOF found"),
+            ("(1", "expected next token to be ), got EOF instead"),
+            ("fn(x { x }", "expected next token to be ), got { instead"),
+            ("if (x) { x", "expected } to close block, got EOF"),
+            (
+                "99999999999999999999999",
+                "could not parse 99999999999999999999999 as integer",
+            ),
+            ("break;", "break outside of a loop"),
+            (
+                "while (true) { fn() { continue; } }",
+                "continue outside of a loop",
+            ),
+            (
+                "for (x of xs) { }",
+                "expected next token to be IN, got IDENT instead",
+            ),
+            (
+                "match (x) { [..rest, a] => 1 }",
+                "the rest of an array pattern must come last",
+            ),
+            (
+                "match (x) { 1 + 2 => 3 }",
+                "expected next token to be =>, got + instead",
+            ),
+            (
+                "match (x) { 1 => 2 3 => 4 }",
+                "expected next token to be ,, got INT instead",
+            ),
+            (
+                "match (x) { fn => 1 }",
+                "expected a pattern, got FUNCTION instead",
+            ),
+            ("match (x) { 1 => 2", "expected } to close match, got EOF"),
+            ("match (x) { 1 => 2,", "expected a pattern, got EOF instead"),
+            (
+                "let {name: n} = person;",
+                "the keys of a hash pattern must be literals, got name instead",
+            ),
+            (
+                "let 1 = x;",
+                "expected next token to be IDENT, got INT instead",
+            ),
+            (
+                "macro([a]) { a }",
+                "macro parameters must be identifiers, got [a]",
+            ),
+            (
+                "macro(a = 1) { a }",
+                "macro parameters must be identifiers, got a = 1",
+            ),
+            ("fn(...rest, x) { }", "the rest parameter must come last"),
+            (
+                "fn(x = 1, y) { }",
+                "parameter y without a default value follows one with a default value",
+            ),
+            (
+                "f(x: 1, 2)",
+                "positional argument follows the named argument x",
+            ),
+            ("struct P { x, x }", "duplicate field x in struct P"),
+            ("P { x: 1, x: 2 }", "field x of P is given more than once"),
+            (
+                "impl P { fn f(self) { } fn f(self) { } }",
+                "duplicate method f in impl P",
+            ),
+            ("p.1", "expected next token to be IDENT, got INT instead"),
+        ];
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            parser.parse_program();
+
+            assert_eq!(parser.errors().first().map(String::as_str), Some(expected));
+        }
+    }
+
     #[test]
     fn test_expression_precedence_parsing() {
         let input = "2 + 2 + 3 * 1 - 2 + 5 * 4 - 1";
-        let mut lexer = Lexer::new(input.to_string());
+        let lexer = Lexer::new(input.to_string());
         let mut parser = Parser::new(lexer);
 
         let program = parser.parse_program().unwrap();
@@ -633,7 +2365,7 @@ mod tests {
     }
 
     fn lingo_source_code_parser(code: &str, len: usize) {
-        let mut lexer = Lexer::new(code.to_string());
+        let lexer = Lexer::new(code.to_string());
         let mut parser = Parser::new(lexer);
 
         let program = parser.parse_program();
@@ -653,7 +2385,7 @@ mod tests {
 
                 for (i, expected_identifier) in tests.into_iter().enumerate() {
                     let stat = program.statements.get(i).unwrap();
-                    if !test_let_statement(stat, expected_identifier) {
+                    if !test_let_statement(stat.as_ref(), expected_identifier) {
                         return;
                     }
                 }
@@ -661,6 +2393,18 @@ mod tests {
         }
     }
 
+    fn first_expression(statements: &[Box<dyn Statement>]) -> &dyn Expression {
+        statements
+            .first()
+            .unwrap()
+            .as_any()
+            .downcast_ref::<ExpressionStatement>()
+            .expect("statement not ExpressionStatement")
+            .expression
+            .as_deref()
+            .unwrap()
+    }
+
     fn check_parser_errors(parser: &Parser) {
         let errors = parser.errors();
 
@@ -675,10 +2419,10 @@ mod tests {
         }
 
         // fail now
-        assert!(false);
+        panic!("parser has {} errors", errors.len());
     }
 
-    fn test_let_statement(stat: &Box<dyn Statement>, name: &str) -> bool {
+    fn test_let_statement(stat: &dyn Statement, name: &str) -> bool {
         if stat.token_literal() != "let" {
             eprintln!(
                 "statement token_literal not 'let'. got={}",
@@ -694,26 +2438,30 @@ mod tests {
             .downcast_ref::<LetStatement>()
             .expect("statement not LetStatement.");
 
-        if let_stat.name.value != name {
+        let Some(let_name) = let_stat.name() else {
             eprintln!(
-                "let_stat name's value not '{}'. got={}",
-                name, let_stat.name.value
+                "let_stat pattern not an identifier. got={}",
+                let_stat.pattern.format()
             );
             return false;
-        }
+        };
 
-        if let_stat.name.token_literal() != name {
+        if let_name.value != name {
             eprintln!(
-                "stat name not '{}'. got={}",
-                name,
-                let_stat.name.token_literal()
+                "let_stat name's value not '{}'. got={}",
+                name, let_name.value
             );
             return false;
+        }
+
+        if let_name.token_literal() != name {
+            eprintln!("stat name not '{}'. got={}", name, let_name.token_literal());
+            return false;
         };
         true
     }
 
-    fn test_integer_literal(expression: &Box<dyn Expression>, value: usize) -> bool {
+    fn test_integer_literal(expression: &dyn Expression, value: usize) -> bool {
         let integer_literal = expression
             .as_any()
             .downcast_ref::<IntegerLiteral>()
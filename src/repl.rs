@@ -1,37 +1,644 @@
+use crate::builtins::BUILTINS;
+use crate::dump::{dump, DumpError, DumpMode};
+use crate::environment::{Env, Environment};
+use crate::evaluator::eval_program;
+use crate::gc::{self, Root};
 use crate::lexer::Lexer;
-use crate::token::EOF;
-use std::io::{stdin, stdout, Read, Write};
+use crate::macro_expansion::{define_macros, expand_macros};
+use crate::object::Object;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::token::{EOF, KEYWORDS};
+use crate::trace::{format_trace, take};
+use rustyline::completion::Completer;
+use rustyline::config::{Config, EditMode};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::borrow::Cow;
+use std::path::PathBuf;
+use std::time::Instant;
+use std::{env, fs};
 
 const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+const HISTORY_FILE: &str = ".lingo_history";
 
-/// read eval print loop
+/// what the REPL does with each line of input, switched with `:mode`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// print the tokens of the input
+    Tokens,
+    /// print the parsed AST of the input
+    Ast,
+    /// print the disassembled bytecode the input compiles to
+    Bytecode,
+    /// evaluate the input and print the resulting value
+    Eval,
+}
+
+/// read eval print loop.
+/// input with unbalanced braces, parentheses, brackets or strings is continued on the next line,
+/// Ctrl-C discards the current input and Ctrl-D leaves the REPL
 pub fn start() {
-    let mut s = String::new();
+    let config = Config::builder()
+        .edit_mode(EditMode::Emacs)
+        .auto_add_history(false)
+        .build();
+    let mut editor: Editor<ReplHelper, DefaultHistory> = match Editor::with_config(config) {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("could not start the line editor: {}", err);
+            return;
+        }
+    };
+
+    let history = history_path();
+    if let Some(history) = &history {
+        // there is no history yet on the first run
+        let _ = editor.load_history(history);
+    }
+
+    let mut session = Session::new();
+    editor.set_helper(Some(ReplHelper {
+        env: session.env.clone(),
+    }));
+
+    let mut input = String::new();
     loop {
-        print!("{}", PROMPT);
-        let _ = stdout().flush();
-        stdin()
-            .read_line(&mut s)
-            .expect("Did not enter a correct string");
-        if let Some('\n') = s.chars().next_back() {
-            s.pop();
+        let prompt = if input.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                input.push_str(&line);
+                input.push('\n');
+                if is_incomplete(&input) {
+                    continue;
+                }
+            }
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("could not read input: {}", err);
+                break;
+            }
         }
-        if let Some('\r') = s.chars().next_back() {
-            s.pop();
+
+        let entry = input.trim();
+        if !entry.is_empty() {
+            let _ = editor.add_history_entry(entry);
         }
 
-        let mut lex = Lexer::new(s.clone());
+        print!("{}", session.handle(&input));
+        // `:reset` replaces the environment the completion is drawn from
+        if let Some(helper) = editor.helper_mut() {
+            helper.env = session.env.clone();
+        }
 
-        loop {
-            let token = lex.next_token();
+        input.clear();
+    }
 
-            if token.token_type == EOF {
-                break;
+    if let Some(history) = &history {
+        if let Err(err) = editor.save_history(history) {
+            eprintln!("could not save history to {}: {}", history.display(), err);
+        }
+    }
+}
+
+/// the history is kept in `~/.lingo_history`, or not at all without a home directory
+fn history_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// check whether the input still has unclosed `(`, `[`, `{` or `"`,
+/// in which case the REPL asks for another line instead of evaluating it.
+/// too many closing delimiters make the input complete, so that the parser can report them
+fn is_incomplete(input: &str) -> bool {
+    let mut depth: i32 = 0;
+    let mut in_string = false;
+    for ch in input.chars() {
+        match ch {
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    in_string || depth > 0
+}
+
+/// completes keywords, builtins and the names bound in the session,
+/// and hints the remaining parameters of a function after `name(`
+struct ReplHelper {
+    env: Env,
+}
+
+impl ReplHelper {
+    fn candidates(&self, prefix: &str) -> Vec<String> {
+        let mut candidates: Vec<String> = KEYWORDS
+            .keys()
+            .copied()
+            .chain(BUILTINS.iter().map(|builtin| builtin.name))
+            .map(str::to_string)
+            .chain(
+                self.env
+                    .borrow()
+                    .bindings()
+                    .into_iter()
+                    .map(|(name, _)| name),
+            )
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+
+    /// the parameters of the function called by the innermost unclosed `(`
+    /// which don't have an argument yet
+    fn parameter_hint(&self, line: &str) -> Option<String> {
+        let mut depth = 0;
+        let mut commas = 0;
+        let mut open = None;
+        // the delimiters and commas inside of strings are left out
+        let mut in_string = false;
+        let code: Vec<(usize, char)> = line
+            .char_indices()
+            .filter(|&(_, ch)| {
+                if ch == '"' {
+                    in_string = !in_string;
+                    return false;
+                }
+                !in_string
+            })
+            .collect();
+        for &(i, ch) in code.iter().rev() {
+            match ch {
+                ')' | ']' | '}' => depth += 1,
+                '(' | '[' | '{' if depth > 0 => depth -= 1,
+                '(' => {
+                    open = Some(i);
+                    break;
+                }
+                '[' | '{' => return None,
+                ',' if depth == 0 => commas += 1,
+                _ => {}
             }
+        }
 
-            println!("{}", token);
+        let name = line[..open?].trim_end();
+        let name = &name[word_start(name, name.len())..];
+        if name.is_empty() {
+            return None;
         }
+        let function = match self.env.borrow().get(name)? {
+            Object::Function(function) => function,
+            _ => return None,
+        };
 
-        s.clear();
+        let remaining: Vec<String> = function
+            .parameters
+            .iter()
+            .skip(commas)
+            .map(|param| param.format())
+            .collect();
+        if remaining.is_empty() && commas > 0 {
+            return None;
+        }
+        // an argument that is being typed, a string as well, is followed by the next parameter
+        let separator = if !in_string && (line.ends_with('(') || line.ends_with(", ")) {
+            ""
+        } else if !in_string && line.ends_with(',') {
+            " "
+        } else if remaining.len() > 1 {
+            return Some(format!(", {})", remaining[1..].join(", ")));
+        } else {
+            return Some(")".to_string());
+        };
+        Some(format!("{}{})", separator, remaining.join(", ")))
+    }
+}
+
+/// the position where the identifier ending at `pos` starts
+fn word_start(line: &str, pos: usize) -> usize {
+    line[..pos]
+        .rfind(|ch: char| !(ch.is_ascii_alphabetic() || ch == '_'))
+        .map_or(0, |i| i + 1)
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = word_start(line, pos);
+        Ok((start, self.candidates(&line[start..pos])))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        self.parameter_hint(line)
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        // dim the hint so that it doesn't look like typed input
+        Cow::Owned(format!("\x1b[2m{}\x1b[0m", hint))
+    }
+}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+const HELP: &str = "\
+:load <file>    evaluate a file in the current session
+:reset          drop all bindings of the session
+:env            list the bindings of the session with their values
+:type <expr>    show the runtime type of the value of the expression
+:time <expr>    evaluate the expression and show how long it took
+:mode [mode]    show or switch the input mode, one of tokens|ast|bytecode|eval
+:gc [bytes]     collect garbage and show the collector stats,
+                or set how many bytes are allocated between automatic collections
+:help           show this help
+";
+
+/// the state kept by the REPL between two inputs
+struct Session {
+    env: Env,
+    /// knows the constants declared by earlier inputs
+    resolver: Resolver,
+    /// the macros defined by earlier inputs
+    macros: Env,
+    mode: Mode,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            env: Environment::new(),
+            resolver: Resolver::new(),
+            macros: Environment::new(),
+            mode: Mode::Eval,
+        }
+    }
+
+    /// run a meta-command or process the input according to the current mode,
+    /// returning what should be printed
+    fn handle(&mut self, input: &str) -> String {
+        if let Some(command) = input.trim().strip_prefix(':') {
+            return self.run_command(command);
+        }
+        match self.mode {
+            Mode::Tokens => tokens_output(input),
+            Mode::Ast => ast_output(input),
+            Mode::Bytecode => bytecode_output(input),
+            Mode::Eval => self.eval_input(input),
+        }
+    }
+
+    fn run_command(&mut self, command: &str) -> String {
+        let (name, arg) = match command.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (command, ""),
+        };
+        match (name, arg) {
+            ("help", _) => HELP.to_string(),
+            ("load", "") => "usage: :load <file>\n".to_string(),
+            ("load", path) => match fs::read_to_string(path) {
+                Ok(source) => self.eval_input(&source),
+                Err(err) => format!("could not read {}: {}\n", path, err),
+            },
+            ("reset", _) => {
+                self.env = Environment::new();
+                self.resolver = Resolver::new();
+                self.macros = Environment::new();
+                "environment reset\n".to_string()
+            }
+            ("env", _) => {
+                let mut out = String::new();
+                for (name, value) in self.env.borrow().bindings() {
+                    out.push_str(&format!("{} = {}\n", name, value.inspect()));
+                }
+                out
+            }
+            ("type", "") => "usage: :type <expr>\n".to_string(),
+            ("type", source) => match self.eval(source) {
+                Ok(value) => format!("{}\n", value.object_type()),
+                Err(errors) => errors,
+            },
+            ("time", "") => "usage: :time <expr>\n".to_string(),
+            ("time", source) => {
+                let start = Instant::now();
+                let result = self.eval(source);
+                let elapsed = start.elapsed();
+                match result {
+                    Ok(value) => format!("{}\ntime: {:?}\n", value.inspect(), elapsed),
+                    Err(errors) => errors,
+                }
+            }
+            ("gc", "") => {
+                gc::collect(&[Root::Env(&self.env)]);
+                let stats = gc::stats();
+                format!(
+                    "collections: {}, objects freed: {}, bytes freed: {}, live bytes: {}\n",
+                    stats.collections, stats.objects_freed, stats.bytes_freed, stats.live_bytes
+                )
+            }
+            ("gc", threshold) => match threshold.parse() {
+                Ok(bytes) => {
+                    gc::set_threshold(bytes);
+                    format!("gc threshold: {} bytes\n", bytes)
+                }
+                Err(_) => "usage: :gc [bytes]\n".to_string(),
+            },
+            ("mode", "") => format!("mode: {}\n", mode_name(self.mode)),
+            ("mode", "tokens") => self.switch_mode(Mode::Tokens),
+            ("mode", "ast") => self.switch_mode(Mode::Ast),
+            ("mode", "bytecode") => self.switch_mode(Mode::Bytecode),
+            ("mode", "eval") => self.switch_mode(Mode::Eval),
+            ("mode", other) => format!(
+                "unknown mode {}, expected one of tokens|ast|bytecode|eval\n",
+                other
+            ),
+            _ => format!(
+                "unknown command :{}, type :help for a list of commands\n",
+                command
+            ),
+        }
+    }
+
+    fn switch_mode(&mut self, mode: Mode) -> String {
+        self.mode = mode;
+        format!("mode: {}\n", mode_name(mode))
+    }
+
+    fn eval_input(&mut self, input: &str) -> String {
+        let evaluated = match self.eval(input) {
+            Ok(evaluated) => evaluated,
+            Err(errors) => return errors,
+        };
+        let mut out: String = self
+            .resolver
+            .warnings()
+            .iter()
+            .map(|warning| format!("WARNING: {}\n", warning))
+            .collect();
+        match evaluated {
+            // statements like `let` don't produce a value worth printing
+            Object::Null => {}
+            Object::Error(_) => {
+                out.push_str(&format!(
+                    "{}\n{}",
+                    evaluated.inspect(),
+                    format_trace(&take())
+                ));
+            }
+            evaluated => out.push_str(&format!("{}\n", evaluated.inspect())),
+        }
+        out
+    }
+
+    /// expand the macros of the input and evaluate it in the session environment, failing with
+    /// the formatted parser errors if it can't be parsed, with the error of a failing macro,
+    /// or with the resolver errors if it changes constants
+    fn eval(&mut self, input: &str) -> Result<Object, String> {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let mut program = parser.parse_program().expect("parse_program returned none");
+        if !parser.errors().is_empty() {
+            return Err(parser_errors_output(parser.errors()));
+        }
+        define_macros(&mut program, &self.macros);
+        let program =
+            expand_macros(&program, &self.macros).map_err(|err| format!("ERROR: {}\n", err))?;
+        if let Err(errors) = self.resolver.resolve(&program) {
+            return Err(errors
+                .iter()
+                .map(|err| format!("ERROR: {}\n", err))
+                .collect());
+        }
+        Ok(eval_program(&program, &self.env))
+    }
+}
+
+fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Tokens => "tokens",
+        Mode::Ast => "ast",
+        Mode::Bytecode => "bytecode",
+        Mode::Eval => "eval",
+    }
+}
+
+fn tokens_output(input: &str) -> String {
+    let mut lex = Lexer::new(input.to_string());
+    let mut out = String::new();
+
+    loop {
+        let token = lex.next_token();
+
+        if token.token_type == EOF {
+            break;
+        }
+
+        out.push_str(&format!("{}\n", token));
+    }
+    out
+}
+
+fn ast_output(input: &str) -> String {
+    let mut parser = Parser::new(Lexer::new(input.to_string()));
+    let program = parser.parse_program().expect("parse_program returned none");
+    if !parser.errors().is_empty() {
+        return parser_errors_output(parser.errors());
+    }
+    program.dump().to_tree()
+}
+
+/// the input is compiled on its own, names bound in the session show up as global bindings
+fn bytecode_output(input: &str) -> String {
+    match dump(input, DumpMode::Bytecode) {
+        Ok(out) => out,
+        Err(DumpError::Parser(errors)) => parser_errors_output(&errors),
+        Err(DumpError::Compiler(err)) => format!("compile error: {}\n", err),
+    }
+}
+
+fn parser_errors_output(errors: &[String]) -> String {
+    let mut out = "Woops! The input could not be parsed:\n".to_string();
+    for err in errors {
+        out.push_str(&format!("    {}\n", err));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repl::{is_incomplete, ReplHelper, Session};
+    use std::env::temp_dir;
+    use std::fs;
+
+    #[test]
+    fn test_is_incomplete() {
+        let tests = vec![
+            ("1 + 2", false),
+            ("let f = fn(x) {", true),
+            ("let f = fn(x) {\n  x\n}", false),
+            ("add(1,", true),
+            ("[1, 2", true),
+            ("\"hello", true),
+            ("\"{\"", false),
+            ("\"(\" + (", true),
+            ("1 + 2)", false),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(is_incomplete(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_meta_commands() {
+        let mut session = Session::new();
+
+        assert_eq!(session.handle("let b = [1, 2];"), "");
+        assert_eq!(session.handle("let a = 1;"), "");
+        assert_eq!(session.handle(":env"), "a = 1\nb = [1, 2]\n");
+        assert_eq!(session.handle(":type b"), "ARRAY\n");
+        assert_eq!(session.handle(":type fn(x) { x }"), "FUNCTION\n");
+        assert!(session.handle(":time a + 1").starts_with("2\ntime: "));
+
+        assert_eq!(session.handle(":reset"), "environment reset\n");
+        assert_eq!(session.handle(":env"), "");
+        assert_eq!(session.handle("a"), "ERROR: identifier not found: a\n");
+        assert_eq!(session.handle("const c = 1;"), "");
+        assert_eq!(
+            session.handle("c = 2"),
+            "ERROR: cannot assign to constant c at line 1, column 1\n"
+        );
+        assert_eq!(
+            session.handle(
+                "let unless = macro(cond, then) { quote(if (!unquote(cond)) { unquote(then) }) };"
+            ),
+            ""
+        );
+        assert_eq!(session.handle("unless(c > 1, c * 10)"), "10\n");
+        assert_eq!(
+            session.handle("unless(true)"),
+            "ERROR: wrong number of arguments: want=2, got=1 in macro unless called at line 1, column 7\n"
+        );
+        assert_eq!(
+            session.handle("match (c) { 1 => \"one\" }"),
+            "WARNING: non-exhaustive match, a value no arm matches is a runtime error at line 1, column 1\none\n"
+        );
+        assert_eq!(session.handle("let f = fn() { a };"), "");
+        assert_eq!(
+            session.handle("f()"),
+            "ERROR: identifier not found: a\nstack trace, innermost call last:\n  in f called at line 1, column 2\n"
+        );
+
+        assert_eq!(session.handle(":mode ast"), "mode: ast\n");
+        assert_eq!(
+            session.handle("1"),
+            "Program\n  statements[0]: ExpressionStatement\n    expression: IntegerLiteral value=1\n"
+        );
+        assert_eq!(session.handle(":mode bytecode"), "mode: bytecode\n");
+        assert!(session
+            .handle("1")
+            .ends_with("== <main> ==\n0000    1  OpConstant 0                  ; 1\n0003    |  OpReturnValue\n"));
+        assert_eq!(session.handle(":mode eval"), "mode: eval\n");
+
+        assert_eq!(
+            session.handle(":type 1 +"),
+            "Woops! The input could not be parsed:\n    no prefix parse function for EOF found\n"
+        );
+        session.handle("let leak = fn() { let f = fn() { f }; 1 }; leak();");
+        assert!(session.handle(":gc").starts_with("collections: "));
+        assert_eq!(session.handle(":gc 4096"), "gc threshold: 4096 bytes\n");
+        assert_eq!(session.handle(":gc lots"), "usage: :gc [bytes]\n");
+        assert!(session.handle(":nope").starts_with("unknown command :nope"));
+        assert!(session.handle(":help").contains(":load <file>"));
+    }
+
+    #[test]
+    fn test_load_command() {
+        let path = temp_dir().join("lingo_test_load_command.lingo");
+        fs::write(&path, "let double = fn(x) {\n  x * 2\n};\ndouble(21)\n").unwrap();
+
+        let mut session = Session::new();
+        assert_eq!(session.handle(&format!(":load {}", path.display())), "42\n");
+        assert_eq!(session.handle("double(2)"), "4\n");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_completion_candidates() {
+        let mut session = Session::new();
+        session.handle("let first_name = \"lingo\"; let lang = 1;");
+        let helper = ReplHelper {
+            env: session.env.clone(),
+        };
+
+        assert_eq!(helper.candidates("l"), vec!["lang", "last", "len", "let"]);
+        assert_eq!(helper.candidates("fi"), vec!["first", "first_name"]);
+        assert_eq!(helper.candidates("re"), vec!["rest", "return"]);
+        assert!(helper.candidates("zz").is_empty());
+    }
+
+    #[test]
+    fn test_parameter_hint() {
+        let mut session = Session::new();
+        session.handle("let add = fn(a, b, c) { a + b + c }; let x = 1;");
+        let helper = ReplHelper {
+            env: session.env.clone(),
+        };
+
+        let tests = vec![
+            ("add(", Some("a, b, c)")),
+            ("let y = add(", Some("a, b, c)")),
+            ("add(1", Some(", b, c)")),
+            ("add(1,", Some(" b, c)")),
+            ("add(1, ", Some("b, c)")),
+            ("add(1, [1, 2], ", Some("c)")),
+            ("add(1, 2, 3", Some(")")),
+            ("add(\"(\", ", Some("b, c)")),
+            ("add(\"a, b)\", [\",\"], ", Some("c)")),
+            ("add(1, \"(x, ", Some(", c)")),
+            ("add(1, 2, 3, ", None),
+            ("add(1)", None),
+            ("x(", None),
+            ("len(", None),
+            ("(", None),
+        ];
+        for (line, expected) in tests {
+            assert_eq!(
+                helper.parameter_hint(line).as_deref(),
+                expected,
+                "line: {}",
+                line
+            );
+        }
     }
 }
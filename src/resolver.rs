@@ -0,0 +1,381 @@
+use crate::ast::{
+    ArrayLiteral, ArrayPattern, AssignStatement, BlockStatement, CallExpression, Expression,
+    ExpressionStatement, ForStatement, FunctionLiteral, HashLiteral, HashPattern, Identifier,
+    IfExpression, ImplStatement, IndexExpression, InfixExpression, LetStatement, MatchExpression,
+    MemberExpression, Pattern, PrefixExpression, Program, ReturnStatement, Statement,
+    StructLiteral, StructStatement, WhileStatement, WildcardPattern,
+};
+use crate::limits;
+use crate::token::Span;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+
+/// a mistake found by the resolver, or a warning about code which may fail,
+/// located at the node it is about
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolveError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.message, self.span.line, self.span.column
+        )
+    }
+}
+
+/// check how a program uses its bindings before it runs. constants declared with `const`
+/// can't be assigned to, their elements can't be assigned to and their name can't be bound
+/// again in the same scope. match expressions without an arm matching every value get a warning.
+/// like in the evaluator, a function literal opens a scope and blocks share the scope they are in.
+/// the resolver keeps the bindings of the top level between calls to `resolve`,
+/// so that a program can be checked piece by piece
+pub struct Resolver {
+    /// whether each binding is a constant, the innermost scope last
+    scopes: Vec<HashMap<String, bool>>,
+    errors: Vec<ResolveError>,
+    warnings: Vec<ResolveError>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Resolver::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver {
+            scopes: vec![HashMap::new()],
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// check the program, the bindings of a rejected program are forgotten since it doesn't run
+    pub fn resolve(&mut self, program: &Program) -> Result<(), Vec<ResolveError>> {
+        self.warnings.clear();
+        let globals = self.scopes[0].clone();
+        for stat in &program.statements {
+            self.resolve_statement(stat.as_ref());
+        }
+        let errors = mem::take(&mut self.errors);
+        if errors.is_empty() {
+            return Ok(());
+        }
+        self.scopes = vec![globals];
+        Err(errors)
+    }
+
+    /// the warnings about the last program resolved, which don't keep it from running
+    pub fn warnings(&self) -> &[ResolveError] {
+        &self.warnings
+    }
+
+    fn resolve_statement(&mut self, stat: &dyn Statement) {
+        let any = stat.as_any();
+        if let Some(stat) = any.downcast_ref::<ExpressionStatement>() {
+            self.resolve_optional_expression(&stat.expression);
+        } else if let Some(stat) = any.downcast_ref::<LetStatement>() {
+            self.resolve_optional_expression(&stat.value);
+            self.declare_pattern(stat.pattern.as_ref(), stat.constant);
+        } else if let Some(stat) = any.downcast_ref::<ReturnStatement>() {
+            self.resolve_optional_expression(&stat.return_value);
+        } else if let Some(block) = any.downcast_ref::<BlockStatement>() {
+            self.resolve_block_statement(block);
+        } else if let Some(stat) = any.downcast_ref::<AssignStatement>() {
+            self.resolve_assign_statement(stat);
+        } else if let Some(stat) = any.downcast_ref::<WhileStatement>() {
+            self.resolve_expression(stat.condition.as_ref());
+            self.resolve_block_statement(&stat.body);
+        } else if let Some(stat) = any.downcast_ref::<ForStatement>() {
+            self.resolve_expression(stat.iterable.as_ref());
+            self.declare(&stat.variable, false);
+            self.resolve_block_statement(&stat.body);
+        } else if let Some(stat) = any.downcast_ref::<StructStatement>() {
+            self.declare(&stat.name, false);
+        } else if let Some(stat) = any.downcast_ref::<ImplStatement>() {
+            for (_, method) in &stat.methods {
+                self.resolve_expression(method);
+            }
+        }
+    }
+
+    fn resolve_block_statement(&mut self, block: &BlockStatement) {
+        for stat in &block.statements {
+            limits::grow_stack(|| self.resolve_statement(stat.as_ref()));
+        }
+    }
+
+    fn resolve_assign_statement(&mut self, stat: &AssignStatement) {
+        let mut target = stat.target.as_ref();
+        while let Some(index) = target.as_any().downcast_ref::<IndexExpression>() {
+            self.resolve_expression(index.index.as_ref());
+            target = index.left.as_ref();
+        }
+        self.resolve_expression(stat.value.as_ref());
+
+        if let Some(ident) = target.as_any().downcast_ref::<Identifier>() {
+            if self.is_constant(&ident.value) {
+                let message = if stat.target.as_any().is::<Identifier>() {
+                    format!("cannot assign to constant {}", ident.value)
+                } else {
+                    format!("cannot assign to an element of constant {}", ident.value)
+                };
+                self.error(message, ident);
+            }
+        }
+    }
+
+    fn resolve_expression(&mut self, expression: &dyn Expression) {
+        limits::grow_stack(|| self.resolve_nested_expression(expression));
+    }
+
+    fn resolve_nested_expression(&mut self, expression: &dyn Expression) {
+        let any = expression.as_any();
+        if let Some(prefix) = any.downcast_ref::<PrefixExpression>() {
+            self.resolve_expression(prefix.right.as_ref());
+        } else if let Some(infix) = any.downcast_ref::<InfixExpression>() {
+            self.resolve_expression(infix.left.as_ref());
+            self.resolve_expression(infix.right.as_ref());
+        } else if let Some(if_expression) = any.downcast_ref::<IfExpression>() {
+            self.resolve_expression(if_expression.condition.as_ref());
+            self.resolve_block_statement(&if_expression.consequence);
+            if let Some(alternative) = &if_expression.alternative {
+                self.resolve_block_statement(alternative);
+            }
+        } else if let Some(literal) = any.downcast_ref::<FunctionLiteral>() {
+            self.scopes.push(HashMap::new());
+            for param in literal.parameters.iter() {
+                self.resolve_optional_expression(&param.default);
+                self.declare_pattern(param.pattern.as_ref(), false);
+            }
+            if let Some(rest) = &literal.rest {
+                self.declare(rest, false);
+            }
+            self.resolve_block_statement(&literal.body);
+            self.scopes.pop();
+        } else if let Some(call) = any.downcast_ref::<CallExpression>() {
+            self.resolve_expression(call.function.as_ref());
+            for argument in &call.arguments {
+                self.resolve_expression(argument.as_ref());
+            }
+            for (_, argument) in &call.named_arguments {
+                self.resolve_expression(argument.as_ref());
+            }
+        } else if let Some(array) = any.downcast_ref::<ArrayLiteral>() {
+            for element in &array.elements {
+                self.resolve_expression(element.as_ref());
+            }
+        } else if let Some(index) = any.downcast_ref::<IndexExpression>() {
+            self.resolve_expression(index.left.as_ref());
+            self.resolve_expression(index.index.as_ref());
+        } else if let Some(hash) = any.downcast_ref::<HashLiteral>() {
+            for (key, value) in &hash.pairs {
+                self.resolve_expression(key.as_ref());
+                self.resolve_expression(value.as_ref());
+            }
+        } else if let Some(literal) = any.downcast_ref::<StructLiteral>() {
+            for (_, value) in &literal.fields {
+                self.resolve_expression(value.as_ref());
+            }
+        } else if let Some(member) = any.downcast_ref::<MemberExpression>() {
+            self.resolve_expression(member.object.as_ref());
+        } else if let Some(expression) = any.downcast_ref::<MatchExpression>() {
+            self.resolve_match_expression(expression);
+        }
+    }
+
+    fn resolve_match_expression(&mut self, expression: &MatchExpression) {
+        self.resolve_expression(expression.subject.as_ref());
+        for arm in &expression.arms {
+            self.declare_pattern(arm.pattern.as_ref(), false);
+            if let Some(guard) = &arm.guard {
+                self.resolve_expression(guard.as_ref());
+            }
+            self.resolve_block_statement(&arm.body);
+        }
+        let exhaustive = expression.arms.iter().any(|arm| {
+            let pattern = arm.pattern.as_any();
+            arm.guard.is_none() && (pattern.is::<WildcardPattern>() || pattern.is::<Identifier>())
+        });
+        if !exhaustive {
+            self.warnings.push(ResolveError {
+                message: "non-exhaustive match, a value no arm matches is a runtime error"
+                    .to_string(),
+                span: expression.token.span,
+            });
+        }
+    }
+
+    /// bind the names of the pattern in the innermost scope
+    fn declare_pattern(&mut self, pattern: &dyn Pattern, constant: bool) {
+        let any = pattern.as_any();
+        if let Some(ident) = any.downcast_ref::<Identifier>() {
+            self.declare(ident, constant);
+        } else if let Some(array) = any.downcast_ref::<ArrayPattern>() {
+            for element in &array.elements {
+                self.declare_pattern(element.as_ref(), constant);
+            }
+            if let Some(rest) = &array.rest {
+                self.declare(rest, constant);
+            }
+        } else if let Some(hash) = any.downcast_ref::<HashPattern>() {
+            for (_, pattern) in &hash.pairs {
+                self.declare_pattern(pattern.as_ref(), constant);
+            }
+        }
+    }
+
+    fn resolve_optional_expression(&mut self, expression: &Option<Box<dyn Expression>>) {
+        if let Some(expression) = expression {
+            self.resolve_expression(expression.as_ref());
+        }
+    }
+
+    /// bind the name in the innermost scope, where binding a constant again would change it
+    fn declare(&mut self, name: &Identifier, constant: bool) {
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.get(&name.value) == Some(&true) {
+            self.error(format!("cannot redeclare constant {}", name.value), name);
+            return;
+        }
+        scope.insert(name.value.clone(), constant);
+    }
+
+    /// whether the name refers to a constant, looking it up from the innermost scope outwards
+    fn is_constant(&self, name: &str) -> bool {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    fn error(&mut self, message: String, ident: &Identifier) {
+        self.errors.push(ResolveError {
+            message,
+            span: ident.token.span,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+
+    fn resolve(resolver: &mut Resolver, input: &str) -> Vec<String> {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program().unwrap();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        match resolver.resolve(&program) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.iter().map(|err| err.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_constants() {
+        let tests = vec![
+            ("const a = 1; let b = a + 1; b = 2; b += a;", vec![]),
+            (
+                "const a = 1;\na = 2;",
+                vec!["cannot assign to constant a at line 2, column 1"],
+            ),
+            (
+                "const xs = [1, {\"k\": 2}];\nfn() {\n  xs[1][\"k\"] += 1;\n}",
+                vec!["cannot assign to an element of constant xs at line 3, column 3"],
+            ),
+            (
+                "const a = 1; let a = 2; for (a in []) { }",
+                vec![
+                    "cannot redeclare constant a at line 1, column 18",
+                    "cannot redeclare constant a at line 1, column 30",
+                ],
+            ),
+            // parameters and bindings of functions shadow the constants around them
+            (
+                "const a = 1; let f = fn(a) { a = 2; let b = fn() { a += 1; } };",
+                vec![],
+            ),
+            ("const a = 1; let f = fn() { let a = 2; a = 3; };", vec![]),
+            (
+                "let f = fn() { const a = 1; if (true) { a = 2; } };",
+                vec!["cannot assign to constant a at line 1, column 41"],
+            ),
+            (
+                "while (true) { const n = 1; n = 2; }",
+                vec!["cannot assign to constant n at line 1, column 29"],
+            ),
+            // every name a destructuring `const` binds is a constant
+            (
+                "const [a, ..rest] = [1, 2]; const {k} = {};\nrest = 1; k = 2;",
+                vec![
+                    "cannot assign to constant rest at line 2, column 1",
+                    "cannot assign to constant k at line 2, column 11",
+                ],
+            ),
+            (
+                "const a = 1; let f = fn([a], {b}) { a = 2; b = 3; };",
+                vec![],
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                resolve(&mut Resolver::new(), input),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_constants_across_programs() {
+        let mut resolver = Resolver::new();
+        assert!(resolve(&mut resolver, "const a = 1;").is_empty());
+        assert_eq!(
+            resolve(&mut resolver, "a = 2"),
+            vec!["cannot assign to constant a at line 1, column 1"]
+        );
+        // the constant declared by a rejected program doesn't exist
+        assert!(!resolve(&mut resolver, "const b = 1; a = 2").is_empty());
+        assert!(resolve(&mut resolver, "let b = 1; b = 2").is_empty());
+    }
+
+    #[test]
+    fn test_match_expressions() {
+        let mut resolver = Resolver::new();
+        assert_eq!(
+            resolve(&mut resolver, "const a = 1; match (a) { [a] => 1, _ => 2 }"),
+            vec!["cannot redeclare constant a at line 1, column 27"]
+        );
+
+        let tests = vec![
+            ("match (1) { 1 => 1, _ => 2 }", vec![]),
+            ("match (1) { 1 => 1, n => n }", vec![]),
+            (
+                "match (1) { 1 => 1, n if n > 1 => n }",
+                vec!["non-exhaustive match, a value no arm matches is a runtime error at line 1, column 1"],
+            ),
+            (
+                "let f = fn(x) {\n  match (x) { [a, ..b] => a, {\"k\": v} => v }\n};",
+                vec!["non-exhaustive match, a value no arm matches is a runtime error at line 2, column 3"],
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut resolver = Resolver::new();
+            assert!(resolve(&mut resolver, input).is_empty(), "input: {}", input);
+            let warnings: Vec<String> = resolver.warnings().iter().map(|w| w.to_string()).collect();
+            assert_eq!(warnings, expected, "input: {}", input);
+        }
+    }
+}
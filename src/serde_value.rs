@@ -0,0 +1,733 @@
+use crate::object::{HashKey, HashPair, Object};
+use indexmap::IndexMap;
+use serde::de::value::SeqDeserializer;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::ser::{self, Serialize};
+use std::fmt;
+use std::rc::Rc;
+
+/// convert a Rust value to a Lingo value.
+///
+/// structs and maps become hashes, sequences and tuples become arrays, `None` and `()` become
+/// `null`. an enum variant becomes its name if it holds no data, otherwise a hash with the name
+/// of the variant as its only key. Lingo has no floating point numbers, so floats can't be converted
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Object, Error> {
+    value.serialize(Serializer)
+}
+
+/// convert a Lingo value to a Rust value, the reverse of `to_value`.
+/// an instance of a struct converts like a hash of its fields
+pub fn from_value<T: DeserializeOwned>(value: &Object) -> Result<T, Error> {
+    T::deserialize(Deserializer { value })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+fn array(elements: Vec<Object>) -> Object {
+    Object::Array(Rc::new(elements))
+}
+
+/// a hash with a single pair, which is how enum variants holding data are represented
+fn variant(name: &str, value: Object) -> Result<Object, Error> {
+    let mut pairs = Hash::default();
+    pairs.insert(Object::String(name.into()), value)?;
+    Ok(pairs.finish())
+}
+
+/// the pairs of a hash being serialized
+#[derive(Default)]
+struct Hash {
+    pairs: IndexMap<HashKey, HashPair>,
+    /// the key of the pair whose value comes next
+    key: Option<Object>,
+}
+
+impl Hash {
+    fn insert(&mut self, key: Object, value: Object) -> Result<(), Error> {
+        let hash_key = key
+            .hash_key()
+            .ok_or_else(|| Error(format!("unusable as hash key: {}", key.object_type())))?;
+        self.pairs.insert(hash_key, HashPair { key, value });
+        Ok(())
+    }
+
+    fn finish(self) -> Object {
+        Object::Hash(Rc::new(self.pairs))
+    }
+}
+
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Object;
+    type Error = Error;
+    type SerializeSeq = Array;
+    type SerializeTuple = Array;
+    type SerializeTupleStruct = Array;
+    type SerializeTupleVariant = Array;
+    type SerializeMap = Hash;
+    type SerializeStruct = Hash;
+    type SerializeStructVariant = Hash;
+
+    fn serialize_bool(self, v: bool) -> Result<Object, Error> {
+        Ok(Object::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Object, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Object, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Object, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Object, Error> {
+        Ok(Object::Integer(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Object, Error> {
+        i64::try_from(v)
+            .map(Object::Integer)
+            .map_err(|_| Error(format!("integer out of range: {}", v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Object, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Object, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Object, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Object, Error> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Object, Error> {
+        i64::try_from(v)
+            .map(Object::Integer)
+            .map_err(|_| Error(format!("integer out of range: {}", v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Object, Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Object, Error> {
+        Err(Error(format!("Lingo has no floating point numbers: {}", v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Object, Error> {
+        Ok(Object::String(v.to_string().into()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Object, Error> {
+        Ok(Object::String(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Object, Error> {
+        Ok(array(
+            v.iter()
+                .map(|byte| Object::Integer((*byte).into()))
+                .collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Object, Error> {
+        Ok(Object::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Object, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Object, Error> {
+        Ok(Object::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Object, Error> {
+        Ok(Object::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Object, Error> {
+        Ok(Object::String(variant.into()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Object, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Object, Error> {
+        variant(name, value.serialize(Serializer)?)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Array, Error> {
+        Ok(Array {
+            variant: None,
+            elements: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Array, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Array, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Array, Error> {
+        Ok(Array {
+            variant: Some(name),
+            elements: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Hash, Error> {
+        Ok(Hash::default())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Hash, Error> {
+        Ok(Hash::default())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Hash, Error> {
+        // the name of the variant waits in `key` until the fields are serialized
+        Ok(Hash {
+            pairs: IndexMap::new(),
+            key: Some(Object::String(name.into())),
+        })
+    }
+}
+
+/// the elements of an array being serialized,
+/// along with the name of the variant they belong to for tuple variants
+struct Array {
+    variant: Option<&'static str>,
+    elements: Vec<Object>,
+}
+
+impl Array {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.elements.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Object, Error> {
+        match self.variant {
+            Some(name) => variant(name, array(self.elements)),
+            None => Ok(array(self.elements)),
+        }
+    }
+}
+
+impl ser::SerializeSeq for Array {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Object, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for Array {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Object, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for Array {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Object, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for Array {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Object, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeMap for Hash {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error("value serialized before its key".to_string()))?;
+        self.insert(key, value.serialize(Serializer)?)
+    }
+
+    fn end(self) -> Result<Object, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStruct for Hash {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(Object::String(key.into()), value.serialize(Serializer)?)
+    }
+
+    fn end(self) -> Result<Object, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for Hash {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(Object::String(key.into()), value.serialize(Serializer)?)
+    }
+
+    fn end(mut self) -> Result<Object, Error> {
+        let name = self.key.take();
+        let fields = self.finish();
+        match name {
+            Some(Object::String(name)) => variant(&name, fields),
+            _ => Ok(fields),
+        }
+    }
+}
+
+struct Deserializer<'a> {
+    value: &'a Object,
+}
+
+impl<'a> Deserializer<'a> {
+    fn invalid<T>(&self, expected: &str) -> Result<T, Error> {
+        Err(Error(format!(
+            "expected {}, got {}",
+            expected,
+            self.value.object_type()
+        )))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Object::Integer(value) => visitor.visit_i64(*value),
+            Object::Boolean(value) => visitor.visit_bool(*value),
+            Object::String(value) => visitor.visit_str(value),
+            Object::Null => visitor.visit_unit(),
+            Object::Array(elements) => visitor.visit_seq(Elements {
+                elements: elements.iter(),
+            }),
+            Object::Range(start, end) => {
+                visitor.visit_seq(SeqDeserializer::<_, Error>::new(*start..*end))
+            }
+            Object::Hash(pairs) => visitor.visit_map(Pairs {
+                pairs: pairs.values(),
+                value: None,
+            }),
+            Object::Instance(instance) => visitor.visit_map(Fields {
+                fields: instance
+                    .definition
+                    .fields
+                    .iter()
+                    .zip(instance.values.iter()),
+                value: None,
+            }),
+            Object::ReturnValue(value) => Deserializer { value }.deserialize_any(visitor),
+            _ => self.invalid("a value which isn't a function"),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Object::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.value {
+            Object::String(name) => visitor.visit_enum(name.to_string().into_deserializer()),
+            Object::Hash(pairs) if pairs.len() == 1 => {
+                let pair = pairs.values().next().unwrap();
+                visitor.visit_enum(Variant { pair })
+            }
+            _ => self.invalid("STRING or HASH with a single key"),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct Elements<'a> {
+    elements: std::slice::Iter<'a, Object>,
+}
+
+impl<'de> SeqAccess<'de> for Elements<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.elements.next() {
+            Some(value) => seed.deserialize(Deserializer { value }).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+struct Pairs<'a> {
+    pairs: indexmap::map::Values<'a, HashKey, HashPair>,
+    /// the value of the pair whose key was just deserialized
+    value: Option<&'a Object>,
+}
+
+impl<'de> MapAccess<'de> for Pairs<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.pairs.next() {
+            Some(pair) => {
+                self.value = Some(&pair.value);
+                seed.deserialize(Deserializer { value: &pair.key })
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(Deserializer { value }),
+            None => Err(Error("value deserialized before its key".to_string())),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.pairs.len())
+    }
+}
+
+/// the fields of an instance, named by strings
+struct Fields<'a> {
+    fields: std::iter::Zip<std::slice::Iter<'a, String>, std::slice::Iter<'a, Object>>,
+    /// the value of the field whose name was just deserialized
+    value: Option<&'a Object>,
+}
+
+impl<'de> MapAccess<'de> for Fields<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.fields.next() {
+            Some((field, value)) => {
+                self.value = Some(value);
+                seed.deserialize(field.as_str().into_deserializer())
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(Deserializer { value }),
+            None => Err(Error("value deserialized before its key".to_string())),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+/// an enum variant holding data, a hash with the name of the variant as its only key
+struct Variant<'a> {
+    pair: &'a HashPair,
+}
+
+impl<'de, 'a> EnumAccess<'de> for Variant<'a> {
+    type Error = Error;
+    type Variant = Deserializer<'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Deserializer<'a>), Error> {
+        let name = seed.deserialize(Deserializer {
+            value: &self.pair.key,
+        })?;
+        Ok((
+            name,
+            Deserializer {
+                value: &self.pair.value,
+            },
+        ))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Deserializer<'_> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.value {
+            Object::Null => Ok(()),
+            _ => self.invalid("NULL"),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::environment::Environment;
+    use crate::evaluator::eval_program;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::serde_value::{from_value, to_value, Error};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Point,
+        Circle(i64),
+        Rectangle { width: i64, height: i64 },
+        Line(i64, i64),
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Drawing {
+        name: String,
+        visible: bool,
+        layer: Option<u8>,
+        shapes: Vec<Shape>,
+        tags: BTreeMap<String, i64>,
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let drawing = Drawing {
+            name: "sketch".to_string(),
+            visible: true,
+            layer: None,
+            shapes: vec![
+                Shape::Point,
+                Shape::Circle(3),
+                Shape::Rectangle {
+                    width: 2,
+                    height: 4,
+                },
+                Shape::Line(1, 2),
+            ],
+            tags: BTreeMap::from([("a".to_string(), 1), ("b".to_string(), 2)]),
+        };
+        let value = to_value(&drawing).unwrap();
+        assert_eq!(
+            value.inspect(),
+            "{name: sketch, visible: true, layer: null, \
+             shapes: [Point, {Circle: 3}, {Rectangle: {width: 2, height: 4}}, {Line: [1, 2]}], \
+             tags: {a: 1, b: 2}}"
+        );
+        assert_eq!(from_value::<Drawing>(&value), Ok(drawing));
+    }
+
+    #[test]
+    fn test_instances() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Point {
+            x: i64,
+            tags: Vec<String>,
+        }
+
+        let input = "struct Point { x, tags }; Point { tags: [\"a\"], x: 1 }";
+        let program = Parser::new(Lexer::new(input.to_string()))
+            .parse_program()
+            .unwrap();
+        let value = eval_program(&program, &Environment::new());
+        assert_eq!(
+            from_value::<Point>(&value),
+            Ok(Point {
+                x: 1,
+                tags: vec!["a".to_string()],
+            })
+        );
+        assert_eq!(
+            from_value::<BTreeMap<String, Vec<String>>>(&value),
+            Err(Error(
+                "invalid type: integer `1`, expected a sequence".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_conversion_errors() {
+        assert_eq!(
+            to_value(&1.5).map(|value| value.inspect()),
+            Err(Error(
+                "Lingo has no floating point numbers: 1.5".to_string()
+            ))
+        );
+        assert_eq!(
+            to_value(&u64::MAX).map(|value| value.inspect()),
+            Err(Error(format!("integer out of range: {}", u64::MAX)))
+        );
+        let value = to_value(&("x", 300)).unwrap();
+        assert_eq!(
+            from_value::<(String, u8)>(&value),
+            Err(Error(
+                "invalid value: integer `300`, expected u8".to_string()
+            ))
+        );
+        assert_eq!(
+            from_value::<Vec<i64>>(&to_value("text").unwrap()),
+            Err(Error(
+                "invalid type: string \"text\", expected a sequence".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolScope {
+    /// bound at the top level of the program
+    Global,
+    /// bound inside the function being compiled
+    Local,
+    /// one of `builtins::BUILTINS`
+    Builtin,
+    /// bound inside an enclosing function, `depth` levels up from the function being compiled
+    Free { depth: usize },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub scope: SymbolScope,
+    /// slot of the binding in its scope
+    pub index: usize,
+}
+
+/// associate identifiers with the scope and slot the compiler stores their values in.
+/// each function literal is compiled with a symbol table enclosed by the one of its surroundings
+#[derive(Default)]
+pub struct SymbolTable {
+    pub outer: Option<Box<SymbolTable>>,
+    store: HashMap<String, Symbol>,
+    num_definitions: usize,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    pub fn new_enclosed(outer: SymbolTable) -> Self {
+        SymbolTable {
+            outer: Some(Box::new(outer)),
+            ..SymbolTable::default()
+        }
+    }
+
+    /// bind the name in this table. binding a name again reuses its slot,
+    /// so that closures referring to the name see the latest value like they do in the evaluator
+    pub fn define(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.store.get(name) {
+            if symbol.scope != SymbolScope::Builtin {
+                return symbol.clone();
+            }
+        }
+
+        let scope = if self.outer.is_none() {
+            SymbolScope::Global
+        } else {
+            SymbolScope::Local
+        };
+        let symbol = Symbol {
+            name: name.to_string(),
+            scope,
+            index: self.num_definitions,
+        };
+        self.num_definitions += 1;
+        self.store.insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    pub fn define_builtin(&mut self, index: usize, name: &str) -> Symbol {
+        let symbol = Symbol {
+            name: name.to_string(),
+            scope: SymbolScope::Builtin,
+            index,
+        };
+        self.store.insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    /// make the name refer to the slot of the symbol, returning what it referred to before
+    /// for `restore` to put back
+    pub fn alias(&mut self, name: &str, symbol: &Symbol) -> Option<Symbol> {
+        self.store.insert(name.to_string(), symbol.clone())
+    }
+
+    pub fn restore(&mut self, name: &str, previous: Option<Symbol>) {
+        match previous {
+            Some(symbol) => self.store.insert(name.to_string(), symbol),
+            None => self.store.remove(name),
+        };
+    }
+
+    /// look the name up in this table and then in the enclosing ones.
+    /// locals of enclosing functions are turned into free symbols recording how far up they are
+    pub fn resolve(&self, name: &str) -> Option<Symbol> {
+        if let Some(symbol) = self.store.get(name) {
+            return Some(symbol.clone());
+        }
+
+        let mut symbol = self.outer.as_ref()?.resolve(name)?;
+        symbol.scope = match symbol.scope {
+            SymbolScope::Local => SymbolScope::Free { depth: 1 },
+            SymbolScope::Free { depth } => SymbolScope::Free { depth: depth + 1 },
+            scope => scope,
+        };
+        Some(symbol)
+    }
+
+    /// the outermost table, holding the global bindings
+    pub fn global_mut(&mut self) -> &mut SymbolTable {
+        match self.outer {
+            Some(_) => self.outer.as_mut().unwrap().global_mut(),
+            None => self,
+        }
+    }
+
+    /// the names of the bindings of this table, indexed by their slot
+    pub fn names(&self) -> Vec<String> {
+        let mut names = vec![String::new(); self.num_definitions];
+        for symbol in self.store.values() {
+            if symbol.scope != SymbolScope::Builtin {
+                names[symbol.index] = symbol.name.clone();
+            }
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::symbol_table::{Symbol, SymbolScope, SymbolTable};
+
+    fn symbol(name: &str, scope: SymbolScope, index: usize) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            scope,
+            index,
+        }
+    }
+
+    #[test]
+    fn test_define_and_resolve() {
+        let mut global = SymbolTable::new();
+        assert_eq!(global.define("a"), symbol("a", SymbolScope::Global, 0));
+        assert_eq!(global.define("b"), symbol("b", SymbolScope::Global, 1));
+        // defining a name again keeps its slot
+        assert_eq!(global.define("a"), symbol("a", SymbolScope::Global, 0));
+        global.define_builtin(0, "len");
+
+        let mut first_local = SymbolTable::new_enclosed(global);
+        assert_eq!(first_local.define("c"), symbol("c", SymbolScope::Local, 0));
+
+        let mut second_local = SymbolTable::new_enclosed(first_local);
+        assert_eq!(second_local.define("d"), symbol("d", SymbolScope::Local, 0));
+
+        let mut third_local = SymbolTable::new_enclosed(second_local);
+        assert_eq!(third_local.define("e"), symbol("e", SymbolScope::Local, 0));
+
+        let tests = vec![
+            symbol("a", SymbolScope::Global, 0),
+            symbol("b", SymbolScope::Global, 1),
+            symbol("len", SymbolScope::Builtin, 0),
+            symbol("c", SymbolScope::Free { depth: 2 }, 0),
+            symbol("d", SymbolScope::Free { depth: 1 }, 0),
+            symbol("e", SymbolScope::Local, 0),
+        ];
+        for expected in tests {
+            assert_eq!(third_local.resolve(&expected.name), Some(expected));
+        }
+        assert_eq!(third_local.resolve("f"), None);
+    }
+
+    #[test]
+    fn test_names() {
+        let mut global = SymbolTable::new();
+        global.define_builtin(0, "len");
+        global.define("x");
+        global.define("y");
+        global.define("x");
+        assert_eq!(global.names(), vec!["x", "y"]);
+    }
+
+    #[test]
+    fn test_alias() {
+        let mut global = SymbolTable::new();
+        global.define_builtin(0, "len");
+        let hidden = global.define("<len>");
+        let previous = global.alias("len", &hidden);
+        assert_eq!(global.resolve("len"), Some(hidden));
+        global.restore("len", previous);
+        assert_eq!(
+            global.resolve("len"),
+            Some(symbol("len", SymbolScope::Builtin, 0))
+        );
+
+        let previous = global.alias("x", &symbol("<x>", SymbolScope::Global, 0));
+        global.restore("x", previous);
+        assert_eq!(global.resolve("x"), None);
+    }
+}
@@ -13,6 +13,7 @@ pub const EOF: TokenType = "EOF";
 pub const IDENT: TokenType = "IDENT";
 // literals
 pub const INT: TokenType = "INT";
+pub const STRING: TokenType = "STRING";
 
 // operators
 pub const ASSIGN: TokenType = "=";
@@ -21,36 +22,109 @@ pub const MINUS: TokenType = "-";
 pub const BANG: TokenType = "!";
 pub const ASTERISK: TokenType = "*";
 pub const SLASH: TokenType = "/";
+pub const PERCENT: TokenType = "%";
+pub const POWER: TokenType = "**";
+pub const AMPERSAND: TokenType = "&";
+pub const CARET: TokenType = "^";
+pub const TILDE: TokenType = "~";
+pub const SHIFT_LEFT: TokenType = "<<";
+pub const SHIFT_RIGHT: TokenType = ">>";
 pub const LT: TokenType = "<";
 pub const GT: TokenType = ">";
 pub const EQ: TokenType = "==";
 pub const NOT_EQ: TokenType = "!=";
+pub const PLUS_ASSIGN: TokenType = "+=";
+pub const MINUS_ASSIGN: TokenType = "-=";
+pub const ASTERISK_ASSIGN: TokenType = "*=";
+pub const SLASH_ASSIGN: TokenType = "/=";
+pub const FAT_ARROW: TokenType = "=>";
+pub const PIPE: TokenType = "|>";
 
 // delimiters
 pub const COMMA: TokenType = ",";
 pub const SEMICOLON: TokenType = ";";
+pub const COLON: TokenType = ":";
+pub const BAR: TokenType = "|";
+pub const DOT: TokenType = ".";
+pub const DOT_DOT: TokenType = "..";
+pub const ELLIPSIS: TokenType = "...";
 pub const LPAREN: TokenType = "(";
 pub const RPAREN: TokenType = ")";
 pub const LBRACE: TokenType = "{";
 pub const RBRACE: TokenType = "}";
+pub const LBRACKET: TokenType = "[";
+pub const RBRACKET: TokenType = "]";
+
+/// the operators and delimiters made of punctuation which the lexer reads as one token,
+/// apart from the brackets, `,`, `;` and `"` which can't be part of an added operator
+pub const SYMBOLS: [TokenType; 28] = [
+    ASSIGN,
+    EQ,
+    FAT_ARROW,
+    BANG,
+    NOT_EQ,
+    PLUS,
+    PLUS_ASSIGN,
+    MINUS,
+    MINUS_ASSIGN,
+    ASTERISK,
+    ASTERISK_ASSIGN,
+    POWER,
+    SLASH,
+    SLASH_ASSIGN,
+    PERCENT,
+    LT,
+    SHIFT_LEFT,
+    GT,
+    SHIFT_RIGHT,
+    AMPERSAND,
+    CARET,
+    TILDE,
+    COLON,
+    BAR,
+    PIPE,
+    DOT,
+    DOT_DOT,
+    ELLIPSIS,
+];
 
 // keywords
 pub const FUNCTION: TokenType = "FUNCTION";
 pub const LET: TokenType = "LET";
+pub const CONST: TokenType = "CONST";
 pub const TRUE: TokenType = "TRUE";
 pub const FALSE: TokenType = "FALSE";
 pub const IF: TokenType = "IF";
 pub const ELSE: TokenType = "ELSE";
 pub const RETURN: TokenType = "RETURN";
+pub const WHILE: TokenType = "WHILE";
+pub const FOR: TokenType = "FOR";
+pub const IN: TokenType = "IN";
+pub const BREAK: TokenType = "BREAK";
+pub const CONTINUE: TokenType = "CONTINUE";
+pub const MACRO: TokenType = "MACRO";
+pub const MATCH: TokenType = "MATCH";
+pub const STRUCT: TokenType = "STRUCT";
+pub const IMPL: TokenType = "IMPL";
 
-static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
+pub static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
     "fn" => FUNCTION,
     "let" => LET,
+    "const" => CONST,
     "true" => TRUE,
     "false" => FALSE,
     "if" => IF,
     "else" => ELSE,
     "return" => RETURN,
+    "while" => WHILE,
+    "for" => FOR,
+    "in" => IN,
+    "break" => BREAK,
+    "continue" => CONTINUE,
+    "macro" => MACRO,
+    "match" => MATCH,
+    "struct" => STRUCT,
+    "impl" => IMPL,
 };
 
 /// check the `KEYWORDS` table to see whether the given identifier is in fact a keyword
@@ -64,10 +138,31 @@ pub fn lookup_ident(ident: &str) -> TokenType {
     }
 }
 
-#[derive(Clone)]
+/// location of a token in the source code
+/// `line` and `column` are 1-based, `start` and `end` are byte offsets into the input
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{} ({}..{})",
+            self.line, self.column, self.start, self.end
+        )
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct Token {
     pub token_type: TokenType,
     pub literal: String,
+    pub span: Span,
 }
 
 impl Token {
@@ -75,6 +170,7 @@ impl Token {
         Self {
             token_type,
             literal: ascii::escape_default(ch).to_string(),
+            span: Span::default(),
         }
     }
 }
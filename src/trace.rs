@@ -0,0 +1,152 @@
+use std::cell::RefCell;
+use std::fmt;
+
+thread_local! {
+    static TRACE: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+}
+
+/// a call of a Lingo function which was being executed when a runtime error happened
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// the name the function was bound to with `let`, or `<anonymous>`
+    pub function: String,
+    /// the position of the call in the source, 0 when it is unknown,
+    /// for example when the function was called by the host or the bytecode has no line tables
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "in {}", self.function),
+            line => write!(
+                f,
+                "in {} called at line {}, column {}",
+                self.function, line, self.column
+            ),
+        }
+    }
+}
+
+/// forget the trace of the previous runtime error, at the start of a run
+pub fn clear() {
+    TRACE.with(|trace| trace.borrow_mut().clear());
+}
+
+/// record a call a runtime error is returning from, innermost calls are recorded first
+pub fn push(frame: Frame) {
+    TRACE.with(|trace| trace.borrow_mut().push(frame));
+}
+
+/// replace the recorded trace, the frames given innermost last
+pub fn set(frames: Vec<Frame>) {
+    TRACE.with(|trace| *trace.borrow_mut() = frames.into_iter().rev().collect());
+}
+
+/// the trace of the last runtime error, innermost call last
+pub fn take() -> Vec<Frame> {
+    TRACE.with(|trace| {
+        let mut frames = trace.take();
+        frames.reverse();
+        frames
+    })
+}
+
+/// print the trace one call per line, innermost call last. runs of the same call,
+/// left by deep recursion, are shortened to their first frame and a count
+pub fn format_trace(frames: &[Frame]) -> String {
+    if frames.is_empty() {
+        return String::new();
+    }
+    let mut out = "stack trace, innermost call last:\n".to_string();
+    let mut index = 0;
+    while index < frames.len() {
+        let frame = &frames[index];
+        let repeated = frames[index..]
+            .iter()
+            .take_while(|other| *other == frame)
+            .count();
+        out.push_str(&format!("  {}\n", frame));
+        if repeated > 1 {
+            out.push_str(&format!("  ... repeated {} more times\n", repeated - 1));
+        }
+        index += repeated;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::Compiler;
+    use crate::environment::Environment;
+    use crate::evaluator::eval_program;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::trace::{format_trace, take};
+    use crate::vm::Vm;
+
+    /// the error and trace printed by both back ends
+    fn traces(input: &str) -> (String, String) {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program().unwrap();
+
+        let evaluated = eval_program(&program, &Environment::new()).inspect();
+        let evaluated = format!("{}\n{}", evaluated, format_trace(&take()));
+
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let executed = match Vm::new().run(&bytecode) {
+            Ok(value) => value.inspect(),
+            Err(err) => format!("ERROR: {}", err),
+        };
+        let executed = format!("{}\n{}", executed, format_trace(&take()));
+        (evaluated, executed)
+    }
+
+    #[test]
+    fn test_traces() {
+        let tests = vec![
+            (
+                "let divide = fn(a, b) { a / b };
+let ratio = fn(x) {
+  divide(x, 0)
+};
+let apply = fn(f) { 1 + f(10) };
+apply(ratio)",
+                "ERROR: division by zero
+stack trace, innermost call last:
+  in apply called at line 6, column 6
+  in ratio called at line 5, column 26
+  in divide called at line 3, column 9
+",
+            ),
+            (
+                "let call = fn(f) { f() }; call(fn() { len(1) })",
+                "ERROR: argument to `len` not supported, got INTEGER
+stack trace, innermost call last:
+  in call called at line 1, column 31
+  in <anonymous> called at line 1, column 21
+",
+            ),
+            (
+                "let countdown = fn(n) { if (n == 0) { missing } else { countdown(n - 1) } };
+countdown(3)",
+                "ERROR: identifier not found: missing
+stack trace, innermost call last:
+  in countdown called at line 2, column 10
+  in countdown called at line 1, column 65
+  ... repeated 2 more times
+",
+            ),
+            // errors outside of functions have no trace
+            ("1 + true", "ERROR: type mismatch: INTEGER + BOOLEAN\n"),
+        ];
+        for (input, expected) in tests {
+            let (evaluated, executed) = traces(input);
+            assert_eq!(evaluated, expected, "evaluator, input: {}", input);
+            assert_eq!(executed, expected, "vm, input: {}", input);
+        }
+        // a successful run leaves no trace behind
+        assert_eq!(traces("let f = fn() { 1 }; f()").0, "1\n");
+    }
+}
@@ -0,0 +1,957 @@
+use crate::builtins::BUILTINS;
+use crate::code::*;
+use crate::compiler::Bytecode;
+use crate::evaluator::{
+    add_methods, array_length_matches, bind_arguments, call_host_function, destructure_error,
+    eval_index_assignment, eval_index_expression, eval_infix_expression, eval_iterable,
+    eval_member_expression, eval_prefix_expression, iteration_item, literal_matches, new_array,
+    new_hash, new_instance, new_struct, no_match_error, NAMED_ARGUMENTS_ERROR,
+};
+use crate::gc::{self, Root};
+use crate::limits;
+use crate::object::{Closure, HashPair, Locals, Object};
+use crate::trace;
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// a function call being executed
+struct Frame {
+    locals: Rc<Locals>,
+    /// the offset of the next instruction to execute
+    ip: usize,
+    /// the height of the stack when the call started, the callee sits right below it
+    base_pointer: usize,
+}
+
+/// a stack machine executing the bytecode produced by the compiler.
+/// the values of the global bindings are kept between calls to `run`
+#[derive(Default)]
+pub struct Vm {
+    constants: Vec<Object>,
+    globals: Vec<Option<Object>>,
+    global_names: Vec<String>,
+    /// grows as needed: its height is bounded by the call depth and nesting limits
+    stack: Vec<Object>,
+    frames: Vec<Frame>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm::default()
+    }
+
+    /// run the top level of the program, returning its value or the message of the runtime error
+    pub fn run(&mut self, bytecode: &Bytecode) -> Result<Object, String> {
+        self.constants = bytecode.constants.clone();
+        self.global_names = bytecode.globals.clone();
+        if self.globals.len() < self.global_names.len() {
+            self.globals.resize(self.global_names.len(), None);
+        }
+
+        let locals = Rc::new(Locals {
+            function: bytecode.main.clone(),
+            slots: RefCell::new(vec![None; bytecode.main.locals.len()]),
+            outer: None,
+        });
+        gc::track_locals(&locals);
+        limits::start_run();
+        trace::clear();
+        self.stack.clear();
+        self.frames = vec![Frame {
+            locals,
+            ip: 0,
+            base_pointer: 0,
+        }];
+
+        let result = self.execute();
+        if result.is_err() {
+            trace::set(self.stack_trace());
+        }
+        self.stack.clear();
+        self.frames.clear();
+        result
+    }
+
+    fn execute(&mut self) -> Result<Object, String> {
+        loop {
+            let op = {
+                let frame = self.current_frame();
+                match frame.locals.function.instructions.get(frame.ip) {
+                    Some(op) => *op,
+                    // only reached by bytecode which was not produced by the compiler
+                    None => return Ok(Object::Null),
+                }
+            };
+            self.current_frame_mut().ip += 1;
+            limits::step()?;
+
+            match op {
+                OP_CONSTANT => {
+                    let index = self.read_u16();
+                    let constant = self.constants[index].clone();
+                    self.push(constant)?;
+                }
+                OP_POP => {
+                    self.pop();
+                }
+                OP_ADD | OP_SUB | OP_MUL | OP_DIV | OP_MOD | OP_POW | OP_BIT_AND | OP_BIT_OR
+                | OP_BIT_XOR | OP_SHIFT_LEFT | OP_SHIFT_RIGHT | OP_EQUAL | OP_NOT_EQUAL
+                | OP_GREATER_THAN | OP_LESS_THAN => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let result = eval_infix_expression(infix_operator(op), left, right);
+                    self.push_result(result)?;
+                }
+                OP_TRUE => self.push(Object::Boolean(true))?,
+                OP_FALSE => self.push(Object::Boolean(false))?,
+                OP_NULL => self.push(Object::Null)?,
+                OP_MINUS | OP_BANG | OP_BIT_NOT => {
+                    let right = self.pop();
+                    let operator = match op {
+                        OP_MINUS => "-",
+                        OP_BANG => "!",
+                        _ => "~",
+                    };
+                    self.push_result(eval_prefix_expression(operator, right))?;
+                }
+                OP_JUMP => {
+                    let target = self.read_u16();
+                    self.current_frame_mut().ip = target;
+                }
+                OP_JUMP_NOT_TRUTHY => {
+                    let target = self.read_u16();
+                    if !self.pop().is_truthy() {
+                        self.current_frame_mut().ip = target;
+                    }
+                }
+                OP_SET_GLOBAL => {
+                    let index = self.read_u16();
+                    self.globals[index] = Some(self.pop());
+                }
+                OP_GET_GLOBAL => {
+                    let index = self.read_u16();
+                    match &self.globals[index] {
+                        Some(value) => self.push(value.clone())?,
+                        None => {
+                            return Err(format!(
+                                "identifier not found: {}",
+                                self.global_names[index]
+                            ))
+                        }
+                    }
+                }
+                OP_SET_LOCAL => {
+                    let index = self.read_u8();
+                    let value = self.pop();
+                    self.current_frame().locals.slots.borrow_mut()[index] = Some(value);
+                }
+                OP_GET_LOCAL => {
+                    let index = self.read_u8();
+                    let locals = self.current_frame().locals.clone();
+                    self.push_local(&locals, index)?;
+                }
+                OP_GET_FREE => {
+                    let depth = self.read_u8();
+                    let index = self.read_u8();
+                    let locals = self.enclosing_locals(depth)?;
+                    self.push_local(&locals, index)?;
+                }
+                OP_SET_FREE => {
+                    let depth = self.read_u8();
+                    let index = self.read_u8();
+                    let value = self.pop();
+                    let locals = self.enclosing_locals(depth)?;
+                    let mut slots = locals.slots.borrow_mut();
+                    match slots.get_mut(index) {
+                        Some(slot) => *slot = Some(value),
+                        None => return Err("free variable out of scope".to_string()),
+                    }
+                }
+                OP_GET_BUILTIN => {
+                    let index = self.read_u8();
+                    self.push(Object::Builtin(BUILTINS[index]))?;
+                }
+                OP_ARRAY => {
+                    let count = self.read_u16();
+                    let elements = self.pop_many(count)?;
+                    self.push_result(new_array(elements))?;
+                }
+                OP_HASH => {
+                    let count = self.read_u16();
+                    let elements = self.pop_many(count)?;
+                    let mut pairs = IndexMap::new();
+                    let mut elements = elements.into_iter();
+                    while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+                        let hash_key = key.hash_key().ok_or_else(|| {
+                            format!("unusable as hash key: {}", key.object_type())
+                        })?;
+                        pairs.insert(hash_key, HashPair { key, value });
+                    }
+                    self.push_result(new_hash(pairs))?;
+                }
+                OP_INDEX => {
+                    let index = self.pop();
+                    let left = self.pop();
+                    self.push_result(eval_index_expression(left, index))?;
+                }
+                OP_INDEX_KEEP => {
+                    let len = self.stack.len();
+                    if len < 2 {
+                        return Err("stack underflow".to_string());
+                    }
+                    let element = eval_index_expression(
+                        self.stack[len - 2].clone(),
+                        self.stack[len - 1].clone(),
+                    );
+                    self.push_result(element)?;
+                }
+                OP_SET_INDEX => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let collection = self.pop();
+                    self.push_result(eval_index_assignment(collection, index, value))?;
+                }
+                OP_CALL => {
+                    let count = self.read_u8();
+                    self.call(count, Vec::new())?;
+                }
+                OP_CALL_NAMED => {
+                    let count = self.read_u8();
+                    let named_count = self.read_u8();
+                    let mut named = Vec::with_capacity(named_count);
+                    for _ in 0..named_count {
+                        let value = self.pop();
+                        let name = self.pop();
+                        named.push((name.inspect(), value));
+                    }
+                    named.reverse();
+                    self.call(count, named)?;
+                }
+                OP_MISSING_ARGUMENT => {
+                    let index = self.read_u8();
+                    let missing = matches!(
+                        self.current_frame().locals.slots.borrow().get(index),
+                        Some(None)
+                    );
+                    self.push(Object::Boolean(missing))?;
+                }
+                OP_RETURN_VALUE | OP_RETURN => {
+                    let value = if op == OP_RETURN_VALUE {
+                        self.pop()
+                    } else {
+                        Object::Null
+                    };
+                    let frame = self.frames.pop().unwrap();
+                    self.stack.truncate(frame.base_pointer);
+                    if self.frames.is_empty() {
+                        return Ok(value);
+                    }
+                    self.push(value)?;
+                }
+                OP_CLOSURE => {
+                    let index = self.read_u16();
+                    let function = match &self.constants[index] {
+                        Object::CompiledFunction(function) => function.clone(),
+                        other => return Err(format!("not a function: {}", other.object_type())),
+                    };
+                    let env = Some(self.current_frame().locals.clone());
+                    self.push(Object::Closure(Rc::new(Closure { function, env })))?;
+                }
+                OP_ITER => {
+                    let iterable = self.pop();
+                    self.push_result(eval_iterable(iterable))?;
+                }
+                OP_ITER_NEXT => {
+                    let target = self.read_u16();
+                    let index = self.pop();
+                    let items = self.pop();
+                    let item = match index {
+                        Object::Integer(index) => {
+                            iteration_item(&items, index).map(|item| (index, item))
+                        }
+                        _ => None,
+                    };
+                    match item {
+                        Some((index, item)) => {
+                            self.push(Object::Integer(index + 1))?;
+                            self.push(item)?;
+                        }
+                        None => self.current_frame_mut().ip = target,
+                    }
+                }
+                OP_MATCH_LITERAL => {
+                    let literal = self.pop();
+                    let value = self.pop();
+                    self.push(Object::Boolean(literal_matches(&literal, &value)))?;
+                }
+                OP_MATCH_ARRAY => {
+                    let count = self.read_u16();
+                    let has_rest = self.read_u8() == 1;
+                    let matches = match self.pop() {
+                        Object::Array(elements) => {
+                            array_length_matches(elements.len(), count, has_rest)
+                        }
+                        _ => false,
+                    };
+                    self.push(Object::Boolean(matches))?;
+                }
+                OP_MATCH_KEY => {
+                    let key = self.pop();
+                    let matches = match (self.pop(), key.hash_key()) {
+                        (Object::Hash(pairs), Some(key)) => pairs.contains_key(&key),
+                        _ => false,
+                    };
+                    self.push(Object::Boolean(matches))?;
+                }
+                OP_REST => {
+                    let count = self.read_u16();
+                    match self.pop() {
+                        Object::Array(elements) => {
+                            let rest = elements[count.min(elements.len())..].to_vec();
+                            self.push_result(new_array(rest))?;
+                        }
+                        other => return Err(format!("not an array: {}", other.object_type())),
+                    }
+                }
+                OP_NO_MATCH => {
+                    let value = self.pop();
+                    return Err(no_match_error(&value));
+                }
+                OP_STRUCT => {
+                    let count = self.read_u8();
+                    let fields = self.pop_many(count)?.iter().map(Object::inspect).collect();
+                    let name = self.pop();
+                    self.push_result(new_struct(&name.inspect(), fields))?;
+                }
+                OP_INSTANCE => {
+                    let count = self.read_u8();
+                    let fields = named_values(self.pop_many(count * 2)?);
+                    let definition = self.pop();
+                    self.push_result(new_instance(definition, fields))?;
+                }
+                OP_MEMBER => {
+                    let name = self.pop();
+                    let object = self.pop();
+                    self.push_result(eval_member_expression(object, &name.inspect()))?;
+                }
+                OP_IMPL => {
+                    let count = self.read_u8();
+                    let methods = named_values(self.pop_many(count * 2)?);
+                    let definition = self.pop();
+                    if let Object::Error(message) = add_methods(definition, methods) {
+                        return Err(message);
+                    }
+                }
+                OP_NO_DESTRUCTURE => {
+                    let pattern = self.pop();
+                    let value = self.pop();
+                    return Err(destructure_error(&pattern.inspect(), &value));
+                }
+                _ => return Err(format!("unknown opcode {}", op)),
+            }
+        }
+    }
+
+    /// call the callee sitting below its `count` arguments on the stack,
+    /// with the named arguments already taken off the stack
+    fn call(&mut self, count: usize, named: Vec<(String, Object)>) -> Result<(), String> {
+        let callee_position = self
+            .stack
+            .len()
+            .checked_sub(count + 1)
+            .ok_or("stack underflow")?;
+        let callee = self.stack[callee_position].clone();
+        if !named.is_empty() && !matches!(callee, Object::Closure(_) | Object::BoundMethod(_)) {
+            return Err(NAMED_ARGUMENTS_ERROR.to_string());
+        }
+        match callee {
+            Object::BoundMethod(method) => {
+                // the instance becomes the first argument of the method
+                self.stack[callee_position] = method.method.clone();
+                let args = self.stack.split_off(callee_position + 1);
+                self.push(Object::Instance(method.receiver.clone()))?;
+                self.stack.extend(args);
+                self.call(count + 1, named)
+            }
+            Object::Closure(closure) => {
+                let function = &closure.function;
+                let args = self.stack.split_off(callee_position + 1);
+                // the parameters the call leaves out are set by the function to their default value
+                let mut slots = bind_arguments(&function.signature, args, named)?;
+                limits::check_call_depth(self.frames.len())?;
+                slots.resize(function.locals.len(), None);
+                let locals = Rc::new(Locals {
+                    function: function.clone(),
+                    slots: RefCell::new(slots),
+                    outer: closure.env.clone(),
+                });
+                gc::track_locals(&locals);
+                self.frames.push(Frame {
+                    locals,
+                    ip: 0,
+                    base_pointer: callee_position,
+                });
+                if gc::should_collect() {
+                    self.collect_garbage();
+                }
+                limits::check_heap(0)?;
+                Ok(())
+            }
+            Object::Builtin(builtin) => {
+                let args = self.stack.split_off(callee_position + 1);
+                self.stack.truncate(callee_position);
+                self.push_result((builtin.func)(args))
+            }
+            Object::HostFunction(function) => {
+                let args = self.stack.split_off(callee_position + 1);
+                self.stack.truncate(callee_position);
+                self.push_result(call_host_function(&function, args))
+            }
+            other => Err(format!("not a function: {}", other.object_type())),
+        }
+    }
+
+    /// the calls being executed, each with the position of the call instruction in its caller
+    fn stack_trace(&self) -> Vec<trace::Frame> {
+        self.frames
+            .windows(2)
+            .map(|frames| {
+                let (caller, callee) = (&frames[0], &frames[1]);
+                let (line, column) =
+                    position_at(&caller.locals.function.lines, caller.ip.saturating_sub(1))
+                        .unwrap_or((0, 0));
+                trace::Frame {
+                    function: callee
+                        .locals
+                        .function
+                        .name
+                        .as_deref()
+                        .unwrap_or("<anonymous>")
+                        .to_string(),
+                    line,
+                    column,
+                }
+            })
+            .collect()
+    }
+
+    /// the roots of the virtual machine are the values on the stack, the globals
+    /// and the locals of the calls being executed
+    fn collect_garbage(&self) {
+        let mut roots: Vec<Root> = self.stack.iter().map(Root::Value).collect();
+        roots.extend(self.globals.iter().flatten().map(Root::Value));
+        roots.extend(self.frames.iter().map(|frame| Root::Locals(&frame.locals)));
+        gc::collect(&roots);
+    }
+
+    /// the locals of the function call `depth` levels up from the current one
+    fn enclosing_locals(&self, depth: usize) -> Result<Rc<Locals>, String> {
+        let mut locals = self.current_frame().locals.clone();
+        for _ in 0..depth {
+            locals = match &locals.outer {
+                Some(outer) => outer.clone(),
+                None => return Err("free variable out of scope".to_string()),
+            };
+        }
+        Ok(locals)
+    }
+
+    fn push_local(&mut self, locals: &Locals, index: usize) -> Result<(), String> {
+        let value = locals.slots.borrow().get(index).cloned();
+        match value {
+            Some(Some(value)) => self.push(value),
+            Some(None) => Err(format!(
+                "identifier not found: {}",
+                locals.function.locals[index]
+            )),
+            None => Err("free variable out of scope".to_string()),
+        }
+    }
+
+    fn push(&mut self, value: Object) -> Result<(), String> {
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// push the result of an operation, turning error objects into runtime errors
+    fn push_result(&mut self, result: Object) -> Result<(), String> {
+        match result {
+            Object::Error(message) => Err(message),
+            result => self.push(result),
+        }
+    }
+
+    /// pop the `count` topmost values, in the order they were pushed
+    fn pop_many(&mut self, count: usize) -> Result<Vec<Object>, String> {
+        let start = self
+            .stack
+            .len()
+            .checked_sub(count)
+            .ok_or("stack underflow")?;
+        Ok(self.stack.split_off(start))
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().unwrap_or(Object::Null)
+    }
+
+    fn read_u8(&mut self) -> usize {
+        let frame = self.current_frame_mut();
+        let value = frame.locals.function.instructions[frame.ip] as usize;
+        frame.ip += 1;
+        value
+    }
+
+    fn read_u16(&mut self) -> usize {
+        let frame = self.current_frame_mut();
+        let value = read_u16(&frame.locals.function.instructions, frame.ip) as usize;
+        frame.ip += 2;
+        value
+    }
+
+    fn current_frame(&self) -> &Frame {
+        self.frames.last().unwrap()
+    }
+
+    fn current_frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+}
+
+/// pair up the names and the values pushed one after the other
+fn named_values(elements: Vec<Object>) -> Vec<(String, Object)> {
+    let mut pairs = Vec::with_capacity(elements.len() / 2);
+    let mut elements = elements.into_iter();
+    while let (Some(name), Some(value)) = (elements.next(), elements.next()) {
+        pairs.push((name.inspect(), value));
+    }
+    pairs
+}
+
+fn infix_operator(op: Opcode) -> &'static str {
+    match op {
+        OP_ADD => "+",
+        OP_SUB => "-",
+        OP_MUL => "*",
+        OP_DIV => "/",
+        OP_MOD => "%",
+        OP_POW => "**",
+        OP_BIT_AND => "&",
+        OP_BIT_OR => "|",
+        OP_BIT_XOR => "^",
+        OP_SHIFT_LEFT => "<<",
+        OP_SHIFT_RIGHT => ">>",
+        OP_EQUAL => "==",
+        OP_NOT_EQUAL => "!=",
+        OP_GREATER_THAN => ">",
+        _ => "<",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::Compiler;
+    use crate::environment::Environment;
+    use crate::evaluator::eval_program;
+    use crate::gc;
+    use crate::lexer::Lexer;
+    use crate::object::Object;
+    use crate::parser::Parser;
+    use crate::vm::Vm;
+
+    /// programs whose results must be the same in the evaluator and in the virtual machine
+    const PROGRAMS: &[(&str, &str)] = &[
+        ("", "null"),
+        ("5", "5"),
+        ("-10", "-10"),
+        ("5 + 5 + 5 + 5 - 10", "10"),
+        ("(5 + 10 * 2 + 15 / 3) * 2 + -10", "50"),
+        ("1 < 2", "true"),
+        ("1 > 2", "false"),
+        ("(1 < 2) == true", "true"),
+        ("!!5", "true"),
+        ("\"a\" == \"a\"", "true"),
+        ("\"Hello\" + \" \" + \"World!\"", "Hello World!"),
+        ("1 == true", "false"),
+        ("let n = if (false) { 1 }; n == n", "true"),
+        ("if (1 > 2) { 10 } else { 20 }", "20"),
+        ("if (false) { 10 }", "null"),
+        ("if (true) { }", "null"),
+        ("if (true) { let a = 1; }", "null"),
+        ("if (true) { 1; 2 }", "2"),
+        ("if (if (false) { 10 }) { 10 } else { 20 }", "20"),
+        ("return 10; 9;", "10"),
+        ("if (10 > 1) { if (10 > 1) { return 10; } return 1; }", "10"),
+        ("let a = 5; let b = a * 2; b + a", "15"),
+        ("let a = 5;", "null"),
+        ("let a = 1; let a = a + 1; a", "2"),
+        ("let add = fn(a, b) { a + b }; add(1, add(2, 3))", "6"),
+        ("let f = fn() { return 1; 2 }; f()", "1"),
+        ("let f = fn() { }; f()", "null"),
+        ("let f = fn() { let a = 1; }; f()", "null"),
+        ("let f = fn(x) { if (x > 0) { return x; }; -x }; f(-3) + f(2)", "5"),
+        // a `return` inside of an operand returns from the function right away
+        ("fn() { len(if (true) { return 1; }) }()", "1"),
+        ("fn() { -if (true) { return 1; } }()", "1"),
+        ("fn() { [if (true) { return 1; }, 2] }()", "1"),
+        ("[if (true) { return 1; }, 2]", "1"),
+        ("-if (true) { return 1; }", "1"),
+        ("fn() { 1 + if (true) { return 2; } * 3 }()", "2"),
+        ("fn() { let x = if (true) { return 1; }; 2 }()", "1"),
+        ("fn(x = if (true) { return 1; }) { 2 }()", "1"),
+        ("fn() { {\"a\": 1}[if (true) { return 2; }] }()", "2"),
+        // the keys of a hash literal are checked once all of it is evaluated
+        ("fn() { {[]: if (true) { return 1; }} }()", "1"),
+        ("{[]: 1, \"a\": 2}", "ERROR: unusable as hash key: ARRAY"),
+        // deep calls with operands on the stack reach the same depth on both back ends
+        (
+            "let f = fn(n) { if (n == 0) { 0 } else { [1, 2, 3, f(n - 1)][0] } }; f(900)",
+            "1",
+        ),
+        (
+            "let f = fn() { 1 + (1 + (1 + f())) }; f()",
+            "ERROR: maximum call depth of 1000 exceeded",
+        ),
+        (
+            "let adder = fn(x) { fn(y) { x + y } }; let add_two = adder(2); add_two(3)",
+            "5",
+        ),
+        (
+            "let f = fn(a) { fn(b) { fn(c) { a + b + c } } }; f(1)(2)(3)",
+            "6",
+        ),
+        (
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+            "610",
+        ),
+        (
+            "let f = fn() { let count = fn(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } }; count(5) }; f()",
+            "5",
+        ),
+        ("let f = fn() { g() }; let g = fn() { 7 }; f()", "7"),
+        ("let len = fn(x) { 42 }; len(\"abc\")", "42"),
+        ("len(\"four\") + len([1, 2])", "6"),
+        ("first(rest(push([1, 2], 3)))", "2"),
+        ("[1, 2 * 2, 3 + 3][1]", "4"),
+        ("[1, 2, 3][3]", "null"),
+        ("[1, 2, 3][-1]", "null"),
+        ("{\"one\": 1, 2: 2, true: 3}", "{one: 1, 2: 2, true: 3}"),
+        ("{\"one\": 1}[\"o\" + \"ne\"]", "1"),
+        ("{1: 1}[2]", "null"),
+        ("5 + true; 5;", "ERROR: type mismatch: INTEGER + BOOLEAN"),
+        ("-true", "ERROR: unknown operator: -BOOLEAN"),
+        ("true + false", "ERROR: unknown operator: BOOLEAN + BOOLEAN"),
+        ("\"a\" - \"b\"", "ERROR: unknown operator: STRING - STRING"),
+        ("if (10 > 1) { true + false; }", "ERROR: unknown operator: BOOLEAN + BOOLEAN"),
+        ("foobar", "ERROR: identifier not found: foobar"),
+        ("let f = fn() { x }; f()", "ERROR: identifier not found: x"),
+        ("if (false) { let a = 1; }; a", "ERROR: identifier not found: a"),
+        ("1 / 0", "ERROR: division by zero"),
+        ("9223372036854775807 + 1", "ERROR: integer overflow"),
+        ("[10 % 4, -10 % 4, 2 ** 10, 3 ** 2 ** 2, (3 ** 2) ** 2, 2 * 2 ** 3 % 5]", "[2, -2, 1024, 81, 81, 1]"),
+        ("1 % 0", "ERROR: division by zero"),
+        ("[-2 ** 2, (-2) ** 2, -2 ** 3, 2 ** -(1 - 2)]", "[-4, 4, -8, 2]"),
+        ("2 ** -1", "ERROR: negative exponent"),
+        ("2 ** 64", "ERROR: integer overflow"),
+        ("[1 ** 10000000000, (-1) ** 10000000001, 0 ** 0]", "[1, -1, 1]"),
+        ("2 ** 10000000000", "ERROR: integer overflow"),
+        // bitwise and shift operators
+        ("let flags = 0; let flags = flags | 1 << 3 | 1; [flags, flags & 8, flags ^ 9, ~flags, flags >> 3, 1 << 63]", "[9, 8, 0, -10, 1, -9223372036854775808]"),
+        ("let f = |x, mask = 255| x & mask; [300 |> f, 300 |> f(15)]", "[44, 12]"),
+        ("1 << 64", "ERROR: shift amount too large"),
+        ("1 >> -2", "ERROR: negative shift amount"),
+        ("~\"a\"", "ERROR: unknown operator: ~STRING"),
+        ("1 & \"a\"", "ERROR: type mismatch: INTEGER & STRING"),
+        // structs
+        (
+            "struct Point { x, y }; let p = Point { y: 2, x: 1 }; [p.x, p.y, p]",
+            "[1, 2, Point {x: 1, y: 2}]",
+        ),
+        (
+            "struct Point { x, y }; impl Point { fn len(self) { self.x * self.x + self.y * self.y } fn add(self, other) { Point { x: self.x + other.x, y: self.y + other.y } } }; let p = Point { x: 1, y: 2 }; [p.len(), p.add(p).add(Point { x: 0, y: 1 }).len(), Point.len(p)]",
+            "[5, 29, 5]",
+        ),
+        // the methods of an `impl` are seen by the instances created before it
+        (
+            "struct Counter { n }; let c = Counter { n: 1 }; impl Counter { fn next(self, by = 1) { Counter { n: self.n + by } } }; let f = c.next; [f().n, c.next(by: 5).n, f]",
+            "[2, 6, method Counter.next]",
+        ),
+        (
+            "struct Node { value, next }; let list = Node { value: 1, next: Node { value: 2, next: 0 } }; match list.next { n => n.value }",
+            "2",
+        ),
+        ("struct Empty {}; [Empty {}, Empty]", "[Empty {}, struct Empty {  }]"),
+        ("struct Point { x, y }; Point { x: 1, z: 2 }", "ERROR: struct Point has no field z"),
+        ("struct Point { x, y }; Point { x: 1 }", "ERROR: missing field y of struct Point"),
+        ("struct Point { x, y }; Point { x: 1, y: 2 }.z", "ERROR: struct Point has no field z"),
+        ("struct Point { x, y }; Point { x: 1, y: 2 }.len()", "ERROR: struct Point has no field len"),
+        ("struct Point { x, y }; Point.len", "ERROR: struct Point has no method len"),
+        ("let p = 1; p.x", "ERROR: cannot access field x of INTEGER"),
+        ("let p = 1; p { x: 1 }", "ERROR: not a struct: INTEGER"),
+        ("let p = 1; impl p { fn f(self) { self } }", "ERROR: not a struct: INTEGER"),
+        (
+            "struct Point { x, y }; impl Point { fn len(self) { self.z } }; Point { x: 1, y: 2 }.len()",
+            "ERROR: struct Point has no field z",
+        ),
+        ("1(2)", "ERROR: not a function: INTEGER"),
+        ("fn(x) { x }()", "ERROR: wrong number of arguments: want=1, got=0, function defined at line 1, column 1"),
+        ("fn(x) { x } + 1", "ERROR: type mismatch: FUNCTION + INTEGER"),
+        ("len(1)", "ERROR: argument to `len` not supported, got INTEGER"),
+        ("len(1, 2)", "ERROR: wrong number of arguments. got=2, want=1"),
+        ("1[0]", "ERROR: index operator not supported: INTEGER"),
+        ("{fn(x) { x }: 1}", "ERROR: unusable as hash key: FUNCTION"),
+        ("{1: 2}[[]]", "ERROR: unusable as hash key: ARRAY"),
+        ("let i = 0; while (i < 5) { let i = i + 1; }; i", "5"),
+        ("while (false) { 1 }", "null"),
+        ("let i = 0; while (true) { let i = i + 1; if (i == 3) { break; } }; i", "3"),
+        (
+            "let i = 0; let odd = 0; while (i < 6) { let i = i + 1; if (i / 2 * 2 == i) { continue; } let odd = odd + i; }; odd",
+            "9",
+        ),
+        ("let sum = 0; for (x in [1, 2, 3]) { let sum = sum + x; }; sum", "6"),
+        ("let s = \"\"; for (c in \"abc\") { let s = c + s; }; s", "cba"),
+        ("let s = \"\"; for (k in {\"a\": 1, \"b\": 2}) { let s = s + k; }; s", "ab"),
+        ("let sum = 0; for (i in range(1, 5)) { let sum = sum + i; }; sum", "10"),
+        ("let n = 0; for (i in range(-2)) { let n = n + 1; }; n", "0"),
+        (
+            "let pairs = []; for (i in range(3)) { for (j in range(3)) { if (j > i) { break; } let pairs = push(pairs, [i, j]); } }; pairs",
+            "[[0, 0], [1, 0], [1, 1], [2, 0], [2, 1], [2, 2]]",
+        ),
+        (
+            "let find = fn(xs, y) { for (x in xs) { if (x == y) { return true; } }; false }; [find([1, 2], 2), find([], 1)]",
+            "[true, false]",
+        ),
+        ("let f = fn() { for (x in [1]) { } }; f()", "null"),
+        ("for (x in 5) { }", "ERROR: not iterable: INTEGER"),
+        ("range(1, true)", "ERROR: arguments to `range` must be INTEGER, got INTEGER, BOOLEAN"),
+        // the integers of a range are not stored
+        ("len(range(100000000000000))", "100000000000000"),
+        (
+            "let n = 0; for (i in range(100000000000000)) { if (i == 4) { break; } n += i; }; n",
+            "6",
+        ),
+        // a `break` or `continue` inside of an operand stops the expression it is in
+        (
+            "let n = 0; let i = 0; while (i < 4) { i += 1; n += len(if (i == 2) { continue; } else { [i] }); }; n",
+            "3",
+        ),
+        (
+            "let n = 0; for (i in [1, 2, 3]) { n += -if (i == 2) { break; } else { i }; }; n",
+            "-1",
+        ),
+        (
+            "let a = []; for (i in range(3)) { a = push(a, [i, if (i == 1) { continue; } else { i }]); }; a",
+            "[[0, 0], [2, 2]]",
+        ),
+        (
+            "let n = 0; for (i in range(3)) { n += {\"a\": i}[if (i == 0) { continue; } else { \"a\" }]; }; n",
+            "3",
+        ),
+        (
+            "[range(2, 5), range(5, 2), range(3)[1], range(3)[3], range(3)[-1], first(range(2, 4)), last(range(2, 4)), first(range(0))]",
+            "[range(2, 5), range(5, 5), 1, null, null, 2, 3, null]",
+        ),
+        ("len(range(-9223372036854775807 - 1, 1))", "ERROR: integer overflow"),
+        ("while (1 + true) { }", "ERROR: type mismatch: INTEGER + BOOLEAN"),
+        ("const a = 2; let f = fn(x) { x * a }; f(3)", "6"),
+        ("let x = 1; x = x + 1; x", "2"),
+        ("let x = 10; x += 5; x -= 3; x *= 2; x /= 4; x", "6"),
+        ("let s = \"a\"; s += \"b\"; s", "ab"),
+        ("let sum = 0; for (x in range(5)) { sum += x; }; sum", "10"),
+        ("let i = 0; while (i < 3) { i += 1; }; i", "3"),
+        (
+            "let counter = fn() { let n = 0; fn() { n += 1; n } }; let next = counter(); next(); next(); next()",
+            "3",
+        ),
+        ("let total = 0; let add = fn(x) { total = total + x; }; add(2); add(3); total", "5"),
+        ("let a = [1, 2, 3]; a[0] = 10; a[2] *= 5; a", "[10, 2, 15]"),
+        ("let a = [1, 2]; let b = a; a[0] = 5; [a, b]", "[[5, 2], [1, 2]]"),
+        (
+            "let h = {\"a\": 1}; h[\"b\"] = 2; h[\"a\"] += 10; h",
+            "{a: 11, b: 2}",
+        ),
+        ("let m = [[1, 2], [3, 4]]; m[1][0] = 30; m[0][1] += 20; m", "[[1, 22], [30, 4]]"),
+        (
+            "let f = fn() { let xs = [0, 0]; let set = fn(i) { xs[i] = i + 1; }; set(0); set(1); xs }; f()",
+            "[1, 2]",
+        ),
+        ("x = 1", "ERROR: identifier not found: x"),
+        ("x += 1", "ERROR: identifier not found: x"),
+        ("let f = fn() { y = 1 }; f()", "ERROR: identifier not found: y"),
+        ("let a = [1]; a[1] = 2", "ERROR: index out of range: 1"),
+        ("let a = [1]; a[-1] = 2", "ERROR: index out of range: -1"),
+        ("let n = 1; n[0] = 2", "ERROR: index assignment not supported: INTEGER"),
+        ("let h = {}; h[[]] = 1", "ERROR: unusable as hash key: ARRAY"),
+        ("let h = {}; h[\"a\"] += 1", "ERROR: type mismatch: NULL + INTEGER"),
+        ("let x = 1; x /= 0", "ERROR: division by zero"),
+        ("let x = true; x -= 1", "ERROR: type mismatch: BOOLEAN - INTEGER"),
+        (
+            "let describe = fn(v) {
+  match (v) {
+    0 => \"zero\",
+    -1 => \"minus one\",
+    true => \"yes\",
+    \"hi\" => \"greeting\",
+    [] => \"empty\",
+    [x] => \"one: \" + x,
+    [first, ..rest] => [first, rest],
+    {\"k\": {\"n\": n}} => \"nested \" + n,
+    n if n > 10 => \"big\",
+    _ => \"other\"
+  }
+};
+[describe(0), describe(-1), describe(true), describe(\"hi\"), describe([]), describe([\"a\"]),
+ describe([\"a\", \"b\", \"c\"]), describe({\"k\": {\"n\": \"v\"}, \"x\": 1}), describe(11), describe(5)]",
+            "[zero, minus one, yes, greeting, empty, one: a, [a, [b, c]], nested v, big, other]",
+        ),
+        // a literal only matches values of its own type
+        ("match (1) { true => 1, \"1\" => 2, 1 => 3 }", "3"),
+        // the bindings of the arm taken stay in the scope of the match,
+        // an arm which doesn't match or whose guard fails leaves nothing behind
+        (
+            "let f = fn(v) { let a = 9; let r = match (v) { [a, 2] => a, _ => 0 }; [r, a] }; [f([1, 3]), f([1, 2])]",
+            "[[0, 9], [1, 1]]",
+        ),
+        ("let x = 5; match ([7]) { [x] if x > 100 => 1, _ => 2 }; x", "5"),
+        ("let f = fn() { match ([7]) { [x] if x > 1 => 0 }; x }; f()", "7"),
+        (
+            "fn(x) { match ([7, 8]) { [x, 9] => 1, [y, x] if x < 0 => 2, _ => x } }(5)",
+            "5",
+        ),
+        ("let x = 1; match (2) { x if fn() { x }() == 2 => x }", "2"),
+        ("let y = 3; match ([1]) { [y] if y > 5 => 0, _ => y }", "3"),
+        ("match ([1, 2]) { [a, a] if a == 2 => a }", "2"),
+        (
+            "match ([1, [2, 3]]) { [a, [b, ..c]] if a > 5 => 0, [a, [b, ..c]] => { let s = a + b; s + len(c) } }",
+            "4",
+        ),
+        (
+            "let m = fn(x) { match (x) { [a] => match (a) { 1 => \"one\", _ => \"not one\" }, _ => \"none\" } }; [m([1]), m([2]), m(3)]",
+            "[one, not one, none]",
+        ),
+        ("match (3) { 1 => 1, 2 => 2 }", "ERROR: no match arm for value: 3"),
+        ("match ([1]) { [a] if a > 1 => a }", "ERROR: no match arm for value: [1]"),
+        ("match (1) { _ if missing => 1 }", "ERROR: identifier not found: missing"),
+        // destructuring
+        (
+            "let [a, b, ..rest] = [1, 2, 3, 4]; let {name, \"age\": [age]} = {\"name\": \"ann\", \"age\": [3]}; [a, b, rest, name, age]",
+            "[1, 2, [3, 4], ann, 3]",
+        ),
+        (
+            "let f = fn([x, y], {k}) { let [_, z] = [x, y]; fn() { x + z + k } }; f([1, 2], {\"k\": 3})()",
+            "6",
+        ),
+        ("let [a, [b, ..c]] = [1, [2]]; [a, b, c]", "[1, 2, []]"),
+        (
+            "let [a, b] = [1];",
+            "ERROR: pattern [a, b] does not match [1]",
+        ),
+        ("let {k} = [1]; k", "ERROR: pattern {k} does not match [1]"),
+        (
+            "let f = fn(a, [b, 2]) { b }; f(1, [2, 3])",
+            "ERROR: pattern [b, 2] does not match [2, 3]",
+        ),
+        // default, rest and named parameters
+        (
+            "let f = fn(x, y = 10, ...rest) { [x, y, rest] }; [f(1), f(1, 2), f(1, 2, 3, 4), f(y: 5, x: 6)]",
+            "[[1, 10, []], [1, 2, []], [1, 2, [3, 4]], [6, 5, []]]",
+        ),
+        // a default value is evaluated at each call, and can use the parameters before it
+        (
+            "let f = fn(a, b = a * 2, [c, d] = [a, b]) { a + b + c + d }; [f(1), f(1, 1), f(b: 3, a: 1), f(1, 2, [0, 0])]",
+            "[6, 4, 8, 3]",
+        ),
+        (
+            "let f = fn(x) { let g = fn(y = x + 1) { y }; g() }; f(1)",
+            "2",
+        ),
+        (
+            "let h = fn(x, y) { x }; h(1)",
+            "ERROR: wrong number of arguments: want=2, got=1, function defined at line 1, column 9",
+        ),
+        (
+            "let h = fn(x, y = 1) { x };\nh(1, 2, 3)",
+            "ERROR: wrong number of arguments: want=1 to 2, got=3, function defined at line 1, column 9",
+        ),
+        (
+            "fn(x, ...rest) { x }()",
+            "ERROR: wrong number of arguments: want=at least 1, got=0, function defined at line 1, column 1",
+        ),
+        ("fn(x, y = 1) { x }(y: 2)", "ERROR: missing argument for parameter x"),
+        ("fn(x) { x }(1, x: 2)", "ERROR: parameter x got more than one argument"),
+        ("fn(x) { x }(1, z: 2)", "ERROR: unknown named argument z"),
+        (
+            "let f = fn(x, y) { x }; f(z: 1)",
+            "ERROR: unknown named argument z",
+        ),
+        // the names bound by a destructured parameter can't be given by name
+        ("fn([c, d]) { c }(d: 1)", "ERROR: unknown named argument d"),
+        ("len(x: 1)", "ERROR: named arguments are only supported by Lingo functions"),
+        // pipelines and lambdas
+        (
+            "let map = fn(xs, f) { let out = []; for (x in xs) { out = push(out, f(x)) }; out }; [1, 2, 3] |> map(|x| x * 2) |> map(|x| x + 1)",
+            "[3, 5, 7]",
+        ),
+        (
+            "let add = |x, y = 1| x + y; [1 |> add, 1 |> add(10), 2 |> add(y: 3)]",
+            "[2, 11, 5]",
+        ),
+        ("let twice = |f| |x| f(f(x)); 3 |> twice(|x| x * x)()", "81"),
+        ("\"abc\" |> |s| { let n = len(s); n * n }", "9"),
+        ("1 |> |x, y| x", "ERROR: wrong number of arguments: want=2, got=1, function defined at line 1, column 6"),
+    ];
+
+    fn parse(input: &str) -> crate::ast::Program {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program().unwrap();
+        assert!(
+            parser.errors().is_empty(),
+            "{}: {:?}",
+            input,
+            parser.errors()
+        );
+        program
+    }
+
+    fn run_vm(input: &str) -> Object {
+        let bytecode = Compiler::new().compile(&parse(input)).unwrap();
+        Vm::new().run(&bytecode).unwrap_or_else(Object::Error)
+    }
+
+    #[test]
+    fn test_vm_matches_evaluator() {
+        for (input, expected) in PROGRAMS {
+            let evaluated = eval_program(&parse(input), &Environment::new());
+            assert_eq!(
+                &evaluated.inspect(),
+                expected,
+                "evaluator, input: {}",
+                input
+            );
+            assert_eq!(&run_vm(input).inspect(), expected, "vm, input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_collections_keep_semantics() {
+        // collect garbage on every call of both back ends
+        gc::set_threshold(0);
+        let before = gc::stats().collections;
+        test_vm_matches_evaluator();
+        assert!(gc::stats().collections > before);
+        gc::set_threshold(gc::DEFAULT_THRESHOLD);
+    }
+
+    #[test]
+    fn test_globals_persist_between_runs() {
+        let mut compiler = Compiler::new();
+        let mut vm = Vm::new();
+        let first = compiler
+            .compile(&parse("let a = 1; let f = fn() { a }"))
+            .unwrap();
+        vm.run(&first).unwrap();
+        let second = compiler.compile(&parse("let a = a + 1; f()")).unwrap();
+        assert_eq!(vm.run(&second).unwrap().inspect(), "2");
+    }
+}